@@ -0,0 +1,65 @@
+//! INSTREAM throughput against a local daemon stand-in that reads and
+//! discards the frames, so the client's framing and writes dominate.
+//!
+//! `cargo bench --bench instream`
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Instant;
+
+use clamav::ClamClient;
+
+const STREAM_SIZE: usize = 256 << 20;
+const ROUNDS: usize = 5;
+
+fn main() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || drain(stream));
+        }
+    });
+
+    let client = ClamClient::new("127.0.0.1", port).unwrap();
+    let data = vec![0x5a; STREAM_SIZE];
+
+    let mut best = f64::MAX;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        client.scan_stream(data.as_slice()).unwrap();
+        best = best.min(started.elapsed().as_secs_f64());
+    }
+
+    println!(
+        "scan_stream of {} MiB: best of {} in {:.3}s, {:.0} MiB/s",
+        STREAM_SIZE >> 20,
+        ROUNDS,
+        best,
+        (STREAM_SIZE >> 20) as f64 / best
+    );
+}
+
+/// Reads one INSTREAM upload and answers it as clean.
+fn drain(mut stream: TcpStream) -> io::Result<()> {
+    let mut command = [0; 10];
+    stream.read_exact(&mut command)?;
+
+    let mut payload = vec![0; 1 << 20];
+    loop {
+        let mut length = [0; 4];
+        stream.read_exact(&mut length)?;
+        let mut remaining = u32::from_be_bytes(length) as usize;
+        if remaining == 0 {
+            break;
+        }
+        while remaining > 0 {
+            let n = remaining.min(payload.len());
+            stream.read_exact(&mut payload[..n])?;
+            remaining -= n;
+        }
+    }
+
+    stream.write_all(b"stream: OK\0")
+}
@@ -0,0 +1,5 @@
+fn main() {
+    // the service of the clamav-grpc binary is generated from its contract
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/clamav.proto").unwrap();
+}
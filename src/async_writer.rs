@@ -0,0 +1,339 @@
+use std::future::{poll_fn, Future};
+use std::io::{self, ErrorKind, Read};
+use std::panic;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_sink::Sink;
+use tokio::io::{AsyncReadExt, AsyncWrite, DuplexStream};
+use tokio::runtime::Handle;
+use tokio::task::{self, JoinError, JoinHandle};
+
+use crate::client::{ClamClient, Result};
+use crate::error::ClamError;
+use crate::response::ScanResult;
+
+const PIPE_SIZE: usize = 4096;
+
+/// The `AsyncWrite` counterpart of `ClamScanWriter`, so `tokio::io::copy`
+/// can stream any `AsyncRead` to clamd. Writes go through a pipe to a task
+/// on tokio's blocking thread pool, which uploads them like
+/// `ClamClient::scan_stream`, so the scan is audited and observed as a
+/// stream; call `finish` once all data has been written to end the stream
+/// and collect the verdict. `shutdown` ends the stream as well and waits for
+/// the scan, failing if it did; the verdict is then left for `into_result`.
+///
+/// Writes fail once clamd has stopped reading, e.g. over StreamMaxLength, and
+/// `finish` then reports why. Dropping the writer without finishing abandons
+/// the scan.
+pub struct AsyncScanWriter {
+    writer: Option<DuplexStream>,
+    ended: Arc<AtomicBool>,
+    scan: JoinHandle<Result<ScanResult>>,
+    result: Option<Result<ScanResult>>,
+}
+
+impl AsyncScanWriter {
+    /// Starts the scan. Must be called from within a tokio runtime.
+    pub fn new(client: Arc<ClamClient>) -> Self {
+        let (writer, reader) = tokio::io::duplex(PIPE_SIZE);
+        let ended = Arc::new(AtomicBool::new(false));
+        let pipe = Pipe {
+            reader,
+            ended: Arc::clone(&ended),
+            runtime: Handle::current(),
+        };
+        // scan_stream stops reading once clamd has answered early; dropping
+        // the pipe then makes further writes fail rather than block
+        let scan = task::spawn_blocking(move || client.scan_stream(pipe));
+
+        Self {
+            writer: Some(writer),
+            ended,
+            scan,
+            result: None,
+        }
+    }
+
+    /// Ends the stream and waits for the verdict.
+    pub async fn finish(mut self) -> Result<ScanResult> {
+        poll_fn(|cx| self.poll_scan(cx)).await;
+        self.result.take().expect("scan result taken")
+    }
+
+    /// The verdict of a writer that has been shut down, or `None` if it has
+    /// not been yet.
+    pub fn into_result(mut self) -> Option<Result<ScanResult>> {
+        self.result.take()
+    }
+
+    /// Turns the writer into a `Sink` of byte chunks.
+    pub fn into_sink(self) -> ScanSink {
+        ScanSink {
+            writer: self,
+            pending: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Ends the stream and polls the scan until its result is stored.
+    fn poll_scan(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.result.is_none() {
+            // hanging up after marking the stream as complete ends it
+            self.ended.store(true, Ordering::SeqCst);
+            self.writer = None;
+            self.result = Some(match Pin::new(&mut self.scan).poll(cx) {
+                Poll::Ready(Ok(result)) => result,
+                Poll::Ready(Err(e)) => Err(join_error(e)),
+                Poll::Pending => return Poll::Pending,
+            });
+        }
+
+        Poll::Ready(())
+    }
+}
+
+impl AsyncWrite for AsyncScanWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.writer.as_mut() {
+            Some(writer) => Pin::new(writer).poll_write(cx, buf),
+            None => Poll::Ready(Err(ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.writer.as_mut() {
+            Some(writer) => Pin::new(writer).poll_flush(cx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.poll_scan(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        match &self.result {
+            Some(Err(e)) => Poll::Ready(Err(io::Error::other(e.to_string()))),
+            _ => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+/// A `Sink` of byte chunks, e.g. `Bytes` from an HTTP body or websocket
+/// messages, streamed to clamd like the writes of `AsyncScanWriter`. It is
+/// ready for the next chunk once the previous one is written, so a daemon
+/// that reads slowly holds back the producer. Closing the sink flushes it;
+/// `finish` ends the stream and returns the verdict.
+pub struct ScanSink {
+    writer: AsyncScanWriter,
+    // the chunk being written, and how much of it has been
+    pending: Vec<u8>,
+    written: usize,
+}
+
+impl ScanSink {
+    pub async fn finish(self) -> Result<ScanResult> {
+        self.writer.finish().await
+    }
+
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.pending.len() {
+            let chunk = &self.pending[self.written..];
+            match Pin::new(&mut self.writer).poll_write(cx, chunk) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(n)) => self.written += n,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        self.pending.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<B: AsRef<[u8]>> Sink<B> for ScanSink {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_write_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: B) -> io::Result<()> {
+        self.get_mut().pending.extend_from_slice(item.as_ref());
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_write_pending(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.writer).poll_flush(cx),
+            other => other,
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Sink::<B>::poll_flush(self, cx)
+    }
+}
+
+/// The reading end of the pipe, read by the blocking task.
+struct Pipe {
+    reader: DuplexStream,
+    ended: Arc<AtomicBool>,
+    runtime: Handle,
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.runtime.block_on(self.reader.read(buf))?;
+        if n == 0 && !buf.is_empty() && !self.ended.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "scan writer dropped before finish",
+            ));
+        }
+
+        Ok(n)
+    }
+}
+
+fn join_error(e: JoinError) -> ClamError {
+    if e.is_panic() {
+        panic::resume_unwind(e.into_panic());
+    }
+    ClamError::Cancelled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDaemon;
+    use tokio::io::AsyncWriteExt;
+
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn test_async_writer_frames_copied_data() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let client = Arc::new(ClamClient::new("127.0.0.1", daemon.port).unwrap());
+        let data = vec![7u8; 10000];
+
+        let result = block_on(async {
+            let mut writer = AsyncScanWriter::new(client);
+            tokio::io::copy(&mut data.as_slice(), &mut writer)
+                .await
+                .unwrap();
+            writer.finish().await
+        });
+
+        assert_eq!(result.unwrap(), ScanResult::Ok);
+        let received = daemon.received();
+        assert_eq!(received.command, b"zINSTREAM".to_vec());
+        assert_eq!(received.payload(), data);
+    }
+
+    #[test]
+    fn test_scan_sink() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let client = Arc::new(ClamClient::new("127.0.0.1", daemon.port).unwrap());
+
+        let result = block_on(async {
+            let mut sink = AsyncScanWriter::new(client).into_sink();
+            for chunk in [&b"hello "[..], b"world"].iter() {
+                poll_fn(|cx| Sink::<&[u8]>::poll_ready(Pin::new(&mut sink), cx))
+                    .await
+                    .unwrap();
+                Pin::new(&mut sink).start_send(*chunk).unwrap();
+            }
+            poll_fn(|cx| Sink::<&[u8]>::poll_close(Pin::new(&mut sink), cx))
+                .await
+                .unwrap();
+            sink.finish().await
+        });
+
+        assert_eq!(result.unwrap(), ScanResult::Ok);
+        assert_eq!(daemon.received().payload(), b"hello world");
+    }
+
+    #[test]
+    fn test_async_writer_audited() {
+        let daemon = MockDaemon::start(b"stream: Eicar FOUND\0");
+        let path = std::env::temp_dir().join(format!(
+            "clamav-client-audit-writer-test-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let log = Arc::new(crate::AuditLog::open(&path).unwrap());
+        let client = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_audit_log(log);
+
+        let result = block_on(async {
+            let mut writer = AsyncScanWriter::new(Arc::new(client));
+            tokio::io::copy(&mut &b"data"[..], &mut writer)
+                .await
+                .unwrap();
+            writer.finish().await
+        });
+        assert!(matches!(result.unwrap(), ScanResult::Found(..)));
+        daemon.received();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(written.contains(r#""input":"stream","verdict":"found","signature":"Eicar""#));
+    }
+
+    #[test]
+    fn test_shutdown_resolves_verdict() {
+        let daemon = MockDaemon::start(b"stream: Eicar FOUND\0");
+        let client = Arc::new(ClamClient::new("127.0.0.1", daemon.port).unwrap());
+
+        let writer = block_on(async {
+            let mut writer = AsyncScanWriter::new(client);
+            tokio::io::copy(&mut &b"data"[..], &mut writer)
+                .await
+                .unwrap();
+            writer.shutdown().await.unwrap();
+            assert!(writer.write_all(b"more").await.is_err());
+            writer
+        });
+
+        assert!(matches!(
+            writer.into_result(),
+            Some(Ok(ScanResult::Found(..)))
+        ));
+        assert_eq!(daemon.received().payload(), b"data");
+    }
+
+    #[test]
+    fn test_shutdown_reports_scan_error() {
+        let client = Arc::new(ClamClient::new("127.0.0.1", 1).unwrap());
+
+        let writer = block_on(async {
+            let mut writer = AsyncScanWriter::new(client);
+            let _ = writer.write_all(b"data").await;
+            assert!(writer.shutdown().await.is_err());
+            writer
+        });
+
+        assert!(matches!(
+            writer.into_result(),
+            Some(Err(ClamError::ConnectionError(_)))
+        ));
+    }
+}
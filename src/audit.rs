@@ -0,0 +1,150 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client::Result;
+use crate::error::ClamError;
+use crate::json;
+use crate::response::ScanResult;
+
+/// Appends one JSON line per scan to a file or any other writer, as evidence
+/// of what was scanned and with which result:
+///
+/// ```text
+/// {"timestamp":1700000000,"input":"/srv/a.pdf","verdict":"found","signature":"Eicar-Test-Signature","reply":"stream: Eicar-Test-Signature FOUND","error":null,"duration_ms":12.5,"db_version":"ClamAV 1.2.0/27000/Mon Oct 16 08:00:00 2023"}
+/// ```
+///
+/// `verdict` is `ok`, `found` or `error`; `reply` is the daemon's reply line
+/// for detections, exactly as it was received. Attach the log to a client with
+/// `ClamClient::with_audit_log` to record its streamed scans.
+pub struct AuditLog {
+    out: Mutex<Box<dyn Write + Send>>,
+    // the daemon's VERSION reply, recorded with every scan
+    db_version: Mutex<Option<String>>,
+}
+
+impl AuditLog {
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        Self {
+            out: Mutex::new(Box::new(out)),
+            db_version: Mutex::new(None),
+        }
+    }
+
+    /// Appends to the file at `path`, creating it if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => Ok(Self::new(file)),
+            Err(e) => Err(ClamError::AuditError(e)),
+        }
+    }
+
+    /// Sets the database version recorded from now on, e.g. the result of
+    /// `ClamClient::version` after a reload.
+    pub fn set_db_version(&self, db_version: Option<String>) {
+        *self.db_version.lock().unwrap_or_else(|e| e.into_inner()) = db_version;
+    }
+
+    /// Writes the line for one scan of `input` and flushes it.
+    pub fn record(
+        &self,
+        input: &str,
+        result: std::result::Result<&ScanResult, &ClamError>,
+        duration: Duration,
+    ) -> Result<()> {
+        let (verdict, signature, reply, error) = match result {
+            Ok(ScanResult::Ok) => ("ok", None, None, None),
+            Ok(ScanResult::Found(_, signature, line)) => (
+                "found",
+                Some(signature.raw.as_str()),
+                Some(line.as_str()),
+                None,
+            ),
+            Ok(ScanResult::Failed(_, message, line)) => {
+                ("error", None, Some(line.as_str()), Some(message.clone()))
+            }
+            Ok(ScanResult::Error(message)) => ("error", None, None, Some(message.clone())),
+            Err(e) => ("error", None, None, Some(e.to_string())),
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let db_version = self
+            .db_version
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+
+        let line = format!(
+            "{{\"timestamp\":{},\"input\":{},\"verdict\":\"{}\",\"signature\":{},\"reply\":{},\"error\":{},\"duration_ms\":{:.1},\"db_version\":{}}}\n",
+            timestamp,
+            json::string(input),
+            verdict,
+            json::optional(signature),
+            json::optional(reply),
+            json::optional(error.as_deref()),
+            duration.as_secs_f64() * 1000.0,
+            json::optional(db_version.as_deref()),
+        );
+
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        match write_line(&mut **out, &line) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(ClamError::AuditError(e)),
+        }
+    }
+}
+
+// written while holding the lock, so lines of concurrent scans don't
+// interleave
+fn write_line(out: &mut dyn Write, line: &str) -> io::Result<()> {
+    out.write_all(line.as_bytes())?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::Signature;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_audit_lines() {
+        let out = Shared::default();
+        let log = AuditLog::new(out.clone());
+
+        let found = ScanResult::found("stream", Signature::from("Eicar"));
+        log.record("a\"b", Ok(&found), Duration::from_millis(5))
+            .unwrap();
+        log.set_db_version(Some(String::from("ClamAV 1.2.0/27000")));
+        let error = ClamError::InvalidData(String::from("garbage"));
+        log.record("c", Err(&error), Duration::ZERO).unwrap();
+
+        let written = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let lines = written.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(
+            r#""input":"a\"b","verdict":"found","signature":"Eicar","reply":"stream: Eicar FOUND","error":null,"duration_ms":5.0,"db_version":null}"#
+        ));
+        assert!(lines[1].contains(
+            r#""input":"c","verdict":"error","signature":null,"reply":null,"error":"Could not parse: garbage","duration_ms":0.0,"db_version":"ClamAV 1.2.0/27000"}"#
+        ));
+    }
+}
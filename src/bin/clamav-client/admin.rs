@@ -0,0 +1,265 @@
+//! `ping`, `version`, `reload` and `stats`: daemon status for operators.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clamav::monitor::StatsSnapshot;
+use clamav::response::{Stats, Version};
+use clamav::{ClamClient, Command, StatsMonitor};
+
+use crate::output::{Output, Printer, Record};
+use crate::{EXIT_CLEAN, EXIT_ERROR};
+
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(60);
+const RELOAD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Flags and `--name value` options given after a subcommand.
+#[derive(Debug, Default, PartialEq)]
+pub struct Flags {
+    wait: bool,
+    follow: bool,
+    timeout: Option<Duration>,
+    interval: Option<Duration>,
+}
+
+impl Flags {
+    pub fn parse(command: &str, operands: &[String]) -> Result<Self, String> {
+        let mut flags = Flags::default();
+        let mut operands = operands.iter();
+
+        while let Some(arg) = operands.next() {
+            let mut seconds = || match operands.next().map(|value| value.parse()) {
+                Some(Ok(seconds)) => Ok(Some(Duration::from_secs(seconds))),
+                Some(Err(e)) => Err(format!("invalid {}: {}", arg, e)),
+                None => Err(format!("{} needs a value", arg)),
+            };
+
+            match (command, arg.as_str()) {
+                ("reload", "--wait") => flags.wait = true,
+                ("reload", "--timeout") => flags.timeout = seconds()?,
+                ("stats", "--follow") => flags.follow = true,
+                ("stats", "--interval") => flags.interval = seconds()?,
+                _ => return Err(format!("unknown option {} for {}", arg, command)),
+            }
+        }
+
+        Ok(flags)
+    }
+}
+
+/// Pings the daemon and reports the round trip time.
+pub fn ping(client: &ClamClient, output: Output) -> i32 {
+    let started = Instant::now();
+    let reply = client.command_typed::<String>(&Command::Ping);
+    let latency = started.elapsed();
+    let endpoint = client.endpoint().to_string();
+
+    let (line, latency_ms, error, code) = match reply {
+        Ok(ref pong) if pong.trim_end_matches('\0') == "PONG" => {
+            let ms = latency.as_secs_f64() * 1000.0;
+            let line = format!("PONG from {} in {:.1} ms", endpoint, ms);
+            (line, Some(format!("{:.3}", ms)), None, EXIT_CLEAN)
+        }
+        Ok(other) => {
+            let error = format!("unexpected reply {:?}", other);
+            (
+                format!("{}: {}", endpoint, error),
+                None,
+                Some(error),
+                EXIT_ERROR,
+            )
+        }
+        Err(e) => (
+            format!("{}: {}", endpoint, e),
+            None,
+            Some(e.to_string()),
+            EXIT_ERROR,
+        ),
+    };
+
+    let mut printer = Printer::new(output);
+    printer.record(Record {
+        line,
+        fields: vec![
+            ("endpoint", Some(endpoint)),
+            ("alive", Some((code == EXIT_CLEAN).to_string())),
+            ("latency_ms", latency_ms),
+            ("error", error),
+        ],
+    });
+    printer.finish();
+    code
+}
+
+pub fn version(client: &ClamClient, output: Output) -> i32 {
+    match client.version() {
+        Ok(version) => {
+            let mut printer = Printer::new(output);
+            printer.record(version_record(&version, None));
+            printer.finish();
+            EXIT_CLEAN
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// Asks the daemon to reload. With `--wait`, polls VERSION until the loaded
+/// database changes or `--timeout` (60 seconds by default) passes; clamd
+/// keeps answering while it reloads, so a changed database is the only sign
+/// the reload finished.
+pub fn reload(client: &ClamClient, flags: &Flags, output: Output) -> i32 {
+    let before = if flags.wait {
+        match client.version() {
+            Ok(version) => Some(version),
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_ERROR;
+            }
+        }
+    } else {
+        None
+    };
+
+    if let Err(e) = client.reload() {
+        eprintln!("{}", e);
+        return EXIT_ERROR;
+    }
+
+    let mut printer = Printer::new(output);
+    let before = match before {
+        Some(before) => before,
+        None => {
+            printer.record(Record {
+                line: String::from("RELOADING"),
+                fields: vec![("status", Some(String::from("reloading")))],
+            });
+            printer.finish();
+            return EXIT_CLEAN;
+        }
+    };
+
+    let timeout = flags.timeout.unwrap_or(RELOAD_TIMEOUT);
+    let started = Instant::now();
+    loop {
+        thread::sleep(RELOAD_POLL_INTERVAL);
+
+        if let Ok(after) = client.version() {
+            if after.build_number != before.build_number
+                || after.release_date_raw != before.release_date_raw
+            {
+                printer.record(version_record(&after, Some("reloaded")));
+                printer.finish();
+                return EXIT_CLEAN;
+            }
+        }
+
+        if started.elapsed() >= timeout {
+            printer.record(version_record(&before, Some("unchanged")));
+            printer.finish();
+            return EXIT_CLEAN;
+        }
+    }
+}
+
+/// Prints STATS once, or with `--follow` every `--interval` seconds (5 by
+/// default) together with the change since the previous poll.
+pub fn stats(client: ClamClient, flags: &Flags, output: Output) -> i32 {
+    if !flags.follow {
+        return match client.stats() {
+            Ok(stats) => {
+                let mut printer = Printer::new(output);
+                printer.record(stats_record(&stats, None));
+                printer.finish();
+                EXIT_CLEAN
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                EXIT_ERROR
+            }
+        };
+    }
+
+    let mut printer = Printer::streaming(output);
+    let monitor = StatsMonitor::start(client, flags.interval.unwrap_or(STATS_INTERVAL));
+    loop {
+        match monitor.recv() {
+            Ok(StatsSnapshot { stats, delta, .. }) => {
+                printer.record(stats_record(&stats, delta.map(|d| d.queue)))
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+fn version_record(version: &Version, status: Option<&str>) -> Record {
+    let line = match status {
+        Some(status) => format!("{} ({})", version, status),
+        None => version.to_string(),
+    };
+
+    let mut fields = vec![
+        ("version", Some(version.version_tag.clone())),
+        ("build", Some(version.build_number.to_string())),
+        ("release_date", Some(version.release_date_raw.clone())),
+    ];
+    if let Some(status) = status {
+        fields.push(("status", Some(status.to_string())));
+    }
+
+    Record { line, fields }
+}
+
+fn stats_record(stats: &Stats, queue_change: Option<i64>) -> Record {
+    let line = match queue_change {
+        Some(change) => format!("{} ({:+} queued)", stats, change),
+        None => stats.to_string(),
+    };
+
+    Record {
+        line,
+        fields: vec![
+            ("state", Some(stats.state.clone())),
+            ("pools", Some(stats.pools.to_string())),
+            ("threads_live", Some(stats.threads_live.to_string())),
+            ("threads_idle", Some(stats.threads_idle.to_string())),
+            ("threads_max", Some(stats.threads_max.to_string())),
+            ("queue", Some(stats.queue.to_string())),
+            ("queue_change", queue_change.map(|c| c.to_string())),
+            ("mem_used", stats.mem_used.clone()),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(command: &str, line: &str) -> Result<Flags, String> {
+        Flags::parse(
+            command,
+            &line
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        assert_eq!(
+            flags("reload", "--wait --timeout 10").unwrap(),
+            Flags {
+                wait: true,
+                timeout: Some(Duration::from_secs(10)),
+                ..Flags::default()
+            }
+        );
+        assert!(flags("stats", "--follow --interval 1").unwrap().follow);
+        assert!(flags("stats", "--wait").is_err());
+        assert!(flags("reload", "--timeout soon").is_err());
+    }
+}
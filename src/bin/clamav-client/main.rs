@@ -0,0 +1,465 @@
+//! Command-line client for clamd.
+//!
+//! `clamav-client scan -` streams stdin, so the client can sit at the end of
+//! a pipeline: `curl -s https://example.com/file | clamav-client scan -`.
+//!
+//! Exit codes follow clamscan: 0 if everything was clean, 1 if anything was
+//! found, 2 if there were errors and nothing was found.
+
+mod admin;
+mod output;
+mod watch;
+
+use std::collections::HashSet;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+
+use clamav::error::ClamError;
+use clamav::response::ScanResult;
+use clamav::{AuditLog, ClamClient, ClientConfig, DirScan, Webhook};
+
+use output::{Output, Printer, Record};
+
+const USAGE: &str =
+    "usage: clamav-client [--host HOST] [--port PORT] [--socket PATH] [--output plain|json|table]
+                     [--webhook URL] [--audit-log FILE] <command>
+
+commands:
+    ping
+    version
+    reload [--wait] [--timeout SECONDS]
+    stats [--follow] [--interval SECONDS]
+    scan [--jobs N] [--on-found report|quarantine|delete|revoke] [--quarantine-dir DIR] <FILE|DIR|->...
+    watch <DIR> [--on-found report|quarantine|delete|revoke] [--quarantine-dir DIR] [--interval SECONDS]";
+
+const EXIT_CLEAN: i32 = 0;
+const EXIT_FOUND: i32 = 1;
+const EXIT_ERROR: i32 = 2;
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    // where clamd listens
+    config: ClientConfig,
+    output: Output,
+    // posted every detection of `scan` directories and `watch`
+    webhook: Option<String>,
+    // JSON lines appended for every scan
+    audit_log: Option<String>,
+    command: String,
+    operands: Vec<String>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(EXIT_ERROR);
+        }
+    };
+
+    let client = match ClamClient::from_config(&args.config) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_ERROR);
+        }
+    };
+
+    let client = match &args.audit_log {
+        Some(path) => match AuditLog::open(path) {
+            Ok(log) => {
+                log.set_db_version(client.version().ok().map(|v| v.to_string()));
+                client.with_audit_log(Arc::new(log))
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                process::exit(EXIT_ERROR);
+            }
+        },
+        None => client,
+    };
+
+    let webhook = match args.webhook.as_deref().map(Webhook::new).transpose() {
+        Ok(webhook) => webhook,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(EXIT_ERROR);
+        }
+    };
+
+    let code = match args.command.as_str() {
+        "scan" => scan(&client, &args.operands, webhook.as_ref(), args.output),
+        "ping" | "version" | "reload" | "stats" => {
+            match admin::Flags::parse(&args.command, &args.operands) {
+                Ok(flags) => match args.command.as_str() {
+                    "ping" => admin::ping(&client, args.output),
+                    "version" => admin::version(&client, args.output),
+                    "reload" => admin::reload(&client, &flags, args.output),
+                    _ => admin::stats(client, &flags, args.output),
+                },
+                Err(message) => {
+                    eprintln!("{}\n{}", message, USAGE);
+                    EXIT_ERROR
+                }
+            }
+        }
+        "watch" => match watch::Options::parse(&args.operands) {
+            Ok(options) => watch::run(
+                &client,
+                &options,
+                webhook.as_ref(),
+                Printer::streaming(args.output),
+            ),
+            Err(message) => {
+                eprintln!("{}\n{}", message, USAGE);
+                EXIT_ERROR
+            }
+        },
+        other => {
+            eprintln!("unknown command {:?}\n{}", other, USAGE);
+            EXIT_ERROR
+        }
+    };
+
+    process::exit(code);
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> std::result::Result<Args, String> {
+    let mut config = ClientConfig::default();
+    let mut output = Output::Plain;
+    let mut webhook = None;
+    let mut audit_log = None;
+
+    let command = loop {
+        let arg = match args.next() {
+            Some(arg) => arg,
+            None => return Err(String::from("missing command")),
+        };
+
+        let mut value = || match args.next() {
+            Some(value) => Ok(value),
+            None => Err(format!("{} needs a value", arg)),
+        };
+
+        match arg.as_str() {
+            "--host" => config.host = Some(value()?),
+            "--port" => {
+                config.port = match value()?.parse() {
+                    Ok(port) => port,
+                    Err(e) => return Err(format!("invalid port: {}", e)),
+                }
+            }
+            "--socket" => config.socket = Some(PathBuf::from(value()?)),
+            "--output" => output = value()?.parse()?,
+            "--webhook" => webhook = Some(value()?),
+            "--audit-log" => audit_log = Some(value()?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => break arg,
+        }
+    };
+
+    let operands: Vec<String> = args.collect();
+    if command == "scan" && operands.is_empty() {
+        return Err(String::from("nothing to scan"));
+    }
+
+    Ok(Args {
+        config,
+        output,
+        webhook,
+        audit_log,
+        command,
+        operands,
+    })
+}
+
+/// Streams each operand (`-` for stdin) and prints one line per verdict.
+/// Directories are walked and their files scanned `--jobs` at a time, each
+/// job over its own connection, followed by a summary of the run.
+fn scan(
+    client: &ClamClient,
+    operands: &[String],
+    webhook: Option<&Webhook>,
+    output: Output,
+) -> i32 {
+    let (flags, operands) = match scan_options(operands) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return EXIT_ERROR;
+        }
+    };
+    let action = match flags.on_found.action(&flags.quarantine_dir) {
+        Ok(action) => action,
+        Err(e) => {
+            eprintln!("{}: {}", flags.quarantine_dir.display(), e);
+            return EXIT_ERROR;
+        }
+    };
+
+    let mut printer = Printer::new(output);
+    let mut tally = Tally::default();
+    let mut walked = false;
+
+    for operand in operands {
+        if operand != "-" && Path::new(operand).is_dir() {
+            walked = true;
+            let dir_scan = DirScan::new(client, operand).jobs(flags.jobs);
+            let dir_scan = match webhook {
+                Some(webhook) => dir_scan.notify(webhook.clone()),
+                None => dir_scan,
+            };
+            let dir_scan = match &action {
+                Some(action) => dir_scan.on_found(action.clone()),
+                None => dir_scan,
+            };
+            scan_dir(dir_scan, operand, &mut printer, &mut tally);
+            continue;
+        }
+
+        let (label, result) = if operand == "-" {
+            ("stdin", client.scan_stream(io::stdin().lock()))
+        } else {
+            (operand.as_str(), client.scan_file(operand))
+        };
+
+        let (status, record) = verdict(label, result.as_ref());
+        tally.add(status);
+        printer.record(record);
+    }
+
+    printer.finish();
+    if walked {
+        let summary = format!(
+            "scanned {} file(s): {} infected, {} error(s)",
+            tally.scanned, tally.found, tally.errors
+        );
+        match output {
+            Output::Plain => println!("{}", summary),
+            _ => eprintln!("{}", summary),
+        }
+    }
+
+    exit_code(tally.found > 0, tally.errors > 0)
+}
+
+/// Options given to `scan` before the files to scan.
+#[derive(Debug, PartialEq)]
+struct ScanFlags {
+    jobs: usize,
+    on_found: watch::OnFound,
+    quarantine_dir: PathBuf,
+}
+
+fn scan_options(operands: &[String]) -> std::result::Result<(ScanFlags, &[String]), String> {
+    let mut flags = ScanFlags {
+        jobs: 1,
+        on_found: watch::OnFound::Report,
+        quarantine_dir: PathBuf::from("quarantine"),
+    };
+
+    let mut rest = operands;
+    while let [option, value, tail @ ..] = rest {
+        match option.as_str() {
+            "--jobs" => {
+                flags.jobs = match value.parse() {
+                    Ok(0) | Err(_) => return Err(format!("invalid --jobs {:?}", value)),
+                    Ok(jobs) => jobs,
+                }
+            }
+            "--on-found" => flags.on_found = watch::OnFound::parse(value)?,
+            "--quarantine-dir" => flags.quarantine_dir = PathBuf::from(value),
+            _ => break,
+        }
+        rest = tail;
+    }
+
+    match rest.first() {
+        None => Err(String::from("nothing to scan")),
+        Some(option) if option.starts_with("--") => Err(format!("unknown option {}", option)),
+        Some(_) => Ok((flags, rest)),
+    }
+}
+
+/// Counts of the results printed so far.
+#[derive(Default)]
+struct Tally {
+    scanned: usize,
+    found: usize,
+    errors: usize,
+}
+
+impl Tally {
+    fn add(&mut self, status: &str) {
+        self.scanned += 1;
+        match status {
+            "found" => self.found += 1,
+            "error" => self.errors += 1,
+            _ => {}
+        }
+    }
+}
+
+/// Prints every file as it completes. Unless the records are printed right
+/// away, a running count is shown on a terminal instead.
+fn scan_dir(dir_scan: DirScan<'_>, dir: &str, printer: &mut Printer, tally: &mut Tally) {
+    let counter = !printer.is_immediate() && io::stderr().is_terminal();
+    let mut reported = HashSet::new();
+
+    let run = dir_scan
+        .on_progress(|path, result| {
+            let (status, record) = verdict(&path.to_string_lossy(), result);
+            tally.add(status);
+            printer.record(record);
+            reported.insert(path.to_path_buf());
+            if counter {
+                eprint!("\rscanned {} file(s)", tally.scanned);
+            }
+        })
+        .run();
+
+    if counter {
+        eprintln!();
+    }
+
+    match run {
+        // directories that couldn't be read never reached the progress callback
+        Ok(report) => {
+            for (path, error) in report.notify_errors {
+                eprintln!("{}: {}", path.display(), error);
+            }
+            for (path, taken) in report.actions {
+                eprintln!("{}: {}", path.display(), taken);
+            }
+            for (path, error) in report.errors {
+                if !reported.contains(&path) {
+                    let label = path.to_string_lossy();
+                    tally.errors += 1;
+                    printer.record(failure(&label, &error));
+                }
+            }
+        }
+        Err(e) => {
+            tally.errors += 1;
+            printer.record(failure(dir, &e.to_string()));
+        }
+    }
+}
+
+/// The record of one scan and its status: `ok`, `found` or `error`.
+fn verdict(
+    label: &str,
+    result: std::result::Result<&ScanResult, &ClamError>,
+) -> (&'static str, Record) {
+    let (line, status, signature, error) = match result {
+        Ok(ScanResult::Ok) => (format!("{}: OK", label), "ok", None, None),
+        Ok(ScanResult::Found(_, signature, _)) => (
+            format!("{}: {} FOUND", label, signature),
+            "found",
+            Some(signature.to_string()),
+            None,
+        ),
+        Ok(ScanResult::Failed(_, message, _) | ScanResult::Error(message)) => {
+            return ("error", failure(label, message))
+        }
+        Ok(other) => return ("error", failure(label, &other.to_string())),
+        Err(e) => return ("error", failure(label, &e.to_string())),
+    };
+
+    let record = Record {
+        line,
+        fields: vec![
+            ("input", Some(label.to_string())),
+            ("status", Some(status.to_string())),
+            ("signature", signature),
+            ("error", error),
+        ],
+    };
+    (status, record)
+}
+
+fn failure(label: &str, error: &str) -> Record {
+    Record {
+        line: format!("{}: {}", label, error),
+        fields: vec![
+            ("input", Some(label.to_string())),
+            ("status", Some(String::from("error"))),
+            ("signature", None),
+            ("error", Some(error.to_string())),
+        ],
+    }
+}
+
+/// A detection takes priority over errors, as in clamscan.
+fn exit_code(found: bool, errors: bool) -> i32 {
+    if found {
+        EXIT_FOUND
+    } else if errors {
+        EXIT_ERROR
+    } else {
+        EXIT_CLEAN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> std::result::Result<Args, String> {
+        parse_args(line.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args("--port 3311 scan - a.txt").unwrap(),
+            Args {
+                config: ClientConfig {
+                    port: 3311,
+                    ..ClientConfig::default()
+                },
+                output: Output::Plain,
+                webhook: None,
+                audit_log: None,
+                command: String::from("scan"),
+                operands: vec![String::from("-"), String::from("a.txt")],
+            }
+        );
+        assert!(args("scan").is_err());
+        assert!(args("--port").is_err());
+        assert!(args("--verbose scan -").is_err());
+        assert_eq!(args("--output json scan -").unwrap().output, Output::Json);
+        assert!(args("--output yaml scan -").is_err());
+    }
+
+    #[test]
+    fn test_scan_options() {
+        let operands = |line: &str| {
+            line.split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        let given = operands("--jobs 4 --on-found delete /srv -");
+        let (flags, rest) = scan_options(&given).unwrap();
+        assert_eq!(flags.jobs, 4);
+        assert_eq!(flags.on_found, watch::OnFound::Delete);
+        assert_eq!(rest, &operands("/srv -")[..]);
+        assert_eq!(scan_options(&operands("/srv")).unwrap().0.jobs, 1);
+        assert!(scan_options(&operands("--jobs 0 /srv")).is_err());
+        assert!(scan_options(&operands("--jobs 4")).is_err());
+        assert!(scan_options(&operands("--verbose /srv")).is_err());
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(false, false), 0);
+        assert_eq!(exit_code(true, false), 1);
+        assert_eq!(exit_code(true, true), 1);
+        assert_eq!(exit_code(false, true), 2);
+    }
+}
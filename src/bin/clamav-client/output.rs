@@ -0,0 +1,184 @@
+use std::str::FromStr;
+
+use clamav::json;
+
+/// How results are printed, chosen with `--output`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// One human-readable line per result, printed as it arrives.
+    Plain,
+    /// A JSON array with one object per result. Every object of a command
+    /// has the same keys; missing values are `null`.
+    Json,
+    /// Results aligned in columns under a header.
+    Table,
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Output::Plain),
+            "json" => Ok(Output::Json),
+            "table" => Ok(Output::Table),
+            other => Err(format!("unknown output format {:?}", other)),
+        }
+    }
+}
+
+/// One result: its plain-text line and its named fields.
+pub struct Record {
+    pub line: String,
+    pub fields: Vec<(&'static str, Option<String>)>,
+}
+
+/// Prints records in the chosen format. Plain records are printed right
+/// away; the other formats need all records and print on `finish`.
+pub struct Printer {
+    output: Output,
+    streaming: bool,
+    records: Vec<Record>,
+}
+
+impl Printer {
+    pub fn new(output: Output) -> Self {
+        Self {
+            output,
+            streaming: false,
+            records: Vec::new(),
+        }
+    }
+
+    /// For commands that run until interrupted: every record is printed
+    /// right away, JSON as one object per line and tables as plain lines.
+    pub fn streaming(output: Output) -> Self {
+        Self {
+            streaming: true,
+            ..Self::new(output)
+        }
+    }
+
+    /// Whether records are printed as they arrive.
+    pub fn is_immediate(&self) -> bool {
+        self.output == Output::Plain || self.streaming
+    }
+
+    pub fn record(&mut self, record: Record) {
+        match (self.output, self.streaming) {
+            (Output::Json, true) => println!("{}", json_object(&record)),
+            (Output::Plain, _) | (_, true) => println!("{}", record.line),
+            _ => self.records.push(record),
+        }
+    }
+
+    pub fn finish(self) {
+        match self.output {
+            Output::Plain => {}
+            Output::Json => println!("{}", json(&self.records)),
+            Output::Table => print!("{}", table(&self.records)),
+        }
+    }
+}
+
+fn json(records: &[Record]) -> String {
+    let objects = records.iter().map(json_object).collect::<Vec<_>>();
+    format!("[{}]", objects.join(","))
+}
+
+fn json_object(record: &Record) -> String {
+    let fields = record
+        .fields
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Some(value) => json::string(value),
+                None => String::from("null"),
+            };
+            format!("{}:{}", json::string(key), value)
+        })
+        .collect::<Vec<_>>();
+
+    format!("{{{}}}", fields.join(","))
+}
+
+fn table(records: &[Record]) -> String {
+    let header = match records.first() {
+        Some(record) => record
+            .fields
+            .iter()
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>(),
+        None => return String::new(),
+    };
+
+    let rows = records
+        .iter()
+        .map(|record| {
+            record
+                .fields
+                .iter()
+                .map(|(_, value)| value.as_deref().unwrap_or("-"))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = header.iter().map(|key| key.len()).collect::<Vec<_>>();
+    for row in &rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect::<Vec<_>>();
+        out.push_str(cells.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                line: String::new(),
+                fields: vec![
+                    ("input", Some(String::from("a \"b\""))),
+                    ("signature", None),
+                ],
+            },
+            Record {
+                line: String::new(),
+                fields: vec![
+                    ("input", Some(String::from("c"))),
+                    ("signature", Some(String::from("Eicar"))),
+                ],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_json() {
+        assert_eq!(
+            json(&records()),
+            r#"[{"input":"a \"b\"","signature":null},{"input":"c","signature":"Eicar"}]"#
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(
+            table(&records()),
+            "input  signature\na \"b\"  -\nc      Eicar\n"
+        );
+    }
+}
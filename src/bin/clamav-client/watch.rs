@@ -0,0 +1,175 @@
+//! `watch`: scans files as they appear in a directory, see `DirWatch`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clamav::policy::Action;
+use clamav::{ClamClient, DirWatch, Webhook};
+
+use crate::output::Printer;
+use crate::{verdict, EXIT_ERROR};
+
+const INTERVAL: Duration = Duration::from_secs(2);
+
+/// What to do with a file something was found in, chosen with
+/// `--on-found`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnFound {
+    Report,
+    /// Move the file into the quarantine directory.
+    Quarantine,
+    Delete,
+    /// Remove all permissions from the file.
+    Revoke,
+}
+
+impl OnFound {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "report" => Ok(OnFound::Report),
+            "quarantine" => Ok(OnFound::Quarantine),
+            "delete" => Ok(OnFound::Delete),
+            "revoke" => Ok(OnFound::Revoke),
+            other => Err(format!("unknown action {:?}", other)),
+        }
+    }
+
+    /// The library action, creating the quarantine directory if needed.
+    pub fn action(self, quarantine_dir: &Path) -> io::Result<Option<Action>> {
+        match self {
+            OnFound::Report => Ok(None),
+            OnFound::Quarantine => {
+                fs::create_dir_all(quarantine_dir)?;
+                Ok(Some(Action::Quarantine(quarantine_dir.to_path_buf())))
+            }
+            OnFound::Delete => Ok(Some(Action::Delete)),
+            OnFound::Revoke => Ok(Some(Action::Revoke)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    dir: PathBuf,
+    on_found: OnFound,
+    quarantine_dir: PathBuf,
+    interval: Duration,
+}
+
+impl Options {
+    pub fn parse(operands: &[String]) -> Result<Self, String> {
+        let mut operands = operands.iter();
+        let dir = match operands.next() {
+            Some(dir) if !dir.starts_with("--") => PathBuf::from(dir),
+            _ => return Err(String::from("missing directory to watch")),
+        };
+
+        let mut options = Options {
+            dir,
+            on_found: OnFound::Report,
+            quarantine_dir: PathBuf::from("quarantine"),
+            interval: INTERVAL,
+        };
+
+        while let Some(arg) = operands.next() {
+            let value = match operands.next() {
+                Some(value) => value,
+                None => return Err(format!("{} needs a value", arg)),
+            };
+
+            match arg.as_str() {
+                "--on-found" => options.on_found = OnFound::parse(value)?,
+                "--quarantine-dir" => options.quarantine_dir = PathBuf::from(value),
+                "--interval" => {
+                    options.interval = match value.parse() {
+                        Ok(seconds) => Duration::from_secs(seconds),
+                        Err(e) => return Err(format!("invalid interval: {}", e)),
+                    }
+                }
+                other => return Err(format!("unknown option {}", other)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Watches until the directory can no longer be read, printing every file
+/// scanned and what was done about it.
+pub fn run(
+    client: &ClamClient,
+    options: &Options,
+    webhook: Option<&Webhook>,
+    mut printer: Printer,
+) -> i32 {
+    let action = match options.on_found.action(&options.quarantine_dir) {
+        Ok(action) => action,
+        Err(e) => {
+            eprintln!("{}: {}", options.quarantine_dir.display(), e);
+            return EXIT_ERROR;
+        }
+    };
+
+    let mut watch = DirWatch::new(client, &options.dir).interval(options.interval);
+    if let Some(webhook) = webhook {
+        watch = watch.notify(webhook.clone());
+    }
+    if let Some(action) = action {
+        watch = watch.on_found(action);
+    }
+
+    let watched = watch.run(|watched| {
+        let label = watched.path.to_string_lossy();
+        if let Some(e) = &watched.notify_error {
+            eprintln!("{}: {}", label, e);
+        }
+
+        let (_, mut record) = verdict(&label, watched.result.as_ref());
+        if let Some(taken) = &watched.action {
+            record.line = format!("{} ({})", record.line, taken);
+        }
+        record
+            .fields
+            .push(("action", watched.action.map(|t| t.to_string())));
+        printer.record(record);
+    });
+
+    match watched {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}: {}", options.dir.display(), e);
+            EXIT_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(line: &str) -> Result<Options, String> {
+        Options::parse(
+            &line
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(
+            options("/srv --on-found quarantine --interval 5").unwrap(),
+            Options {
+                dir: PathBuf::from("/srv"),
+                on_found: OnFound::Quarantine,
+                quarantine_dir: PathBuf::from("quarantine"),
+                interval: Duration::from_secs(5),
+            }
+        );
+        assert!(options("--on-found quarantine").is_err());
+        assert!(options("/srv --on-found shred").is_err());
+    }
+}
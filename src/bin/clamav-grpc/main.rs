@@ -0,0 +1,236 @@
+//! gRPC façade over clamd, serving the methods of `proto/clamav.proto` so
+//! services in any language can scan through one contract.
+//!
+//! `Scan` is client-streaming: chunks are forwarded to clamd as INSTREAM
+//! chunks while they arrive, so request bodies are never buffered whole.
+
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process;
+
+use tokio::io::AsyncWriteExt;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use clamav::error::ClamError;
+use clamav::response::ScanResult;
+use clamav::{AsyncClamClient, ClamClient, ClientConfig};
+
+mod proto {
+    tonic::include_proto!("clamav");
+}
+
+use proto::clamav_server::{Clamav, ClamavServer};
+use proto::{
+    PingReply, PingRequest, ScanChunk, ScanReply, StatsReply, StatsRequest, Verdict, VersionReply,
+    VersionRequest,
+};
+
+const USAGE: &str =
+    "usage: clamav-grpc [--listen ADDR] [--host HOST] [--port PORT] [--socket PATH]";
+
+// chunks received but not yet sent to clamd
+const PIPE_CAPACITY: usize = 256 * 1024;
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    listen: SocketAddr,
+    // where clamd listens
+    config: ClientConfig,
+}
+
+struct Facade {
+    client: AsyncClamClient,
+}
+
+#[tonic::async_trait]
+impl Clamav for Facade {
+    async fn ping(
+        &self,
+        _: Request<PingRequest>,
+    ) -> std::result::Result<Response<PingReply>, Status> {
+        let alive = self.client.ping().await;
+        Ok(Response::new(PingReply { alive }))
+    }
+
+    async fn version(
+        &self,
+        _: Request<VersionRequest>,
+    ) -> std::result::Result<Response<VersionReply>, Status> {
+        let version = self.client.version().await.map_err(status)?;
+        Ok(Response::new(VersionReply {
+            version: version.version_tag,
+            build_number: version.build_number,
+            release_date: version.release_date_raw,
+        }))
+    }
+
+    async fn stats(
+        &self,
+        _: Request<StatsRequest>,
+    ) -> std::result::Result<Response<StatsReply>, Status> {
+        let stats = self.client.stats().await.map_err(status)?;
+        Ok(Response::new(StatsReply {
+            state: stats.state,
+            pools: stats.pools,
+            threads_live: stats.threads_live,
+            threads_idle: stats.threads_idle,
+            threads_max: stats.threads_max,
+            queue: stats.queue,
+            raw: stats.raw,
+        }))
+    }
+
+    async fn scan(
+        &self,
+        request: Request<Streaming<ScanChunk>>,
+    ) -> std::result::Result<Response<ScanReply>, Status> {
+        let mut chunks = request.into_inner();
+        let (mut sender, receiver) = tokio::io::duplex(PIPE_CAPACITY);
+
+        let forward = async move {
+            while let Some(chunk) = chunks.message().await? {
+                if sender.write_all(&chunk.data).await.is_err() {
+                    // the scan ended early, e.g. over StreamMaxLength
+                    break;
+                }
+            }
+            // hanging up ends the stream
+            drop(sender);
+            Ok::<_, Status>(())
+        };
+
+        let (forwarded, result) = tokio::join!(forward, self.client.scan_async_read(receiver));
+        // a broken request stream must not pass for a clean, shorter one
+        forwarded?;
+
+        Ok(Response::new(scan_reply(result.map_err(status)?)))
+    }
+}
+
+fn scan_reply(result: ScanResult) -> ScanReply {
+    match result {
+        ScanResult::Ok => ScanReply {
+            verdict: Verdict::Clean as i32,
+            ..ScanReply::default()
+        },
+        ScanResult::Found(_, signature, reply) => ScanReply {
+            verdict: Verdict::Found as i32,
+            signature: signature.raw,
+            reply,
+        },
+        ScanResult::Failed(_, _, reply) | ScanResult::Error(reply) => ScanReply {
+            verdict: Verdict::Error as i32,
+            reply,
+            ..ScanReply::default()
+        },
+        other => ScanReply {
+            verdict: Verdict::Error as i32,
+            reply: other.to_string(),
+            ..ScanReply::default()
+        },
+    }
+}
+
+fn status(e: ClamError) -> Status {
+    match e {
+        ClamError::ConnectionError(_) | ClamError::Timeout(_) | ClamError::CircuitOpen(_) => {
+            Status::unavailable(e.to_string())
+        }
+        ClamError::SizeCapExceeded(_) | ClamError::ConcurrencyLimitReached(_) => {
+            Status::resource_exhausted(e.to_string())
+        }
+        ClamError::Cancelled => Status::cancelled(e.to_string()),
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let client = match ClamClient::from_config(&args.config) {
+        Ok(client) => AsyncClamClient::new(client),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    let served = Server::builder()
+        .add_service(ClamavServer::new(Facade { client }))
+        .serve(args.listen)
+        .await;
+    if let Err(e) = served {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> std::result::Result<Args, String> {
+    let mut listen = SocketAddr::from(([127, 0, 0, 1], 50051));
+    let mut config = ClientConfig::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || match args.next() {
+            Some(value) => Ok(value),
+            None => Err(format!("{} needs a value", arg)),
+        };
+
+        match arg.as_str() {
+            "--listen" => {
+                listen = match value()?.parse() {
+                    Ok(listen) => listen,
+                    Err(e) => return Err(format!("invalid listen address: {}", e)),
+                }
+            }
+            "--host" => config.host = Some(value()?),
+            "--port" => {
+                config.port = match value()?.parse() {
+                    Ok(port) => port,
+                    Err(e) => return Err(format!("invalid port: {}", e)),
+                }
+            }
+            "--socket" => config.socket = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    Ok(Args { listen, config })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> std::result::Result<Args, String> {
+        parse_args(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(&["--listen", "0.0.0.0:9000", "--socket", "/run/clamd.ctl"]).unwrap();
+        assert_eq!(args.listen, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(args.config.socket, Some(PathBuf::from("/run/clamd.ctl")));
+        assert_eq!(args.config.port, 3310);
+
+        assert!(parse(&["--listen", "nowhere"]).is_err());
+        assert!(parse(&["scan"]).is_err());
+    }
+
+    #[test]
+    fn test_scan_reply() {
+        let found = ScanResult::parse("stream: Eicar-Test FOUND").remove(0);
+        let reply = scan_reply(found);
+        assert_eq!(reply.verdict, Verdict::Found as i32);
+        assert_eq!(reply.signature, "Eicar-Test");
+        assert_eq!(scan_reply(ScanResult::Ok).verdict, Verdict::Clean as i32);
+    }
+}
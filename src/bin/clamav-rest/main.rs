@@ -0,0 +1,455 @@
+//! REST façade over clamd, for services that talk to an HTTP scanning
+//! bridge:
+//!
+//! - `POST /scan` streams the request body to clamd while it arrives and
+//!   answers with the verdict as JSON, e.g.
+//!   `{"verdict":"found","signature":"Eicar-Test-Signature","reply":"stream: Eicar-Test-Signature FOUND"}`.
+//!   Bodies may be sent with a `Content-Length` or chunked.
+//! - `GET /healthz` answers 200 while clamd answers PING, 503 otherwise.
+//!
+//! Every request is served on its own thread and connection to clamd, and
+//! every response closes the connection.
+
+use std::env;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use clamav::error::ClamError;
+use clamav::json;
+use clamav::response::ScanResult;
+use clamav::{ClamClient, ClientConfig};
+
+const USAGE: &str =
+    "usage: clamav-rest [--listen ADDR] [--host HOST] [--port PORT] [--socket PATH]";
+
+// for reading request heads and bodies from slow or stalled clients
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+// longest request or header line accepted
+const MAX_LINE: u64 = 8192;
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    listen: SocketAddr,
+    // where clamd listens
+    config: ClientConfig,
+}
+
+#[derive(Debug, PartialEq)]
+struct Head {
+    method: String,
+    path: String,
+    content_length: Option<u64>,
+    chunked: bool,
+}
+
+struct Reply {
+    status: &'static str,
+    body: String,
+}
+
+impl Reply {
+    fn json(status: &'static str, body: String) -> Self {
+        Self { status, body }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self::json(status, format!("{{\"error\":{}}}", json::string(message)))
+    }
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let client = match ClamClient::from_config(&args.config) {
+        Ok(client) => Arc::new(client),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    let listener = match TcpListener::bind(args.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("cannot listen on {}: {}", args.listen, e);
+            process::exit(1);
+        }
+    };
+
+    for stream in listener.incoming().flatten() {
+        let client = Arc::clone(&client);
+        thread::spawn(move || {
+            if let Err(e) = serve(&client, stream) {
+                eprintln!("{}", e);
+            }
+        });
+    }
+}
+
+fn serve(client: &ClamClient, stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+
+    let reply = match read_head(&mut reader) {
+        Ok(head) => handle(client, &head, &mut reader),
+        Err(e) => Reply::error("400 Bad Request", &e.to_string()),
+    };
+
+    let mut writer = &stream;
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.status,
+        reply.body.len(),
+        reply.body
+    )?;
+    writer.flush()
+}
+
+fn handle<R: BufRead>(client: &ClamClient, head: &Head, body: &mut R) -> Reply {
+    match (head.method.as_str(), head.path.as_str()) {
+        ("GET", "/healthz") if client.ping() => {
+            Reply::json("200 OK", String::from("{\"status\":\"ok\"}"))
+        }
+        ("GET", "/healthz") => Reply::json(
+            "503 Service Unavailable",
+            String::from("{\"status\":\"unavailable\"}"),
+        ),
+        ("POST", "/scan") => {
+            let result = if head.chunked {
+                client.scan_stream(Chunked::new(body))
+            } else {
+                match head.content_length {
+                    Some(length) => client.scan_stream(Exact::new(body, length)),
+                    None => return Reply::error("411 Length Required", "body length unknown"),
+                }
+            };
+            match result {
+                Ok(result) => Reply::json("200 OK", verdict(&result)),
+                Err(e) => Reply::error(error_status(&e), &e.to_string()),
+            }
+        }
+        (_, "/healthz") | (_, "/scan") => Reply::error("405 Method Not Allowed", "wrong method"),
+        _ => Reply::error("404 Not Found", "no such endpoint"),
+    }
+}
+
+fn verdict(result: &ScanResult) -> String {
+    match result {
+        ScanResult::Ok => String::from("{\"verdict\":\"clean\"}"),
+        ScanResult::Found(_, signature, reply) => format!(
+            "{{\"verdict\":\"found\",\"signature\":{},\"reply\":{}}}",
+            json::string(&signature.raw),
+            json::string(reply)
+        ),
+        ScanResult::Failed(_, _, reply) | ScanResult::Error(reply) => {
+            format!(
+                "{{\"verdict\":\"error\",\"reply\":{}}}",
+                json::string(reply)
+            )
+        }
+        other => format!(
+            "{{\"verdict\":\"error\",\"reply\":{}}}",
+            json::string(&other.to_string())
+        ),
+    }
+}
+
+fn error_status(e: &ClamError) -> &'static str {
+    match e {
+        // the request body broke off
+        ClamError::StreamError(_) => "400 Bad Request",
+        ClamError::SizeCapExceeded(_) => "413 Payload Too Large",
+        ClamError::ConnectionError(_)
+        | ClamError::CircuitOpen(_)
+        | ClamError::ConcurrencyLimitReached(_) => "503 Service Unavailable",
+        ClamError::Timeout(_) => "504 Gateway Timeout",
+        _ => "502 Bad Gateway",
+    }
+}
+
+fn read_head<R: BufRead>(reader: &mut R) -> io::Result<Head> {
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(invalid("malformed request line")),
+    };
+
+    let mut head = Head {
+        method,
+        // the query string doesn't select anything
+        path: path.split('?').next().unwrap_or_default().to_string(),
+        content_length: None,
+        chunked: false,
+    };
+
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(head);
+        }
+
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), value.trim()),
+            None => return Err(invalid("malformed header")),
+        };
+        match name.as_str() {
+            "content-length" => match value.parse() {
+                Ok(length) => head.content_length = Some(length),
+                Err(_) => return Err(invalid("malformed Content-Length")),
+            },
+            "transfer-encoding" => head.chunked = value.eq_ignore_ascii_case("chunked"),
+            _ => {}
+        }
+    }
+}
+
+/// One CRLF-terminated line, without the terminator.
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE).read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Err(invalid("truncated or overlong line"));
+    }
+
+    let line = String::from_utf8_lossy(&line);
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads a body of `Content-Length` bytes, failing if the connection ends
+/// before all of them arrived, so a cut off upload isn't scanned as if it
+/// were complete.
+struct Exact<'r, R> {
+    reader: &'r mut R,
+    remaining: u64,
+}
+
+impl<'r, R: BufRead> Exact<'r, R> {
+    fn new(reader: &'r mut R, length: u64) -> Self {
+        Self {
+            reader,
+            remaining: length,
+        }
+    }
+}
+
+impl<R: BufRead> Read for Exact<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Ok(0);
+        }
+
+        let limit = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.reader.read(&mut buf[..limit])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Decodes a chunked request body, ignoring chunk extensions and trailers.
+struct Chunked<'r, R> {
+    reader: &'r mut R,
+    // of the current chunk
+    remaining: u64,
+    done: bool,
+}
+
+impl<'r, R: BufRead> Chunked<'r, R> {
+    fn new(reader: &'r mut R) -> Self {
+        Self {
+            reader,
+            remaining: 0,
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Read for Chunked<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.remaining == 0 {
+            if self.done {
+                return Ok(0);
+            }
+
+            let line = read_line(self.reader)?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            self.remaining = match u64::from_str_radix(size, 16) {
+                Ok(size) => size,
+                Err(_) => return Err(invalid("malformed chunk size")),
+            };
+            if self.remaining == 0 {
+                self.done = true;
+                while !read_line(self.reader)?.is_empty() {}
+                return Ok(0);
+            }
+        }
+
+        let limit = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.reader.read(&mut buf[..limit])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        self.remaining -= n as u64;
+        if self.remaining == 0 && !read_line(self.reader)?.is_empty() {
+            return Err(invalid("chunk longer than its size"));
+        }
+        Ok(n)
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> std::result::Result<Args, String> {
+    let mut listen = SocketAddr::from(([127, 0, 0, 1], 8080));
+    let mut config = ClientConfig::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || match args.next() {
+            Some(value) => Ok(value),
+            None => Err(format!("{} needs a value", arg)),
+        };
+
+        match arg.as_str() {
+            "--listen" => {
+                listen = match value()?.parse() {
+                    Ok(listen) => listen,
+                    Err(e) => return Err(format!("invalid listen address: {}", e)),
+                }
+            }
+            "--host" => config.host = Some(value()?),
+            "--port" => {
+                config.port = match value()?.parse() {
+                    Ok(port) => port,
+                    Err(e) => return Err(format!("invalid port: {}", e)),
+                }
+            }
+            "--socket" => config.socket = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    Ok(Args { listen, config })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clamav::MemoryTransport;
+
+    fn request(client: &ClamClient, raw: &[u8]) -> (&'static str, String) {
+        let mut reader = raw;
+        let reply = match read_head(&mut reader) {
+            Ok(head) => handle(client, &head, &mut reader),
+            Err(e) => Reply::error("400 Bad Request", &e.to_string()),
+        };
+        (reply.status, reply.body)
+    }
+
+    #[test]
+    fn test_scan() {
+        let transport = MemoryTransport::new()
+            .reply(b"stream: OK\0")
+            .reply(b"stream: Eicar-Test FOUND\0");
+        let client = ClamClient::new_memory(transport.clone());
+
+        let clean = request(
+            &client,
+            b"POST /scan HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello",
+        );
+        assert_eq!(clean, ("200 OK", String::from("{\"verdict\":\"clean\"}")));
+
+        let found = request(
+            &client,
+            b"POST /scan?x=1 HTTP/1.1\r\ntransfer-encoding: Chunked\r\n\r\n3;ext\r\nabc\r\n2\r\nde\r\n0\r\nTrailer: x\r\n\r\n",
+        );
+        assert_eq!(found.0, "200 OK");
+        assert_eq!(
+            found.1,
+            "{\"verdict\":\"found\",\"signature\":\"Eicar-Test\",\"reply\":\"stream: Eicar-Test FOUND\"}"
+        );
+
+        let payloads = transport.received();
+        assert!(payloads[0].ends_with(b"\0\0\0\x05hello\0\0\0\0"));
+        assert!(payloads[1].ends_with(b"\0\0\0\x03abc\0\0\0\x02de\0\0\0\0"));
+
+        let unknown_length = request(&client, b"POST /scan HTTP/1.1\r\n\r\n");
+        assert_eq!(unknown_length.0, "411 Length Required");
+        // the script has run out
+        let unavailable = request(
+            &client,
+            b"POST /scan HTTP/1.1\r\nContent-Length: 1\r\n\r\nx",
+        );
+        assert_eq!(unavailable.0, "503 Service Unavailable");
+    }
+
+    #[test]
+    fn test_routing() {
+        let transport = MemoryTransport::new().reply(b"PONG\0");
+        let client = ClamClient::new_memory(transport);
+
+        assert_eq!(
+            request(&client, b"GET /healthz HTTP/1.1\r\n\r\n"),
+            ("200 OK", String::from("{\"status\":\"ok\"}"))
+        );
+        assert_eq!(
+            request(&client, b"GET /healthz HTTP/1.1\r\n\r\n").0,
+            "503 Service Unavailable"
+        );
+        assert_eq!(
+            request(&client, b"GET /scan HTTP/1.1\r\n\r\n").0,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(
+            request(&client, b"GET / HTTP/1.1\r\n\r\n").0,
+            "404 Not Found"
+        );
+        assert_eq!(request(&client, b"GET\r\n\r\n").0, "400 Bad Request");
+        assert_eq!(
+            request(&client, b"POST /scan HTTP/1.1\r\nContent-Length: x\r\n\r\n").0,
+            "400 Bad Request"
+        );
+    }
+
+    #[test]
+    fn test_short_body_is_rejected() {
+        let transport = MemoryTransport::new().reply(b"stream: OK\0");
+        let client = ClamClient::new_memory(transport);
+
+        let short = request(
+            &client,
+            b"POST /scan HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello",
+        );
+        assert_eq!(short.0, "400 Bad Request");
+    }
+
+    #[test]
+    fn test_chunked_rejects_bad_framing() {
+        let mut body = &b"3\r\nabcdef\r\n0\r\n\r\n"[..];
+        assert!(io::copy(&mut Chunked::new(&mut body), &mut io::sink()).is_err());
+        let mut body = &b"zz\r\n"[..];
+        assert!(io::copy(&mut Chunked::new(&mut body), &mut io::sink()).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Stops a client (or several clients sharing the breaker) from waiting on
+/// a daemon that is down. After `threshold` consecutive connection or
+/// command failures the circuit opens and requests fail at once with
+/// `ClamError::CircuitOpen`. Once `cooldown` has passed a single probe is
+/// let through: its success closes the circuit, its failure opens it again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    /// A probe request is in flight.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // a probe that never reports back is replaced after another cooldown
+    HalfOpen { probe_until: Instant },
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match *self.lock() {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Whether a request may go ahead; if not, how long until the next probe
+    /// is let through.
+    pub(crate) fn allow(&self) -> Result<(), Duration> {
+        let mut state = self.lock();
+        let now = Instant::now();
+
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } | State::HalfOpen { probe_until: until } if now < until => {
+                Err(until - now)
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen {
+                    probe_until: now + self.cooldown,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    pub(crate) fn record_success(&self) {
+        *self.lock() = State::Closed { failures: 0 };
+    }
+
+    pub(crate) fn record_failure(&self) {
+        let mut state = self.lock();
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            State::Open { .. } => return,
+            State::HalfOpen { .. } => self.threshold,
+        };
+
+        *state = if failures >= self.threshold {
+            State::Open {
+                until: Instant::now() + self.cooldown,
+            }
+        } else {
+            State::Closed { failures }
+        };
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.allow().is_ok());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.allow().unwrap_err() > Duration::from_secs(59));
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20));
+        breaker.record_failure();
+        thread::sleep(Duration::from_millis(30));
+
+        assert!(breaker.allow().is_ok());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.allow().is_err());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        thread::sleep(Duration::from_millis(30));
+
+        assert!(breaker.allow().is_ok());
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
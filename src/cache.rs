@@ -0,0 +1,323 @@
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::client::Result;
+use crate::response::ScanResult;
+
+/// SHA-256 of a scanned payload.
+pub type ContentHash = [u8; 32];
+
+pub fn content_hash(data: &[u8]) -> ContentHash {
+    Sha256::digest(data).into()
+}
+
+/// Storage for verdicts keyed by content hash. The client only stores
+/// definitive verdicts (clean or infected) and clears the store whenever the
+/// daemon reloads its signature database.
+pub trait ScanCache: Send + Sync {
+    fn get(&self, hash: &ContentHash) -> Option<ScanResult>;
+    fn insert(&self, hash: ContentHash, result: ScanResult);
+    fn clear(&self);
+}
+
+/// In-memory least-recently-used cache whose entries expire after `ttl`.
+#[derive(Debug)]
+pub struct LruScanCache {
+    capacity: usize,
+    ttl: Duration,
+    state: Mutex<LruState>,
+}
+
+#[derive(Debug, Default)]
+struct LruState {
+    entries: HashMap<ContentHash, Entry>,
+    // last use tick -> key, oldest first
+    recency: BTreeMap<u64, ContentHash>,
+    tick: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    result: ScanResult,
+    inserted: Instant,
+    used: u64,
+}
+
+impl LruScanCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ttl,
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruState> {
+        lock(&self.state)
+    }
+}
+
+impl LruState {
+    fn touch(&mut self, hash: &ContentHash) {
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(hash) {
+            self.recency.remove(&entry.used);
+            entry.used = self.tick;
+            self.recency.insert(self.tick, *hash);
+        }
+    }
+
+    fn remove(&mut self, hash: &ContentHash) {
+        if let Some(entry) = self.entries.remove(hash) {
+            self.recency.remove(&entry.used);
+        }
+    }
+}
+
+impl ScanCache for LruScanCache {
+    fn get(&self, hash: &ContentHash) -> Option<ScanResult> {
+        let mut state = self.lock();
+
+        let expired = match state.entries.get(hash) {
+            Some(entry) => entry.inserted.elapsed() > self.ttl,
+            None => return None,
+        };
+
+        if expired {
+            state.remove(hash);
+            return None;
+        }
+
+        state.touch(hash);
+        state.entries.get(hash).map(|entry| entry.result.clone())
+    }
+
+    fn insert(&self, hash: ContentHash, result: ScanResult) {
+        let mut state = self.lock();
+        state.remove(&hash);
+
+        while state.entries.len() >= self.capacity {
+            let oldest = match state.recency.values().next() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+            state.remove(&oldest);
+        }
+
+        state.entries.insert(
+            hash,
+            Entry {
+                result,
+                inserted: Instant::now(),
+                used: 0,
+            },
+        );
+        state.touch(&hash);
+    }
+
+    fn clear(&self) {
+        let mut state = self.lock();
+        state.entries.clear();
+        state.recency.clear();
+    }
+}
+
+/// Coalesces concurrent scans of identical content: while one caller is
+/// scanning a payload, others asking for the same hash wait for its verdict
+/// instead of sending another INSTREAM. If that scan fails, each waiting
+/// caller falls back to scanning on its own.
+#[derive(Debug, Default)]
+pub struct SingleFlight {
+    flights: Mutex<HashMap<ContentHash, Arc<Flight>>>,
+}
+
+#[derive(Debug, Default)]
+struct Flight {
+    state: Mutex<Option<Option<ScanResult>>>,
+    done: Condvar,
+}
+
+/// Publishes the leader's outcome, also when the scan panics, so waiting
+/// callers are never left hanging.
+struct Landing<'a> {
+    group: &'a SingleFlight,
+    hash: ContentHash,
+    flight: Arc<Flight>,
+    result: Option<ScanResult>,
+}
+
+impl SingleFlight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `scan` for `hash` unless an identical scan is already in flight,
+    /// in which case its verdict is shared.
+    pub fn run<F>(&self, hash: ContentHash, scan: F) -> Result<ScanResult>
+    where
+        F: FnOnce() -> Result<ScanResult>,
+    {
+        let (flight, leader) = {
+            let mut flights = lock(&self.flights);
+            match flights.get(&hash) {
+                Some(flight) => (Arc::clone(flight), false),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    flights.insert(hash, Arc::clone(&flight));
+                    (flight, true)
+                }
+            }
+        };
+
+        if leader {
+            let mut landing = Landing {
+                group: self,
+                hash,
+                flight,
+                result: None,
+            };
+
+            let result = scan();
+            landing.result = result.as_ref().ok().cloned();
+            return result;
+        }
+
+        let mut state = lock(&flight.state);
+        while state.is_none() {
+            state = match flight.done.wait(state) {
+                Ok(state) => state,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+        }
+
+        match state.clone().flatten() {
+            Some(result) => Ok(result),
+            None => {
+                drop(state);
+                scan()
+            }
+        }
+    }
+
+    /// Number of distinct payloads currently being scanned.
+    pub fn in_flight(&self) -> usize {
+        lock(&self.flights).len()
+    }
+}
+
+impl Drop for Landing<'_> {
+    fn drop(&mut self) {
+        lock(&self.group.flights).remove(&self.hash);
+        *lock(&self.flight.state) = Some(self.result.take());
+        self.flight.done.notify_all();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(n: u8) -> ContentHash {
+        [n; 32]
+    }
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let cache = LruScanCache::new(2, Duration::from_secs(60));
+        cache.insert(key(1), ScanResult::Ok);
+        cache.insert(key(2), ScanResult::Ok);
+
+        assert!(cache.get(&key(1)).is_some());
+        cache.insert(key(3), ScanResult::Ok);
+
+        assert!(cache.get(&key(1)).is_some());
+        assert!(cache.get(&key(2)).is_none());
+        assert!(cache.get(&key(3)).is_some());
+    }
+
+    #[test]
+    fn test_lru_expires_entries() {
+        let cache = LruScanCache::new(2, Duration::from_millis(0));
+        cache.insert(key(1), ScanResult::Ok);
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert!(cache.get(&key(1)).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_lru_clear() {
+        let cache = LruScanCache::new(2, Duration::from_secs(60));
+        cache.insert(key(1), ScanResult::Ok);
+        cache.clear();
+
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_single_flight_shares_result() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let group = Arc::new(SingleFlight::new());
+        let scans = Arc::new(AtomicUsize::new(0));
+
+        let handles = (0..4)
+            .map(|_| {
+                let group = Arc::clone(&group);
+                let scans = Arc::clone(&scans);
+                std::thread::spawn(move || {
+                    group.run(key(1), || {
+                        scans.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(100));
+                        Ok(ScanResult::Ok)
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().unwrap(), ScanResult::Ok);
+        }
+        assert_eq!(scans.load(Ordering::SeqCst), 1);
+        assert_eq!(group.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_single_flight_followers_retry_after_failure() {
+        use crate::error::ClamError;
+
+        let group = Arc::new(SingleFlight::new());
+        let leader = {
+            let group = Arc::clone(&group);
+            std::thread::spawn(move || {
+                group.run(key(2), || {
+                    std::thread::sleep(Duration::from_millis(100));
+                    Err(ClamError::InvalidData("boom".to_string()))
+                })
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(20));
+        let follower = group.run(key(2), || Ok(ScanResult::Ok));
+
+        assert!(leader.join().unwrap().is_err());
+        assert_eq!(follower.unwrap(), ScanResult::Ok);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::net::Shutdown;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::client::Result;
+use crate::error::ClamError;
+use crate::transport::Connection;
+
+/// Cooperative cancellation for streaming scans. Cancelling shuts down the
+/// connections of all scans currently using the token, so an upload blocked
+/// in a write stops immediately instead of finishing the transfer, and the
+/// scan returns `ClamError::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    inner: Arc<CancelState>,
+}
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    connections: Mutex<Connections>,
+}
+
+#[derive(Debug, Default)]
+struct Connections {
+    next_id: u64,
+    open: HashMap<u64, Connection>,
+}
+
+/// Keeps a connection registered with a token until dropped.
+pub(crate) struct Registration {
+    token: CancelToken,
+    id: u64,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+
+        if let Ok(connections) = self.inner.connections.lock() {
+            for connection in connections.open.values() {
+                let _ = connection.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fails with `Cancelled` once the token has been cancelled.
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(ClamError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn register(&self, connection: &Connection) -> Result<Registration> {
+        let handle = match connection.try_clone() {
+            Ok(handle) => handle,
+            Err(e) => return Err(ClamError::ConnectionError(e)),
+        };
+
+        let id = match self.inner.connections.lock() {
+            Ok(mut connections) => {
+                let id = connections.next_id;
+                connections.next_id += 1;
+                connections.open.insert(id, handle);
+                id
+            }
+            Err(_) => return Err(ClamError::Cancelled),
+        };
+
+        let registration = Registration {
+            token: self.clone(),
+            id,
+        };
+
+        // a cancel racing the insert above may have missed this connection
+        self.check()?;
+        Ok(registration)
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = self.token.inner.connections.lock() {
+            connections.open.remove(&self.id);
+        }
+    }
+}
@@ -1,35 +1,181 @@
-use byteorder::{BigEndian, ByteOrder};
-use std::io::{BufReader, Read, Write};
-use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
-use std::time::Duration;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, ErrorKind, IoSlice, Read, Seek, SeekFrom, Write};
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::audit::AuditLog;
+use crate::breaker::CircuitBreaker;
+#[cfg(feature = "cache")]
+use crate::cache::{self, ScanCache, SingleFlight};
+use crate::cancel::{CancelToken, Registration};
+use crate::command::{Command, Framing};
+use crate::counters::{ClientStats, Counters};
+use crate::dir::DirScan;
 use crate::error::ClamError;
-use crate::response::{ScanResult, Stats, Version};
+use crate::event::{ScanEvent, ScanObserver};
+use crate::filter::FileFilter;
+use crate::glob::Glob;
+use crate::hooks::ClientHooks;
+use crate::limit::{ConcurrencyLimiter, ConcurrencyPermit, RateLimiter};
+use crate::memory::MemoryTransport;
+#[cfg(feature = "metrics")]
+use crate::metrics::{ScanMetrics, Verdict};
+use crate::options::{ScanOptions, ScanTarget};
+use crate::pool::{BufferPool, PooledWriter};
+use crate::reconnect::ReconnectPolicy;
+use crate::report::ScanReport;
+use crate::response::{
+    ClamResponse, ReloadAck, ScanItem, ScanOutcome, ScanResult, ScanVerdict, ShutdownAck, Stats,
+    Version,
+};
+use crate::retry::{self, ReloadRetry};
+use crate::session::{BulkScan, ClamSession, KeepAlive, Pending, ScanInput, ScanIter};
+#[cfg(unix)]
+use crate::transport::PeerCredentials;
+use crate::transport::{Connection, Endpoint};
+use crate::writer::ClamScanWriter;
 
 pub type Result<T> = std::result::Result<T, ClamError>;
 
+const FILE_CHUNK_SIZE: usize = 1 << 20;
+pub(crate) const DEFAULT_CHUNK_SIZE: usize = 4096;
+// large enough to send most small objects in a single frame
+const BULK_CHUNK_SIZE: usize = 256 * 1024;
+// room for the INSTREAM command and the frame lengths around one chunk
+const BULK_WRITE_OVERHEAD: usize = 32;
+pub(crate) const REPLY_POLL_INTERVAL: usize = 64 * 1024;
+// RFC 8305 recommends 250ms between connection attempts
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+// bounds raced attempts of a client without a timeout, so a losing attempt
+// to an address that drops packets doesn't run until the OS gives up
+const RACE_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// how often a cut off reply to an idempotent command is asked for again
+const TRUNCATED_RETRIES: u32 = 2;
+
+// the endpoint connected to, or the last error if there was any endpoint
+type Opened = std::result::Result<(Endpoint, Connection), Option<io::Error>>;
+
 pub struct ClamClient {
-    socket: SocketAddr,
+    // "host:port" for TCP clients, resolved again once `dns_ttl` expires
+    address: Option<String>,
+    dns_ttl: Option<Duration>,
+    resolved: Mutex<Resolved>,
     timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    // INSTREAM frame size for streams and byte scans
+    chunk_size: usize,
+    framing: Framing,
+    buffers: Arc<BufferPool>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    scan_rate_limiter: Option<Arc<RateLimiter>>,
+    concurrency_limiter: Option<Arc<ConcurrencyLimiter>>,
+    breaker: Option<Arc<CircuitBreaker>>,
+    #[cfg(unix)]
+    peer_credentials: Option<PeerCredentials>,
+    #[cfg(feature = "cache")]
+    cache: Option<Arc<dyn ScanCache>>,
+    #[cfg(feature = "cache")]
+    single_flight: Option<Arc<SingleFlight>>,
+    audit_log: Option<Arc<AuditLog>>,
+    observer: Option<Arc<dyn ScanObserver>>,
+    hooks: Option<Arc<dyn ClientHooks>>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<Arc<ScanMetrics>>,
+    // numbers the scans reported to the observer
+    next_scan: AtomicU64,
+    reload_retry: ReloadRetry,
+    reconnect: Option<ReconnectPolicy>,
+    // set by with_persistent_session, opened on first use
+    keepalive: Option<Mutex<Option<KeepAlive>>>,
+    // kept connections answer PING before they are used
+    warm_up: bool,
+    // kept connections idle for longer are closed instead of reused
+    max_idle: Option<Duration>,
+    test_on_checkout: bool,
+    // fetched on first use by a command that needs a recent daemon, None
+    // inside if the daemon doesn't name its release
+    daemon_release: Mutex<Option<Option<(u64, u64, u64)>>>,
+    counters: Arc<Counters>,
+}
+
+/// What `ClamClient::scan_paths` found, path by path and as a whole.
+#[derive(Debug, Default)]
+pub struct PathScan {
+    pub results: Vec<(PathBuf, Result<Vec<ScanResult>>)>,
+    pub report: ScanReport,
+}
+
+/// A scan being reported to the audit log and the observer.
+pub(crate) struct Tracked {
+    id: u64,
+    input: String,
+    path: Option<PathBuf>,
+    started: Instant,
+    // payload bytes sent so far
+    sent: u64,
+}
+
+struct Resolved {
+    // every address the host resolved to, tried in turn on connect
+    endpoints: Vec<Endpoint>,
+    // the endpoint that last accepted a connection
+    last_good: Option<Endpoint>,
+    at: Instant,
 }
 
 impl ClamClient {
     fn build(h: &str, p: u16, timeout: Option<Duration>) -> Result<Self> {
         let address = format!("{}:{}", h, p);
+        let mut client = Self::with_endpoints(resolve(&address)?, timeout);
+        client.address = Some(address);
+        Ok(client)
+    }
 
-        let socket = match address.to_socket_addrs() {
-            Ok(mut iter) => match iter.next() {
-                Some(socket) => socket,
-                None => {
-                    return Err(ClamError::InvalidData(String::from(
-                        "invalid socket address",
-                    )))
-                }
-            },
-            Err(e) => return Err(ClamError::InvalidIpAddress(e)),
-        };
-
-        Ok(Self { socket, timeout })
+    fn with_endpoints(endpoints: Vec<Endpoint>, timeout: Option<Duration>) -> Self {
+        Self {
+            address: None,
+            dns_ttl: None,
+            resolved: Mutex::new(Resolved {
+                endpoints,
+                last_good: None,
+                at: Instant::now(),
+            }),
+            timeout,
+            read_timeout: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            framing: Framing::default(),
+            buffers: Arc::new(BufferPool::default()),
+            rate_limiter: None,
+            scan_rate_limiter: None,
+            concurrency_limiter: None,
+            breaker: None,
+            #[cfg(unix)]
+            peer_credentials: None,
+            #[cfg(feature = "cache")]
+            cache: None,
+            #[cfg(feature = "cache")]
+            single_flight: None,
+            audit_log: None,
+            observer: None,
+            hooks: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            next_scan: AtomicU64::new(1),
+            reload_retry: ReloadRetry::default(),
+            reconnect: None,
+            keepalive: None,
+            warm_up: false,
+            max_idle: None,
+            test_on_checkout: false,
+            daemon_release: Mutex::new(None),
+            counters: Arc::new(Counters::default()),
+        }
     }
 
     pub fn new(h: &str, p: u16) -> Result<Self> {
@@ -40,197 +186,3092 @@ impl ClamClient {
         Self::build(h, p, Some(Duration::from_secs(t)))
     }
 
+    /// Talks to clamd over the Unix socket at `path` (clamd's `LocalSocket`).
+    #[cfg(unix)]
+    pub fn new_unix<P: AsRef<Path>>(path: P) -> Self {
+        Self::with_endpoints(vec![Endpoint::Unix(path.as_ref().to_path_buf())], None)
+    }
+
+    /// Like `new_unix`, failing replies that take longer than `t` seconds
+    /// with `ClamError::Timeout`. Unix sockets connect at once or not at all,
+    /// so there is no connect timeout to apply.
+    #[cfg(unix)]
+    pub fn new_unix_with_timeout<P: AsRef<Path>>(path: P, t: u64) -> Self {
+        Self::new_unix(path).with_read_timeout(Duration::from_secs(t))
+    }
+
+    /// Talks to clamd over a named pipe such as `\\.\pipe\clamd`.
+    #[cfg(windows)]
+    pub fn new_named_pipe<P: AsRef<Path>>(path: P) -> Self {
+        Self::with_endpoints(vec![Endpoint::Pipe(path.as_ref().to_path_buf())], None)
+    }
+
+    /// Like `new_named_pipe`, waiting up to `t` seconds for a free instance
+    /// of the pipe when all are busy. Reads from pipes can't time out.
+    #[cfg(windows)]
+    pub fn new_named_pipe_with_timeout<P: AsRef<Path>>(path: P, t: u64) -> Self {
+        let timeout = Duration::from_secs(t);
+        Self::with_endpoints(
+            vec![Endpoint::Pipe(path.as_ref().to_path_buf())],
+            Some(timeout),
+        )
+    }
+
+    /// Talks to the scripted daemon of `transport` instead of a socket.
+    pub fn new_memory(transport: MemoryTransport) -> Self {
+        Self::with_endpoints(vec![Endpoint::Memory(transport)], None)
+    }
+
+    /// The endpoint that last accepted a connection, or the first one the
+    /// host resolved to if none has yet.
+    pub fn endpoint(&self) -> Endpoint {
+        let resolved = self.resolved();
+        match &resolved.last_good {
+            Some(endpoint) => endpoint.clone(),
+            None => resolved.endpoints[0].clone(),
+        }
+    }
+
+    /// Resolves the host again on connect once the previous lookup is older
+    /// than `ttl`, so a long-lived client follows the daemon when its DNS
+    /// record changes. `Duration::ZERO` resolves on every connect. Without
+    /// this, the host is only resolved when the client is created. If a
+    /// lookup fails, the previous addresses are kept.
+    pub fn with_dns_ttl(mut self, ttl: Duration) -> Self {
+        self.dns_ttl = Some(ttl);
+        self
+    }
+
+    /// Fails reads of daemon replies that take longer than `timeout` with
+    /// `ClamError::Timeout`, instead of waiting on a wedged daemon forever.
+    /// `AsyncClamClient::scan_async_read` applies it to every write of the
+    /// upload and to the reply, which needs the runtime's time driver.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Sends streams and byte scans in INSTREAM frames of up to `size`
+    /// bytes instead of 4 KiB. Files are sent in 1 MiB frames regardless.
+    /// Sizes are clamped to what a frame can hold; `ClientConfig::validate`
+    /// rejects them instead.
+    pub fn with_chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.clamp(1, u32::MAX as usize);
+        self
+    }
+
+    /// Sends commands in the form of `framing` and reads replies
+    /// accordingly, `Framing::NulTerminated` by default. STATS replies span
+    /// several lines, so with `NewlineTerminated` they are never read over
+    /// the persistent session.
+    pub fn with_framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Tunes the client for throughput over many small objects, as in
+    /// backfill jobs using `scan_bulk`. Streams and byte scans go in 256 KiB
+    /// frames, and each upload is written through a pooled buffer of that
+    /// size, so an object below it costs a single write of command, frame
+    /// and terminator. Settings made after this one take precedence.
+    pub fn with_bulk_profile(self) -> Self {
+        let pool = BufferPool::new(BULK_CHUNK_SIZE + BULK_WRITE_OVERHEAD, 64);
+        self.with_chunk_size(BULK_CHUNK_SIZE)
+            .with_buffer_pool(Arc::new(pool))
+    }
+
+    /// Uses `pool` for this client's scan buffers, e.g. to share one pool
+    /// between several clients.
+    pub fn with_buffer_pool(mut self, pool: Arc<BufferPool>) -> Self {
+        self.buffers = pool;
+        self
+    }
+
+    /// Caps the INSTREAM upload rate of this client at whatever the limiter
+    /// allows, in bytes per second. Share one limiter between clients to cap
+    /// their combined bandwidth.
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Caps how many scans this client starts per second, each taking one
+    /// unit from the limiter, e.g. `RateLimiter::new(50)` for 50 scans per
+    /// second. Scans over the rate wait for their turn; every scan in a
+    /// session counts.
+    pub fn with_scan_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.scan_rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Limits how many scans this client runs against the daemon at once.
+    pub fn with_concurrency_limiter(mut self, limiter: Arc<ConcurrencyLimiter>) -> Self {
+        self.concurrency_limiter = Some(limiter);
+        self
+    }
+
+    /// Fails fast with `CircuitOpen` while `breaker` considers the daemon
+    /// down. Share one breaker between clients of the same daemon.
+    pub fn with_circuit_breaker(mut self, breaker: Arc<CircuitBreaker>) -> Self {
+        self.breaker = Some(breaker);
+        self
+    }
+
+    /// Refuses to talk over a Unix socket unless the process serving it
+    /// runs as `credentials` say, so a process that took over the socket
+    /// path never sees scanned data. A mismatch fails with `UntrustedPeer`
+    /// before anything is sent. Other transports aren't checked.
+    #[cfg(unix)]
+    pub fn with_peer_credentials(mut self, credentials: PeerCredentials) -> Self {
+        self.peer_credentials = Some(credentials);
+        self
+    }
+
+    /// Sends PING, VERSION and STATS over one IDSESSION connection kept
+    /// open between calls instead of connecting for each. If clamd has
+    /// closed the idle session, the command is retried on a new one.
+    pub fn with_persistent_session(mut self) -> Self {
+        self.keepalive = Some(Mutex::new(None));
+        self
+    }
+
+    /// Checks each connection the client keeps open, the persistent session
+    /// and the sessions of `scan_iter`, with a PING when it is opened or
+    /// revived. One that doesn't answer is dropped and opened once more, so
+    /// the first command after an idle period doesn't fail on a half-closed
+    /// socket.
+    pub fn with_warm_up_ping(mut self) -> Self {
+        self.warm_up = true;
+        self
+    }
+
+    /// Closes kept connections that went unused for longer than `max_idle`
+    /// instead of reusing them, as clamd drops sessions idle past its
+    /// IdleTimeout (30 seconds by default) without telling the client.
+    pub fn with_max_idle(mut self, max_idle: Duration) -> Self {
+        self.max_idle = Some(max_idle);
+        self
+    }
+
+    /// Sends a PING over a kept connection each time before reusing it, and
+    /// opens a new one if it isn't answered.
+    pub fn with_test_on_checkout(mut self) -> Self {
+        self.test_on_checkout = true;
+        self
+    }
+
+    /// Reopens kept connections after a daemon restart as `policy` says,
+    /// instead of failing the command that found the daemon gone.
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// Remembers verdicts of `scan_bytes`, `scan_string` and
+    /// `scan_bytes_labeled`, their cancellable and async variants included,
+    /// by content hash, so identical payloads are only sent to the daemon
+    /// once. `scan_bytes_outcome` always scans, since it reports the
+    /// transfer, and so do the file scans, `scan_file_mmap` included. The
+    /// cache is cleared when the client reloads the signature database.
+    #[cfg(feature = "cache")]
+    pub fn with_scan_cache(mut self, cache: Arc<dyn ScanCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Coalesces concurrent `scan_bytes`, `scan_string` and
+    /// `scan_bytes_labeled` calls for identical content, cancellable and
+    /// async ones included, so only one INSTREAM per payload reaches the
+    /// daemon. Like the cache, it leaves out `scan_bytes_outcome` and the
+    /// file scans.
+    #[cfg(feature = "cache")]
+    pub fn with_single_flight(mut self, group: Arc<SingleFlight>) -> Self {
+        self.single_flight = Some(group);
+        self
+    }
+
+    /// Records every streamed scan in `log`, including those made in
+    /// sessions, by `scan_bulk` and by `AsyncClamClient::scan_async_read`;
+    /// submitted streams are recorded once their verdict is waited for.
+    /// Files are recorded by path, everything else as `stream`. A scan whose
+    /// record can't be written fails with `AuditError`. Scans through
+    /// `scan_writer` aren't recorded.
+    pub fn with_audit_log(mut self, log: Arc<AuditLog>) -> Self {
+        self.audit_log = Some(log);
+        self
+    }
+
+    /// Sends the events of every scan made with the streaming methods,
+    /// including those in sessions, and of failed connection attempts to
+    /// `observer`. A `Sender<ScanEvent>` forwards them to a channel.
+    pub fn with_observer(mut self, observer: Arc<dyn ScanObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
+    /// Runs `hooks` around every connection and command of the client.
+    pub fn with_hooks(mut self, hooks: Arc<dyn ClientHooks>) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    /// Records the latency of every tracked scan, see `with_observer`, in
+    /// `metrics`.
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics(mut self, metrics: Arc<ScanMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Replaces how commands answered with `RELOADING` are retried, see
+    /// `ReloadRetry`; `ReloadRetry::disabled()` surfaces the reply instead.
+    pub fn with_reload_retry(mut self, retry: ReloadRetry) -> Self {
+        self.reload_retry = retry;
+        self
+    }
+
     pub fn ping(&self) -> bool {
-        match self.command(b"zPING\0") {
-            Ok(resp) => resp == "PONG",
+        match self.command_typed::<String>(&Command::Ping) {
+            Ok(resp) => resp.trim_end_matches('\0') == "PONG",
             Err(_) => false,
         }
     }
 
+    /// Polls PING every 100ms until the daemon answers, failing with a
+    /// `TimedOut` connection error after `timeout`. Each poll uses a new
+    /// connection.
+    pub fn wait_until_ready(&self, timeout: Duration) -> Result<()> {
+        let started = Instant::now();
+
+        loop {
+            let reply = self.send(&Command::Ping).and_then(|mut s| {
+                let mut r = Vec::new();
+                match s.read_to_end(&mut r) {
+                    Ok(n) => {
+                        self.counters.received(n as u64);
+                        self.framing.normalize(&Command::Ping, &mut r);
+                        Ok(r)
+                    }
+                    Err(e) => Err(read_failed(e, ClamError::CommandError)),
+                }
+            });
+            // not `ping`, which may go through the persistent session
+            if let Ok(reply) = reply {
+                if String::from_utf8_lossy(&reply).trim_end_matches('\0') == "PONG" {
+                    return Ok(());
+                }
+            }
+
+            if started.elapsed() >= timeout {
+                return Err(ClamError::ConnectionError(io::Error::new(
+                    ErrorKind::TimedOut,
+                    "daemon did not become ready",
+                )));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
     pub fn version(&self) -> Result<Version> {
-        let resp = self.command(b"zVERSION\0")?;
-        Version::parse(&resp)
+        self.command_typed(&Command::Version)
+    }
+
+    /// Asks clamd to reload its signature database, failing with
+    /// `UnexpectedReply` unless the daemon confirms it is reloading.
+    pub fn reload(&self) -> Result<ReloadAck> {
+        let resp = self.command_typed(&Command::Reload)?;
+
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+
+        Ok(resp)
     }
 
-    pub fn reload(&self) -> Result<String> {
-        self.command(b"zRELOAD\0")
+    /// Scans `target` and returns one result per scanned file; streamed
+    /// targets yield a single result. `options` select the path scan
+    /// command and are ignored for streamed targets.
+    ///
+    /// ```no_run
+    /// use clamav::{ClamClient, ScanOptions, ScanTarget};
+    ///
+    /// let client = ClamClient::new("127.0.0.1", 3310).unwrap();
+    /// let options = ScanOptions {
+    ///     parallel: true,
+    ///     ..Default::default()
+    /// };
+    /// let results = client.scan(ScanTarget::path("/srv/uploads"), options).unwrap();
+    /// ```
+    pub fn scan<'a, T: Into<ScanTarget<'a>>>(
+        &self,
+        target: T,
+        options: ScanOptions,
+    ) -> Result<Vec<ScanResult>> {
+        match target.into() {
+            ScanTarget::Path(path) => {
+                let command = options.path_command(path)?;
+                let _permit = self.scan_permit()?;
+                self.command_typed(&command)
+            }
+            ScanTarget::Bytes(b) => self.scan_bytes(b).map(|r| vec![r]),
+            ScanTarget::Reader(r) => self.scan_stream(r).map(|r| vec![r]),
+        }
     }
 
     pub fn scan_path(&self, path: &str, continue_on_virus: bool) -> Result<Vec<ScanResult>> {
-        let result = if continue_on_virus {
-            self.command(&format!("zCONTSCAN {}\0", path).into_bytes())?
-        } else {
-            self.command(&format!("zSCAN {}\0", path).into_bytes())?
+        let options = ScanOptions {
+            continue_on_virus,
+            ..Default::default()
         };
-
-        Ok(ScanResult::parse(result))
+        self.scan(ScanTarget::Path(path), options)
     }
 
     pub fn multiscan_path(&self, path: &str) -> Result<Vec<ScanResult>> {
-        let result = self.command(&format!("zSCAN {}\0", path).into_bytes())?;
-        Ok(ScanResult::parse(result))
+        let options = ScanOptions {
+            parallel: true,
+            ..Default::default()
+        };
+        self.scan(ScanTarget::Path(path), options)
     }
 
-    pub fn scan_stream<T: Read>(&self, s: T) -> Result<ScanResult> {
-        let mut reader = BufReader::new(s);
-        let mut buffer = [0; 4096];
-        let mut length_buffer = [0; 4];
-        let mut connection = self.connect()?;
+    /// Has the daemon scan every path in turn and returns the results of
+    /// each together with a report of them all. Plain and CONTSCAN scans of
+    /// regular files share one IDSESSION connection; directories and the
+    /// other scan commands, whose replies may span several lines, get a
+    /// connection each.
+    pub fn scan_paths<I>(&self, paths: I, options: ScanOptions) -> PathScan
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let mut scan = PathScan::default();
+        let mut session = None;
 
-        self.connection_write(&connection, b"zINSTREAM\0")?;
+        for path in paths {
+            let path = path.as_ref().to_path_buf();
+            let label = path.to_string_lossy();
+            let started = Instant::now();
 
-        while let Ok(bytes_read) = reader.read(&mut buffer) {
-            if bytes_read > std::u32::MAX as usize {
-                return Err(ClamError::InvalidDataLength(bytes_read));
+            let result = match options.path_command(&label) {
+                Ok(command @ (Command::Scan(_) | Command::ContScan(_))) if path.is_file() => {
+                    self.session_path_scan(&mut session, &command)
+                }
+                Ok(_) => self.scan(ScanTarget::Path(&label), options),
+                Err(e) => Err(e),
+            };
+
+            let report = &mut scan.report;
+            report.timings.push((path.clone(), started.elapsed()));
+            match &result {
+                Ok(results) => report.results.extend(results.iter().map(|result| {
+                    let file = match result {
+                        ScanResult::Found(file, ..) => PathBuf::from(file),
+                        _ => path.clone(),
+                    };
+                    (file, result.clone())
+                })),
+                Err(e) => report.errors.push((path.clone(), e.to_string())),
             }
+            scan.results.push((path, result));
+        }
 
-            BigEndian::write_u32(&mut length_buffer, bytes_read as u32);
+        scan
+    }
 
-            self.connection_write(&connection, &length_buffer)?;
-            self.connection_write(&connection, &buffer)?;
+    fn session_path_scan<'a>(
+        &'a self,
+        session: &mut Option<ClamSession<'a>>,
+        command: &Command,
+    ) -> Result<Vec<ScanResult>> {
+        let open = match session {
+            Some(open) => open,
+            None => session.insert(self.session()?),
+        };
 
-            if bytes_read < 4096 {
-                break;
+        self.pace_scan();
+        let result = open
+            .submit::<Vec<ScanResult>>(command)
+            .and_then(|pending| open.wait(pending));
+        if result.is_err() {
+            // the session's framing can't be trusted anymore
+            *session = None;
+        }
+        result
+    }
+
+    /// Like `scan`, with every result naming what it is about: the file
+    /// path the daemon reported, or `stream` for streamed targets.
+    pub fn scan_items<'a, T: Into<ScanTarget<'a>>>(
+        &self,
+        target: T,
+        options: ScanOptions,
+    ) -> Result<Vec<ScanItem>> {
+        match target.into() {
+            ScanTarget::Path(path) => {
+                let reply = self.path_reply(path, options)?;
+                Ok(ScanItem::parse_reply(&reply, path))
             }
+            target => Ok(self
+                .scan(target, options)?
+                .into_iter()
+                .map(|result| ScanItem::from_result("stream", result))
+                .collect()),
         }
+    }
 
-        self.connection_write(&connection, &[0, 0, 0, 0])?;
+    /// Like `scan`ning a path, with the verdicts keyed by the path each
+    /// reply line names. Clean files below a scanned directory aren't listed
+    /// individually: clamd only reports the directory as clean.
+    pub fn scan_path_map(
+        &self,
+        path: &str,
+        options: ScanOptions,
+    ) -> Result<BTreeMap<PathBuf, ScanVerdict>> {
+        let reply = self.path_reply(path, options)?;
+        Ok(ScanVerdict::parse_reply(&reply, path))
+    }
 
-        let mut result = String::new();
-        match connection.read_to_string(&mut result) {
-            Ok(_) => {
-                let scan_result = ScanResult::parse(&result);
+    fn path_reply(&self, path: &str, options: ScanOptions) -> Result<String> {
+        let command = options.path_command(path)?;
+        let _permit = self.scan_permit()?;
+        let raw = self.command(&command)?;
+        Ok(String::from_utf8_lossy(&raw).into_owned())
+    }
 
-                if let Some(singular) = scan_result.first() {
-                    Ok(singular.clone())
-                } else {
-                    Err(ClamError::InvalidData(result))
-                }
+    pub fn scan_stream<T: Read>(&self, s: T) -> Result<ScanResult> {
+        self.scan_stream_outcome(s).map(|o| o.result)
+    }
+
+    /// Like `scan_stream`, additionally reporting how much was sent, how long
+    /// the scan took and which daemon handled it.
+    pub fn scan_stream_outcome<T: Read>(&self, s: T) -> Result<ScanOutcome> {
+        self.stream_outcome(s, None, "stream")
+    }
+
+    /// Like `scan_stream`, aborting with `ClamError::Cancelled` as soon as
+    /// `token` is cancelled.
+    pub fn scan_stream_cancellable<T: Read>(
+        &self,
+        s: T,
+        token: &CancelToken,
+    ) -> Result<ScanResult> {
+        self.stream_outcome(s, Some(token), "stream")
+            .map(|o| o.result)
+    }
+
+    /// Like `scan_stream`, with the scan recorded as `label` in events and
+    /// the audit log instead of `stream`, and the result naming it as its
+    /// subject. Use it to trace verdicts back to e.g. an upload ID.
+    pub fn scan_stream_labeled<T: Read>(&self, s: T, label: &str) -> Result<ScanItem> {
+        let outcome = self.stream_outcome(s, None, label)?;
+        Ok(labeled(label, outcome.result))
+    }
+
+    /// Like `scan_stream`, for readers of unknown size: at most `cap` bytes
+    /// are sent, and if the reader holds more the stream is ended there and
+    /// the scan fails with `ClamError::SizeCapExceeded`, whatever the daemon
+    /// made of the part it got.
+    pub fn scan_stream_capped<T: Read>(&self, s: T, cap: u64) -> Result<ScanResult> {
+        let mut tracked = self.track("stream", None);
+        let mut capped = Capped {
+            inner: s,
+            remaining: cap,
+            exceeded: false,
+        };
+
+        let outcome = match self.send_stream(&mut capped, None, &mut tracked) {
+            Ok(_) if capped.exceeded => Err(ClamError::SizeCapExceeded(cap)),
+            outcome => outcome,
+        };
+        self.finish_outcome(tracked, outcome).map(|o| o.result)
+    }
+
+    /// Like `scan_stream`, with this scan's upload capped at whatever
+    /// `limiter` allows, in bytes per second, on top of any client-wide
+    /// `with_rate_limiter`. Use it to throttle e.g. background rescans while
+    /// interactive scans on the same client run at full speed.
+    pub fn scan_stream_limited<T: Read>(&self, s: T, limiter: &RateLimiter) -> Result<ScanResult> {
+        let limited = Limited { inner: s, limiter };
+        self.stream_outcome(limited, None, "stream")
+            .map(|o| o.result)
+    }
+
+    fn stream_outcome<T: Read>(
+        &self,
+        s: T,
+        cancel: Option<&CancelToken>,
+        label: &str,
+    ) -> Result<ScanOutcome> {
+        let mut tracked = self.track(label, None);
+        let outcome = self.send_stream(s, cancel, &mut tracked);
+        self.finish_outcome(tracked, outcome)
+    }
+
+    fn send_stream<T: Read>(
+        &self,
+        mut s: T,
+        cancel: Option<&CancelToken>,
+        tracked: &mut Tracked,
+    ) -> Result<ScanOutcome> {
+        let _permit = self.scan_permit()?;
+        let started = Instant::now();
+        let mut buffer = BufferPool::get(&self.buffers);
+        buffer.resize(self.chunk_size, 0);
+        let connection = self.connect()?;
+        let _registration = self.register_cancel(cancel, &connection)?;
+        let mut writer = PooledWriter::new(&connection, BufferPool::get(&self.buffers));
+
+        self.command_write(&mut writer, &Command::Instream)?;
+
+        loop {
+            check_cancel(cancel)?;
+            let bytes_read = match s.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(ClamError::StreamError(e)),
+            };
+
+            if bytes_read > u32::MAX as usize {
+                return Err(ClamError::InvalidDataLength(bytes_read));
+            }
+
+            if !self.stream_frame(&connection, &mut writer, &buffer[..bytes_read], tracked)? {
+                break;
             }
-            Err(e) => Err(ClamError::ConnectionError(e)),
         }
+
+        let result = self.instream_result(&connection, writer);
+        check_cancel(cancel)?;
+        Ok(self.outcome(result?, tracked.sent, started))
     }
 
     pub fn scan_string(&self, str: &str) -> Result<ScanResult> {
-        self.scan_bytes(str.as_bytes().to_vec())
+        self.scan_bytes(str)
+    }
+
+    pub fn scan_bytes<B: AsRef<[u8]>>(&self, b: B) -> Result<ScanResult> {
+        #[cfg(feature = "cache")]
+        if self.cache.is_some() || self.single_flight.is_some() {
+            return self.scan_bytes_deduplicated(b.as_ref(), None, "stream");
+        }
+
+        self.scan_chunks(b.as_ref().chunks(self.chunk_size))
     }
 
-    pub fn scan_bytes(&self, b: Vec<u8>) -> Result<ScanResult> {
-        let mut connection = self.connect()?;
-        self.connection_write(&connection, b"zINSTREAM\0")?;
+    #[cfg(feature = "cache")]
+    fn scan_bytes_deduplicated(
+        &self,
+        b: &[u8],
+        cancel: Option<&CancelToken>,
+        label: &str,
+    ) -> Result<ScanResult> {
+        let hash = cache::content_hash(b);
 
-        let buffer = b.chunks(4096);
-        for chunks in buffer {
-            let len = chunks.len();
-            self.connection_write(&connection, &(len as u32).to_be_bytes())?;
-            self.connection_write(&connection, chunks)?;
+        if let Some(cache) = &self.cache {
+            if let Some(result) = cache.get(&hash) {
+                return Ok(result);
+            }
         }
-        self.connection_write(&connection, &[0; 4])?;
 
-        let mut result = String::new();
-        match connection.read_to_string(&mut result) {
-            Ok(_) => {
-                let scan_result = ScanResult::parse(&result);
+        let scan = || {
+            self.chunks_outcome(b.chunks(self.chunk_size), cancel, label)
+                .map(|o| o.result)
+        };
+        let result = match &self.single_flight {
+            Some(group) => group.run(hash, scan)?,
+            None => scan()?,
+        };
 
-                if let Some(singular) = scan_result.first() {
-                    Ok(singular.clone())
-                } else {
-                    Err(ClamError::InvalidData(result))
-                }
+        if let Some(cache) = &self.cache {
+            if let ScanResult::Ok | ScanResult::Found(..) = result {
+                cache.insert(hash, result.clone());
             }
-            Err(e) => Err(ClamError::ConnectionError(e)),
         }
+
+        Ok(result)
+    }
+
+    /// Scans every payload over one IDSESSION connection and returns the
+    /// results in the same order, saving a connection per payload. Fails as
+    /// a whole if any scan does; see `scan_iter` to carry on past failures.
+    pub fn scan_bytes_batch(&self, batch: &[&[u8]]) -> Result<Vec<ScanResult>> {
+        if batch.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut session = self.session()?;
+        batch.iter().map(|b| session.scan_bytes(b)).collect()
+    }
+
+    /// Streams the readers over up to `concurrency` connections at once and
+    /// returns their results in input order. Every worker keeps a session
+    /// open like `scan_iter` does, so a failed scan fails only its own item.
+    pub fn scan_streams<I, R>(&self, streams: I, concurrency: usize) -> Vec<Result<ScanResult>>
+    where
+        I: IntoIterator<Item = R>,
+        I::IntoIter: Send,
+        R: Read + Send,
+    {
+        let streams = Mutex::new(streams.into_iter().enumerate());
+
+        let mut results = thread::scope(|scope| {
+            let workers = (0..concurrency.max(1))
+                .map(|_| {
+                    let streams = SharedStreams(&streams);
+                    scope.spawn(move || {
+                        self.scan_iter(streams)
+                            .map(|(stream, result)| (stream.index, result))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| match worker.join() {
+                    Ok(results) => results,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                })
+                .collect::<Vec<_>>()
+        });
+
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    pub fn scan_bytes_outcome<B: AsRef<[u8]>>(&self, b: B) -> Result<ScanOutcome> {
+        self.scan_chunks_outcome(b.as_ref().chunks(self.chunk_size))
     }
 
     pub fn scan_chunks(&self, chunks: std::slice::Chunks<u8>) -> Result<ScanResult> {
-        let mut connection = self.connect()?;
-        self.connection_write(&connection, b"zINSTREAM\0")?;
+        self.scan_chunks_outcome(chunks).map(|o| o.result)
+    }
+
+    pub fn scan_chunks_outcome(&self, chunks: std::slice::Chunks<u8>) -> Result<ScanOutcome> {
+        self.chunks_outcome(chunks, None, "stream")
+    }
+
+    /// Like `scan_bytes`, aborting with `ClamError::Cancelled` as soon as
+    /// `token` is cancelled.
+    pub fn scan_bytes_cancellable<B: AsRef<[u8]>>(
+        &self,
+        b: B,
+        token: &CancelToken,
+    ) -> Result<ScanResult> {
+        #[cfg(feature = "cache")]
+        if self.cache.is_some() || self.single_flight.is_some() {
+            return self.scan_bytes_deduplicated(b.as_ref(), Some(token), "stream");
+        }
+
+        self.chunks_outcome(b.as_ref().chunks(self.chunk_size), Some(token), "stream")
+            .map(|o| o.result)
+    }
+
+    /// Like `scan_bytes`, recording the scan as `label`, see
+    /// `scan_stream_labeled`.
+    pub fn scan_bytes_labeled<B: AsRef<[u8]>>(&self, b: B, label: &str) -> Result<ScanItem> {
+        #[cfg(feature = "cache")]
+        if self.cache.is_some() || self.single_flight.is_some() {
+            let result = self.scan_bytes_deduplicated(b.as_ref(), None, label)?;
+            return Ok(labeled(label, result));
+        }
+
+        let outcome = self.chunks_outcome(b.as_ref().chunks(self.chunk_size), None, label)?;
+        Ok(labeled(label, outcome.result))
+    }
+
+    fn chunks_outcome(
+        &self,
+        chunks: std::slice::Chunks<u8>,
+        cancel: Option<&CancelToken>,
+        label: &str,
+    ) -> Result<ScanOutcome> {
+        let mut tracked = self.track(label, None);
+        let outcome = self.retry_scan(&mut tracked, |tracked| {
+            self.send_chunks(chunks.clone(), cancel, tracked)
+        });
+        self.finish_outcome(tracked, outcome)
+    }
+
+    fn send_chunks(
+        &self,
+        chunks: std::slice::Chunks<u8>,
+        cancel: Option<&CancelToken>,
+        tracked: &mut Tracked,
+    ) -> Result<ScanOutcome> {
+        let _permit = self.scan_permit()?;
+        let started = Instant::now();
+        let connection = self.connect()?;
+        let _registration = self.register_cancel(cancel, &connection)?;
+        let mut writer = PooledWriter::new(&connection, BufferPool::get(&self.buffers));
+        self.command_write(&mut writer, &Command::Instream)?;
 
         for chunk in chunks {
-            let len = chunk.len();
-            self.connection_write(&connection, &(len as u32).to_be_bytes())?;
-            self.connection_write(&connection, chunk)?;
+            check_cancel(cancel)?;
+            if !self.stream_frame(&connection, &mut writer, chunk, tracked)? {
+                break;
+            }
         }
-        self.connection_write(&connection, &[0; 4])?;
 
-        let mut result = String::new();
-        match connection.read_to_string(&mut result) {
-            Ok(_) => {
-                let scan_result = ScanResult::parse(&result);
+        let result = self.instream_result(&connection, writer);
+        check_cancel(cancel)?;
+        Ok(self.outcome(result?, tracked.sent, started))
+    }
+
+    /// Streams the file at `path`. Only the length prefixes pass through user
+    /// space: the payload is copied with `io::copy`, which on Linux moves file
+    /// data into the socket with `sendfile`/`splice`.
+    pub fn scan_file<P: AsRef<Path>>(&self, path: P) -> Result<ScanResult> {
+        self.scan_file_outcome(path).map(|o| o.result)
+    }
+
+    pub fn scan_file_outcome<P: AsRef<Path>>(&self, path: P) -> Result<ScanOutcome> {
+        let path = path.as_ref();
+        let mut tracked = self.track(&path.to_string_lossy(), Some(path));
+        let outcome = match File::open(path) {
+            Ok(file) => self.retry_scan(&mut tracked, |tracked| self.send_file(&file, tracked)),
+            Err(e) => Err(ClamError::StreamError(e)),
+        };
+        self.finish_outcome(tracked, outcome)
+    }
+
+    /// Streams all of an already opened file, recorded in the audit log as
+    /// `label`. Acting on the verdict through the same handle, see
+    /// `policy::Action`, guarantees the action hits the file that was
+    /// scanned.
+    pub fn scan_open_file<P: AsRef<Path>>(&self, file: &File, label: P) -> Result<ScanResult> {
+        let label = label.as_ref();
+        let mut tracked = self.track(&label.to_string_lossy(), Some(label));
+        let outcome = self.retry_scan(&mut tracked, |tracked| self.send_file(file, tracked));
+        self.finish_outcome(tracked, outcome).map(|o| o.result)
+    }
+
+    fn send_file(&self, mut file: &File, tracked: &mut Tracked) -> Result<ScanOutcome> {
+        let _permit = self.scan_permit()?;
+        let started = Instant::now();
+        if let Err(e) = file.seek(SeekFrom::Start(0)) {
+            return Err(ClamError::StreamError(e));
+        }
 
-                if let Some(singular) = scan_result.first() {
-                    Ok(singular.clone())
-                } else {
-                    Err(ClamError::InvalidData(result))
+        let mut remaining = match file.metadata() {
+            Ok(m) => m.len(),
+            Err(e) => return Err(ClamError::StreamError(e)),
+        };
+
+        let connection = self.connect()?;
+        self.command_write(&connection, &Command::Instream)?;
+
+        while remaining > 0 {
+            let chunk = remaining.min(FILE_CHUNK_SIZE as u64);
+
+            if let Err(e) = self.file_frame_write(&connection, file, chunk) {
+                if daemon_replied(&connection) {
+                    break;
                 }
+                return Err(e);
+            }
+            remaining -= chunk;
+            self.chunk_sent(tracked, chunk);
+
+            if daemon_replied(&connection) {
+                break;
             }
-            Err(e) => Err(ClamError::ConnectionError(e)),
         }
+
+        let result = self.instream_result(&connection, &connection)?;
+        Ok(self.outcome(result, tracked.sent, started))
     }
 
-    pub fn stats(&self) -> Result<Stats> {
-        let resp: String = self.command(b"zSTATS\0")?;
-        Stats::parse(&resp)
+    /// Maps the file at `path` into memory and streams it without copying it
+    /// through intermediate buffers. Frames are large enough to bypass the
+    /// write buffer, so the mapped pages are handed to the socket directly.
+    /// Like `scan_file`, the scan is recorded by path.
+    #[cfg(feature = "mmap")]
+    pub fn scan_file_mmap<P: AsRef<Path>>(&self, path: P) -> Result<ScanResult> {
+        let path = path.as_ref();
+        let mut tracked = self.track(&path.to_string_lossy(), Some(path));
+        let outcome = match map_file(path) {
+            Ok(map) => {
+                let data = map.as_deref().unwrap_or_default();
+                self.retry_scan(&mut tracked, |tracked| {
+                    self.send_chunks(data.chunks(FILE_CHUNK_SIZE), None, tracked)
+                })
+            }
+            Err(e) => Err(ClamError::StreamError(e)),
+        };
+        self.finish_outcome(tracked, outcome).map(|o| o.result)
     }
 
-    pub fn shutdown(self) -> Result<String> {
-        self.command(b"zSHUTDOWN\0")
+    pub fn scan_writer(&self) -> Result<ClamScanWriter> {
+        let permit = self.scan_permit()?;
+        let connection = self.connect()?;
+        self.command_write(&connection, &Command::Instream)?;
+        Ok(ClamScanWriter::new(
+            connection,
+            &self.buffers,
+            &self.counters,
+            self.chunk_size,
+            self.framing,
+            self.rate_limiter.clone(),
+            permit,
+        ))
     }
 
-    fn command(&self, c: &[u8]) -> Result<String> {
-        let mut s = self.connect()?;
+    /// Opens an IDSESSION connection for sending several commands over one
+    /// socket.
+    pub fn session(&self) -> Result<ClamSession<'_>> {
+        ClamSession::start(self)
+    }
 
-        match s.write_all(c) {
-            Ok(_) => {
-                let mut r = String::new();
-                match s.read_to_string(&mut r) {
-                    Ok(_) => Ok(r),
-                    Err(e) => Err(ClamError::CommandError(e)),
+    /// Lazily scans each input, reusing one session connection for all of
+    /// them, and yields every input together with its verdict.
+    pub fn scan_iter<I>(&self, inputs: I) -> ScanIter<'_, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: ScanInput,
+    {
+        ScanIter::new(self, inputs.into_iter())
+    }
+
+    /// Like `scan_iter`, but keeps several uploads in flight over the
+    /// session instead of waiting for each verdict before sending the next
+    /// input. Verdicts are still yielded in input order. Meant for scanning
+    /// large numbers of small objects, best with `with_bulk_profile`. Each
+    /// scan is audited and observed as finished once its verdict is read.
+    ///
+    /// ```no_run
+    /// use clamav::ClamClient;
+    /// use std::path::PathBuf;
+    ///
+    /// let client = ClamClient::new("127.0.0.1", 3310).unwrap().with_bulk_profile();
+    /// let paths = vec![PathBuf::from("/srv/a"), PathBuf::from("/srv/b")];
+    /// for (path, result) in client.scan_bulk(paths) {
+    ///     println!("{}: {:?}", path.display(), result);
+    /// }
+    /// ```
+    pub fn scan_bulk<I>(&self, inputs: I) -> BulkScan<'_, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: ScanInput,
+    {
+        BulkScan::new(self, inputs.into_iter())
+    }
+
+    /// Streams every regular file below `root`, see `DirScan` for resumable
+    /// scans.
+    pub fn scan_dir<P: AsRef<Path>>(&self, root: P) -> Result<ScanReport> {
+        DirScan::new(self, root).run()
+    }
+
+    /// Expands `pattern` locally, e.g. `artifacts/**/*.zip`, and scans every
+    /// regular file it matches, in path order. `*`, `?` and `[...]` match
+    /// within a path component, `**` any number of components.
+    ///
+    /// Matches are streamed over one session, unless
+    /// `options.shared_filesystem` is set: the daemon then opens them itself
+    /// with the command the other options select, see `scan_paths`. Files
+    /// and directories that can't be read are listed in the report; daemon
+    /// or connection failures while streaming abort the scan.
+    pub fn scan_glob(&self, pattern: &str, options: ScanOptions) -> Result<ScanReport> {
+        self.scan_glob_filtered(pattern, options, FileFilter::default())
+    }
+
+    /// Like `scan_glob`, scanning only the matches `filter` accepts.
+    pub fn scan_glob_filtered(
+        &self,
+        pattern: &str,
+        options: ScanOptions,
+        filter: FileFilter,
+    ) -> Result<ScanReport> {
+        let expansion = Glob::new(pattern)?.expand(filter);
+        let paths = expansion.matches;
+
+        let mut report = if options.shared_filesystem {
+            self.scan_paths(paths, options).report
+        } else {
+            let mut report = ScanReport::default();
+            let mut scans = self.scan_iter(paths);
+            loop {
+                let started = Instant::now();
+                let (path, result) = match scans.next() {
+                    Some(scanned) => scanned,
+                    None => break,
+                };
+
+                report.timings.push((path.clone(), started.elapsed()));
+                match result {
+                    Ok(result) => report.results.push((path, result)),
+                    Err(ClamError::StreamError(e)) => report.errors.push((path, e.to_string())),
+                    Err(e) => return Err(e),
                 }
             }
-            Err(e) => Err(ClamError::CommandError(e)),
+            report
+        };
+
+        report.errors.extend(
+            expansion
+                .errors
+                .into_iter()
+                .map(|(p, e)| (p, e.to_string())),
+        );
+        report.skipped = expansion.skipped;
+        Ok(report)
+    }
+
+    /// Counts of the connections, bytes and commands this client has sent
+    /// and received so far.
+    pub fn stats_snapshot(&self) -> ClientStats {
+        self.counters.snapshot()
+    }
+
+    pub fn stats(&self) -> Result<Stats> {
+        self.command_typed(&Command::Stats)
+    }
+
+    /// Asks clamd to exit. The daemon usually closes the connection without
+    /// replying, possibly resetting it, which counts as acknowledgement.
+    pub fn shutdown(self) -> Result<ShutdownAck> {
+        self.command_started(&Command::Shutdown);
+        let ack = self.shutdown_reply();
+        self.command_ended(&Command::Shutdown, ack.as_ref().err());
+        ack
+    }
+
+    fn shutdown_reply(&self) -> Result<ShutdownAck> {
+        let mut s = self.send(&Command::Shutdown)?;
+        let mut raw = Vec::new();
+
+        match s.read_to_end(&mut raw) {
+            Ok(n) => {
+                self.counters.received(n as u64);
+                <ShutdownAck as ClamResponse>::parse(&raw)
+            }
+            Err(ref e)
+                if e.kind() == ErrorKind::ConnectionReset
+                    || e.kind() == ErrorKind::ConnectionAborted =>
+            {
+                Ok(ShutdownAck)
+            }
+            Err(e) => Err(read_failed(e, ClamError::CommandError)),
         }
     }
 
-    fn connection_write(&self, mut c: &TcpStream, d: &[u8]) -> Result<usize> {
-        match c.write(d) {
-            Ok(a) => Ok(a),
-            Err(e) => Err(ClamError::CommandError(e)),
+    /// Sends `command` and parses the whole reply as `T`, which may be a
+    /// caller-defined `ClamResponse`.
+    pub fn command_typed<T: ClamResponse>(&self, command: &Command) -> Result<T> {
+        let raw = self.command(command)?;
+        T::parse(&raw)
+    }
+
+    /// Sends `c` and returns the raw reply, sending it again while clamd
+    /// answers that it is reloading. RELOAD itself is answered with
+    /// `RELOADING` and is sent only once.
+    fn command(&self, c: &Command) -> Result<Vec<u8>> {
+        if let Command::Reload = c {
+            return self.command_complete(c);
         }
+
+        self.retry_reload(
+            || self.command_complete(c),
+            |reply| retry::reloading_reply(reply),
+        )
     }
 
-    fn connect(&self) -> Result<TcpStream> {
-        let ea = match self.timeout {
-            Some(t) => TcpStream::connect_timeout(&self.socket, t),
-            None => TcpStream::connect(&self.socket),
+    /// Sends an idempotent `c` again on a fresh connection if the reply was
+    /// cut off, as what arrived of it is of no use.
+    fn command_complete(&self, c: &Command) -> Result<Vec<u8>> {
+        let mut retries = if c.is_idempotent() {
+            TRUNCATED_RETRIES
+        } else {
+            0
         };
 
-        match ea {
-            Ok(s) => Ok(s),
-            Err(e) => Err(ClamError::ConnectionError(e)),
+        loop {
+            match self.command_once(c) {
+                Err(ClamError::TruncatedResponse(_)) if retries > 0 => retries -= 1,
+                reply => return reply,
+            }
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    fn command_once(&self, c: &Command) -> Result<Vec<u8>> {
+        self.command_started(c);
+        let reply = self.command_reply(c);
+        self.report_health(reply.as_ref().map(|_| ()));
+        self.command_ended(c, reply.as_ref().err());
+        reply
+    }
 
-    #[test]
-    fn test_client_no_timeout() {
-        let cclient = ClamClient::new("127.0.0.1", 3310).unwrap();
-        let socket_addr =
-            ::std::net::SocketAddr::new(::std::net::IpAddr::from([127, 0, 0, 1]), 3310);
-        assert_eq!(cclient.socket, socket_addr);
-        assert_eq!(cclient.timeout, None);
+    fn command_reply(&self, c: &Command) -> Result<Vec<u8>> {
+        let mut r = match (&self.keepalive, c) {
+            (Some(keepalive), Command::Ping | Command::Version) => {
+                self.keepalive_command(keepalive, c)?
+            }
+            (Some(keepalive), Command::Stats) if self.framing == Framing::NulTerminated => {
+                self.keepalive_command(keepalive, c)?
+            }
+            _ => {
+                let mut s = self.send(c)?;
+                let mut r = Vec::new();
+                match s.read_to_end(&mut r) {
+                    Ok(n) => self.counters.received(n as u64),
+                    Err(e) => return Err(read_failed(e, ClamError::CommandError)),
+                }
+                r
+            }
+        };
+
+        check_terminated(&r, self.framing)?;
+        self.framing.normalize(c, &mut r);
+        Ok(r)
     }
 
-    #[test]
-    fn test_client_with_timeout() {
-        let cclient = ClamClient::new_with_timeout("127.0.0.1", 3310, 60).unwrap();
-        let socket_addr =
-            ::std::net::SocketAddr::new(::std::net::IpAddr::from([127, 0, 0, 1]), 3310);
-        assert_eq!(cclient.socket, socket_addr);
-        assert_eq!(cclient.timeout, Some(::std::time::Duration::from_secs(60)));
+    fn keepalive_command(
+        &self,
+        keepalive: &Mutex<Option<KeepAlive>>,
+        c: &Command,
+    ) -> Result<Vec<u8>> {
+        let mut kept = match keepalive.lock() {
+            Ok(kept) => kept,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(session) = kept.as_mut() {
+            let idle = session.idle();
+            if !self.checkout(session, idle, |s| s.ping(self)) {
+                *kept = None;
+            }
+        }
+
+        let lost = match kept.as_mut() {
+            Some(session) => match session.command(self, c) {
+                Ok(reply) => return Ok(reply),
+                Err(_) => {
+                    *kept = None;
+                    true
+                }
+            },
+            None => false,
+        };
+
+        let open = || self.warmed_up(|| KeepAlive::open(self), |s| s.ping(self));
+        let mut session = if lost { self.reopen(open)? } else { open()? };
+        let reply = session.command(self, c)?;
+        *kept = Some(session);
+        Ok(reply)
+    }
+
+    /// Opens a connection to keep with `open`, checking it with `ping` if
+    /// `with_warm_up_ping` is set. A connection that fails the check is
+    /// dropped for a second one, whose failure is returned.
+    pub(crate) fn warmed_up<T, F, P>(&self, open: F, ping: P) -> Result<T>
+    where
+        F: Fn() -> Result<T>,
+        P: Fn(&mut T) -> Result<()>,
+    {
+        let mut opened = open()?;
+        if self.warm_up && ping(&mut opened).is_err() {
+            drop(opened);
+            opened = open()?;
+            ping(&mut opened)?;
+        }
+        Ok(opened)
+    }
+
+    /// Whether a kept connection, unused for `idle`, may be used again: it
+    /// is within `with_max_idle` and, with `with_test_on_checkout`, answers
+    /// `ping`.
+    pub(crate) fn checkout<T, P>(&self, kept: &mut T, idle: Duration, ping: P) -> bool
+    where
+        P: FnOnce(&mut T) -> Result<()>,
+    {
+        if self.max_idle.is_some_and(|max_idle| idle > max_idle) {
+            return false;
+        }
+        !self.test_on_checkout || ping(kept).is_ok()
+    }
+
+    /// Opens a replacement for a kept connection that died. If the daemon
+    /// refuses it, the reconnect policy treats that as a restart: the
+    /// callback runs, the cached daemon release is dropped, and the
+    /// connection is opened again once the daemon is ready.
+    pub(crate) fn reopen<T, F: Fn() -> Result<T>>(&self, open: F) -> Result<T> {
+        let policy = match &self.reconnect {
+            Some(policy) => policy,
+            None => return open(),
+        };
+        let error = match open() {
+            Err(e @ ClamError::ConnectionError(_)) => e,
+            opened => return opened,
+        };
+
+        if let Some(on_restart) = &policy.on_restart {
+            on_restart(&self.endpoint(), &error);
+        }
+        self.forget_release();
+
+        if let Some(timeout) = policy.ready_timeout {
+            self.wait_until_ready(timeout)?;
+        }
+        open()
+    }
+
+    fn send(&self, c: &Command) -> Result<Connection> {
+        self.check_supported(c)?;
+        let s = self.connect()?;
+        self.command_write(&s, c)?;
+        Ok(s)
+    }
+
+    /// Fails with `UnsupportedByDaemon` if `c` needs a newer daemon than
+    /// the one the client talks to.
+    pub(crate) fn check_supported(&self, c: &Command) -> Result<()> {
+        let needs = match c.since() {
+            Some(needs) => needs,
+            None => return Ok(()),
+        };
+
+        // a daemon that doesn't tell its release gets to answer for itself
+        let have = match self.daemon_release()? {
+            Some(have) => have,
+            None => return Ok(()),
+        };
+        if have >= needs {
+            return Ok(());
+        }
+
+        let release = |(major, minor, patch)| format!("{}.{}.{}", major, minor, patch);
+        Err(ClamError::UnsupportedByDaemon {
+            needs: release(needs),
+            have: release(have),
+        })
+    }
+
+    /// The release of the daemon, or `None` if its VERSION reply doesn't
+    /// name one. Cached until a connection fails or lands on another
+    /// endpoint, as the daemon may have been replaced in between.
+    fn daemon_release(&self) -> Result<Option<(u64, u64, u64)>> {
+        if let Some(release) = *self
+            .daemon_release
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+        {
+            return Ok(release);
+        }
+
+        // not locked while asking, connecting may have to forget the release
+        let release = match self.version() {
+            Ok(version) => version.release(),
+            Err(ClamError::InvalidData(_)) | Err(ClamError::IntParseError(_)) => None,
+            Err(e) => return Err(e),
+        };
+        *self
+            .daemon_release
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(release);
+        Ok(release)
+    }
+
+    fn forget_release(&self) {
+        *self
+            .daemon_release
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    pub(crate) fn command_write<W: Write>(&self, c: W, command: &Command) -> Result<()> {
+        self.connection_write(c, &command.encode_framed(self.framing))?;
+        self.counters.command();
+        Ok(())
+    }
+
+    pub(crate) fn connection_write<W: Write>(&self, mut c: W, d: &[u8]) -> Result<()> {
+        match c.write_all(d) {
+            Ok(_) => {
+                self.counters.sent(d.len() as u64);
+                Ok(())
+            }
+            Err(e) => Err(ClamError::CommandError(e)),
+        }
+    }
+
+    pub(crate) fn frame_write<W: Write>(&self, c: W, chunk: &[u8]) -> Result<()> {
+        match write_frame(c, chunk) {
+            Ok(_) => {
+                self.counters.sent(4 + chunk.len() as u64);
+                Ok(())
+            }
+            Err(e) => Err(ClamError::CommandError(e)),
+        }
+    }
+
+    /// Writes one frame, adding its length to the tracked scan, and every
+    /// `REPLY_POLL_INTERVAL` bytes checks whether clamd has already answered
+    /// (e.g. because the stream exceeded its size limit). Returns false once
+    /// the upload should stop so the reply can be read instead of writing on
+    /// until the socket breaks.
+    fn stream_frame<W: Write>(
+        &self,
+        connection: &Connection,
+        writer: W,
+        chunk: &[u8],
+        tracked: &mut Tracked,
+    ) -> Result<bool> {
+        self.throttle(chunk.len() as u64);
+
+        if let Err(e) = self.frame_write(writer, chunk) {
+            return if daemon_replied(connection) {
+                Ok(false)
+            } else {
+                Err(e)
+            };
+        }
+
+        let before = tracked.sent;
+        self.chunk_sent(tracked, chunk.len() as u64);
+        if before / REPLY_POLL_INTERVAL as u64 != tracked.sent / REPLY_POLL_INTERVAL as u64 {
+            return Ok(!daemon_replied(connection));
+        }
+
+        Ok(true)
+    }
+
+    /// Runs `attempt` until it succeeds with something `reloading` doesn't
+    /// recognise as a reload reply, or the retries run out.
+    fn retry_reload<T, F, R>(&self, mut attempt: F, reloading: R) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+        R: Fn(&T) -> bool,
+    {
+        let mut delays = self.reload_retry.delays();
+        loop {
+            let result = attempt();
+            match (&result, delays.next()) {
+                (Ok(value), Some(delay)) if reloading(value) => thread::sleep(delay),
+                _ => return result,
+            }
+        }
+    }
+
+    /// Retries a streamed scan of replayable input, counting only the bytes
+    /// of the last attempt as sent.
+    fn retry_scan<F>(&self, tracked: &mut Tracked, mut attempt: F) -> Result<ScanOutcome>
+    where
+        F: FnMut(&mut Tracked) -> Result<ScanOutcome>,
+    {
+        self.retry_reload(
+            || {
+                tracked.sent = 0;
+                attempt(tracked)
+            },
+            |outcome| retry::reloading_result(&outcome.result),
+        )
+    }
+
+    /// Waits for the scan rate limiter, then takes a concurrency slot.
+    pub(crate) fn scan_permit(&self) -> Result<Option<ConcurrencyPermit>> {
+        self.pace_scan();
+        self.concurrency_permit()
+    }
+
+    pub(crate) fn concurrency_permit(&self) -> Result<Option<ConcurrencyPermit>> {
+        match &self.concurrency_limiter {
+            Some(limiter) => match ConcurrencyLimiter::acquire(limiter) {
+                Some(permit) => Ok(Some(permit)),
+                None => Err(ClamError::ConcurrencyLimitReached(limiter.max())),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn register_cancel(
+        &self,
+        cancel: Option<&CancelToken>,
+        connection: &Connection,
+    ) -> Result<Option<Registration>> {
+        match cancel {
+            Some(token) => token.register(connection).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn pace_scan(&self) {
+        if let Some(limiter) = &self.scan_rate_limiter {
+            limiter.acquire(1);
+        }
+    }
+
+    pub(crate) fn throttle(&self, bytes: u64) {
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire(bytes);
+        }
+    }
+
+    fn emit<F: FnOnce() -> ScanEvent>(&self, event: F) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(&event());
+        }
+    }
+
+    fn command_started(&self, command: &Command) {
+        if let Some(hooks) = &self.hooks {
+            hooks.on_command_start(command);
+        }
+    }
+
+    fn command_ended(&self, command: &Command, error: Option<&ClamError>) {
+        if let Some(hooks) = &self.hooks {
+            hooks.on_command_end(command, error);
+        }
+    }
+
+    /// Tells the circuit breaker whether the daemon answered. Connection
+    /// failures are counted by `connect`, local errors not at all.
+    fn report_health(&self, result: std::result::Result<(), &ClamError>) {
+        if let Some(breaker) = &self.breaker {
+            match result {
+                Ok(()) => breaker.record_success(),
+                Err(
+                    ClamError::CommandError(_)
+                    | ClamError::Timeout(_)
+                    | ClamError::TruncatedResponse(_),
+                ) => breaker.record_failure(),
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Starts tracking a scan of `input`; `path` is set for files scanned
+    /// by path.
+    pub(crate) fn track(&self, input: &str, path: Option<&Path>) -> Tracked {
+        let tracked = Tracked {
+            id: self.next_scan.fetch_add(1, Ordering::Relaxed),
+            input: input.to_string(),
+            path: path.map(Path::to_path_buf),
+            started: Instant::now(),
+            sent: 0,
+        };
+
+        self.emit(|| ScanEvent::ScanStarted {
+            scan: tracked.id,
+            input: tracked.input.clone(),
+        });
+        self.command_started(&Command::Instream);
+        tracked
+    }
+
+    pub(crate) fn chunk_sent(&self, tracked: &mut Tracked, bytes: u64) {
+        tracked.sent += bytes;
+        self.emit(|| ScanEvent::ChunkSent {
+            scan: tracked.id,
+            bytes,
+            total: tracked.sent,
+        });
+    }
+
+    /// Reports the end of a tracked scan to the observer and records it in
+    /// the audit log, if there is one.
+    pub(crate) fn finish(
+        &self,
+        tracked: Tracked,
+        result: std::result::Result<&ScanResult, &ClamError>,
+    ) -> Result<()> {
+        let duration = tracked.started.elapsed();
+        let event_result = || match result {
+            Ok(result) => Ok(result.clone()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        if let Some(path) = &tracked.path {
+            self.emit(|| ScanEvent::FileScanned {
+                scan: tracked.id,
+                path: path.clone(),
+                result: event_result(),
+            });
+        }
+        self.emit(|| ScanEvent::ScanFinished {
+            scan: tracked.id,
+            input: tracked.input.clone(),
+            result: event_result(),
+            duration,
+        });
+
+        self.report_health(result.map(|_| ()));
+        self.command_ended(&Command::Instream, result.err());
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record(Verdict::of(result), tracked.sent, duration);
+        }
+
+        match &self.audit_log {
+            Some(log) => log.record(&tracked.input, result, duration),
+            None => Ok(()),
+        }
+    }
+
+    fn finish_outcome(
+        &self,
+        tracked: Tracked,
+        outcome: Result<ScanOutcome>,
+    ) -> Result<ScanOutcome> {
+        self.finish(tracked, outcome.as_ref().map(|o| &o.result))?;
+        outcome
+    }
+
+    fn outcome(&self, result: ScanResult, bytes_sent: u64, started: Instant) -> ScanOutcome {
+        ScanOutcome {
+            result,
+            bytes_sent,
+            duration: started.elapsed(),
+            endpoint: self.endpoint(),
+        }
+    }
+
+    fn file_frame_write(&self, connection: &Connection, file: &File, chunk: u64) -> Result<()> {
+        self.throttle(chunk);
+        self.connection_write(connection, &(chunk as u32).to_be_bytes())?;
+
+        let copied = connection.copy_from(&mut file.take(chunk));
+        if let Ok(n) = copied {
+            self.counters.sent(n);
+        }
+
+        match copied {
+            Ok(n) if n == chunk => Ok(()),
+            Ok(_) => Err(ClamError::StreamError(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "file was truncated while scanning",
+            ))),
+            Err(e) => Err(ClamError::StreamError(e)),
+        }
+    }
+
+    /// Terminates the stream and reads the verdict. A failure to send the
+    /// terminator is ignored when clamd has already replied.
+    fn instream_result<W: Write>(&self, connection: &Connection, writer: W) -> Result<ScanResult> {
+        if let Err(e) = self.finish_instream(writer) {
+            if !daemon_replied(connection) {
+                return Err(e);
+            }
+        }
+
+        read_scan_result(
+            connection,
+            &mut BufferPool::get(&self.buffers),
+            &self.counters,
+            self.framing,
+        )
+    }
+
+    pub(crate) fn finish_instream<W: Write>(&self, mut c: W) -> Result<()> {
+        self.connection_write(&mut c, &[0; 4])?;
+
+        match c.flush() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(ClamError::CommandError(e)),
+        }
+    }
+
+    pub(crate) fn buffers(&self) -> &Arc<BufferPool> {
+        &self.buffers
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+
+    pub(crate) fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub(crate) fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    pub(crate) fn connect_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub(crate) fn counters(&self) -> &Arc<Counters> {
+        &self.counters
+    }
+
+    /// Connects to the endpoint that worked last time, falling back to the
+    /// other resolved addresses in order. A host with both IPv6 and IPv4
+    /// addresses gets them raced instead, see `race`. Fails with the last
+    /// error if none accepts the connection.
+    pub(crate) fn connect(&self) -> Result<Connection> {
+        if let Some(breaker) = &self.breaker {
+            if let Err(wait) = breaker.allow() {
+                return Err(ClamError::CircuitOpen(wait));
+            }
+        }
+
+        let candidates = self.candidates();
+        let opened = if dual_stack(&candidates) {
+            self.race(candidates)
+        } else {
+            self.open_in_turn(candidates)
+        };
+
+        match opened {
+            Ok((endpoint, s)) => {
+                self.verify_peer(&s)?;
+                self.counters.connection_opened();
+                let previous = self.resolved().last_good.replace(endpoint.clone());
+                if previous.is_some_and(|previous| previous != endpoint) {
+                    self.forget_release();
+                }
+                match s.set_read_timeout(self.read_timeout) {
+                    Ok(_) => Ok(s),
+                    Err(e) => Err(ClamError::ConnectionError(e)),
+                }
+            }
+            Err(Some(e)) => {
+                if let Some(breaker) = &self.breaker {
+                    breaker.record_failure();
+                }
+                self.forget_release();
+                Err(ClamError::ConnectionError(e))
+            }
+            Err(None) => Err(ClamError::InvalidData(String::from(
+                "invalid socket address",
+            ))),
+        }
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn verify_peer(&self, s: &Connection) -> Result<()> {
+        let mismatch = match (&self.peer_credentials, s) {
+            (Some(credentials), Connection::Unix(s)) => credentials.mismatch(s),
+            _ => return Ok(()),
+        };
+
+        match mismatch {
+            Ok(None) => Ok(()),
+            Ok(Some(mismatch)) => Err(ClamError::UntrustedPeer(mismatch)),
+            Err(e) => Err(ClamError::ConnectionError(e)),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn verify_peer(&self, _: &Connection) -> Result<()> {
+        Ok(())
+    }
+
+    fn open_in_turn(&self, candidates: Vec<Endpoint>) -> Opened {
+        let mut last_error = None;
+
+        for endpoint in candidates {
+            let opened = Connection::open(&endpoint, self.timeout)
+                .and_then(|s| self.connect_hook(&endpoint).map(|_| s));
+
+            match opened {
+                Ok(s) => return Ok((endpoint, s)),
+                Err(e) => {
+                    self.attempt_failed(&endpoint, &e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Connects RFC 8305 style ("Happy Eyeballs"): attempts alternate
+    /// between the address families and each starts `CONNECTION_ATTEMPT_DELAY`
+    /// after the previous one, or as soon as it fails. The first connection
+    /// established wins, so a broken IPv6 path costs a fraction of a second
+    /// rather than a connect timeout per scan.
+    ///
+    /// Losing attempts are left to finish on their own threads; each is
+    /// bounded by the client's timeout, or `RACE_CONNECT_TIMEOUT` without one.
+    fn race(&self, candidates: Vec<Endpoint>) -> Opened {
+        let (done, attempts) = mpsc::channel();
+        let mut pending = interleave(candidates).into_iter();
+        let mut running = 0;
+        let mut last_error = None;
+
+        loop {
+            if let Some(endpoint) = pending.next() {
+                let done = done.clone();
+                let timeout = Some(self.timeout.unwrap_or(RACE_CONNECT_TIMEOUT));
+                // a losing connection is dropped once the channel is gone
+                thread::spawn(move || {
+                    let opened = Connection::open(&endpoint, timeout);
+                    let _ = done.send((endpoint, opened));
+                });
+                running += 1;
+            } else if running == 0 {
+                return Err(last_error);
+            }
+
+            let attempt = if pending.len() > 0 {
+                attempts.recv_timeout(CONNECTION_ATTEMPT_DELAY)
+            } else {
+                attempts.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            };
+            let (endpoint, opened) = match attempt {
+                Ok(attempt) => attempt,
+                // time to start the next attempt
+                Err(_) => continue,
+            };
+
+            running -= 1;
+            match opened.and_then(|s| self.connect_hook(&endpoint).map(|_| s)) {
+                Ok(s) => return Ok((endpoint, s)),
+                Err(e) => {
+                    self.attempt_failed(&endpoint, &e);
+                    last_error = Some(e);
+                }
+            }
+        }
+    }
+
+    fn connect_hook(&self, endpoint: &Endpoint) -> io::Result<()> {
+        match &self.hooks {
+            Some(hooks) => hooks.on_connect(endpoint),
+            None => Ok(()),
+        }
+    }
+
+    fn attempt_failed(&self, endpoint: &Endpoint, e: &io::Error) {
+        self.counters.connection_failed();
+        self.emit(|| ScanEvent::ConnectionError {
+            endpoint: endpoint.clone(),
+            error: e.to_string(),
+        });
+    }
+
+    /// The endpoints to try, the last good one first, resolving the host
+    /// again if the DNS TTL has expired.
+    pub(crate) fn candidates(&self) -> Vec<Endpoint> {
+        if let (Some(address), Some(ttl)) = (&self.address, self.dns_ttl) {
+            // the lookup blocks, so it runs unlocked; restarting the clock
+            // first keeps concurrent connects from all looking up at once
+            let expired = {
+                let mut resolved = self.resolved();
+                let expired = resolved.at.elapsed() >= ttl;
+                if expired {
+                    resolved.at = Instant::now();
+                }
+                expired
+            };
+
+            if expired {
+                if let Ok(endpoints) = resolve(address) {
+                    self.resolved().endpoints = endpoints;
+                }
+            }
+        }
+
+        let resolved = self.resolved();
+        let mut candidates = resolved.endpoints.clone();
+        let last_good = resolved.last_good.as_ref();
+        if let Some(index) = candidates.iter().position(|e| Some(e) == last_good) {
+            let endpoint = candidates.remove(index);
+            candidates.insert(0, endpoint);
+        }
+
+        candidates
+    }
+
+    fn resolved(&self) -> MutexGuard<'_, Resolved> {
+        match self.resolved.lock() {
+            Ok(resolved) => resolved,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+fn resolve(address: &str) -> Result<Vec<Endpoint>> {
+    let endpoints: Vec<Endpoint> = match address.to_socket_addrs() {
+        Ok(iter) => iter.map(Endpoint::Tcp).collect(),
+        Err(e) => return Err(ClamError::InvalidIpAddress(e)),
+    };
+
+    if endpoints.is_empty() {
+        return Err(ClamError::InvalidData(String::from(
+            "invalid socket address",
+        )));
+    }
+
+    Ok(endpoints)
+}
+
+fn dual_stack(candidates: &[Endpoint]) -> bool {
+    let family = |v6| {
+        candidates
+            .iter()
+            .any(|e| matches!(e, Endpoint::Tcp(address) if address.is_ipv6() == v6))
+    };
+    family(true) && family(false)
+}
+
+/// Orders TCP endpoints so the address families alternate, starting with
+/// the family of the first one.
+fn interleave(candidates: Vec<Endpoint>) -> Vec<Endpoint> {
+    let v6 = |e: &Endpoint| matches!(e, Endpoint::Tcp(address) if address.is_ipv6());
+    let first_v6 = candidates.first().is_some_and(v6);
+    let (first, second): (Vec<_>, Vec<_>) = candidates.into_iter().partition(|e| v6(e) == first_v6);
+
+    let mut ordered = Vec::with_capacity(first.len() + second.len());
+    let (mut first, mut second) = (first.into_iter(), second.into_iter());
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+// the daemon names the stream `stream`, which the label replaces
+fn labeled(label: &str, result: ScanResult) -> ScanItem {
+    ScanItem {
+        subject: label.to_string(),
+        ..ScanItem::from_result("stream", result)
+    }
+}
+
+// hands out the readers of `scan_streams` to its workers
+struct SharedStreams<'s, I>(&'s Mutex<I>);
+
+struct IndexedStream<R> {
+    index: usize,
+    stream: R,
+}
+
+impl<I, R> Iterator for SharedStreams<'_, I>
+where
+    I: Iterator<Item = (usize, R)>,
+{
+    type Item = IndexedStream<R>;
+
+    fn next(&mut self) -> Option<IndexedStream<R>> {
+        let (index, stream) = self.0.lock().ok()?.next()?;
+        Some(IndexedStream { index, stream })
+    }
+}
+
+impl<R: Read> ScanInput for IndexedStream<R> {
+    fn scan_in(&mut self, session: &mut ClamSession<'_>) -> Result<ScanResult> {
+        session.scan_stream(&mut self.stream)
+    }
+
+    fn submit_in(&mut self, session: &mut ClamSession<'_>) -> Result<Pending<ScanResult>> {
+        session.submit_stream(&mut self.stream)
+    }
+}
+
+// ends a stream after `remaining` bytes, noting whether there was more
+struct Capped<R> {
+    inner: R,
+    remaining: u64,
+    exceeded: bool,
+}
+
+impl<R: Read> Read for Capped<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            let mut probe = [0; 1];
+            self.exceeded = self.inner.read(&mut probe)? > 0;
+            return Ok(0);
+        }
+
+        let len = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+struct Limited<'a, R> {
+    inner: R,
+    limiter: &'a RateLimiter,
+}
+
+impl<R: Read> Read for Limited<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.limiter.acquire(n as u64);
+        Ok(n)
+    }
+}
+
+// maps the file at `path`; empty files cannot be mapped and give `None`
+#[cfg(feature = "mmap")]
+fn map_file(path: &Path) -> io::Result<Option<memmap2::Mmap>> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+
+    // Safety: the mapping is only read while scanning; a file truncated
+    // by another process during the scan is outside what we can guard.
+    unsafe { memmap2::Mmap::map(&file) }.map(Some)
+}
+
+fn check_cancel(cancel: Option<&CancelToken>) -> Result<()> {
+    match cancel {
+        Some(token) => token.check(),
+        None => Ok(()),
+    }
+}
+
+/// Writes one INSTREAM frame, sending the length prefix and payload in a
+/// single vectored write where the socket allows it.
+pub(crate) fn write_frame<W: Write>(mut w: W, chunk: &[u8]) -> io::Result<()> {
+    let length = (chunk.len() as u32).to_be_bytes();
+    let mut slices = [IoSlice::new(&length), IoSlice::new(chunk)];
+    let mut slices = &mut slices[..];
+
+    while !slices.is_empty() {
+        match w.write_vectored(slices) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    ErrorKind::WriteZero,
+                    "failed to write whole frame",
+                ))
+            }
+            Ok(n) => IoSlice::advance_slices(&mut slices, n),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks without blocking whether clamd has sent anything (or hung up).
+pub(crate) fn daemon_replied(connection: &Connection) -> bool {
+    connection.has_data()
+}
+
+/// Tells a reply read that ran into the read timeout apart from other
+/// failures, which become `other`.
+pub(crate) fn read_failed(e: io::Error, other: fn(io::Error) -> ClamError) -> ClamError {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => ClamError::Timeout(e),
+        _ => other(e),
+    }
+}
+
+/// Fails with `TruncatedResponse` if the connection closed partway through
+/// a reply, before its terminator arrived. An empty reply is left to the
+/// parser.
+pub(crate) fn check_terminated(reply: &[u8], framing: Framing) -> Result<()> {
+    match reply.last() {
+        Some(&last) if last != framing.delimiter() => Err(ClamError::TruncatedResponse(
+            String::from_utf8_lossy(reply).into_owned(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn read_scan_result<R: Read>(
+    mut connection: R,
+    buffer: &mut Vec<u8>,
+    counters: &Counters,
+    framing: Framing,
+) -> Result<ScanResult> {
+    match connection.read_to_end(buffer) {
+        Ok(n) => {
+            counters.received(n as u64);
+            check_terminated(buffer, framing)?;
+            framing.normalize(&Command::Instream, buffer);
+            let result = String::from_utf8_lossy(buffer);
+            let scan_result = ScanResult::parse(&result);
+
+            if let Some(singular) = scan_result.first() {
+                Ok(singular.clone())
+            } else {
+                Err(ClamError::InvalidData(result.into_owned()))
+            }
+        }
+        Err(e) => Err(read_failed(e, ClamError::ConnectionError)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockDaemon;
+    use crate::response::Signature;
+    use std::io;
+    use std::sync::atomic::AtomicBool;
+
+    /// Hands out its data a few bytes at a time, interrupting every other read.
+    struct ChunkedReader {
+        data: Vec<u8>,
+        position: usize,
+        step: usize,
+        interrupt: bool,
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::Error::new(ErrorKind::Interrupted, "interrupted"));
+            }
+
+            let end = (self.position + self.step)
+                .min(self.data.len())
+                .min(self.position + buf.len());
+            let n = end - self.position;
+            buf[..n].copy_from_slice(&self.data[self.position..end]);
+            self.position = end;
+            Ok(n)
+        }
+    }
+
+    struct FailingReader;
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(ErrorKind::BrokenPipe, "source went away"))
+        }
+    }
+
+    #[test]
+    fn test_ping() {
+        let daemon = MockDaemon::start(b"PONG\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        assert!(cclient.ping());
+        assert_eq!(daemon.received().command, b"zPING".to_vec());
+    }
+
+    #[test]
+    fn test_allmatch_needs_recent_daemon() {
+        let daemon = MockDaemon::start(b"ClamAV 0.98.7/21000/Wed Aug  1 08:43:37 2018\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let options = ScanOptions {
+            all_match: true,
+            ..Default::default()
+        };
+
+        // the second attempt is answered from the cached version
+        for _ in 0..2 {
+            match cclient.scan(ScanTarget::path("/tmp"), options) {
+                Err(ClamError::UnsupportedByDaemon { needs, have }) => {
+                    assert_eq!((needs.as_str(), have.as_str()), ("0.99.0", "0.98.7"))
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        assert_eq!(daemon.received().command, b"zVERSION".to_vec());
+    }
+
+    #[test]
+    fn test_allmatch_with_unnamed_release() {
+        let transport = MemoryTransport::new()
+            .reply(b"ClamAV devel\0")
+            .reply(b"/tmp: OK\0");
+        let cclient = ClamClient::new_memory(transport.clone());
+        let options = ScanOptions {
+            all_match: true,
+            ..Default::default()
+        };
+
+        let results = cclient.scan(ScanTarget::path("/tmp"), options).unwrap();
+        assert_eq!(results, vec![ScanResult::Ok]);
+        assert_eq!(
+            transport.received(),
+            [b"zVERSION\0".to_vec(), b"zALLMATCHSCAN /tmp\0".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_release_forgotten_after_failure() {
+        let transport = MemoryTransport::new()
+            .reply(b"ClamAV 0.98.7/21000/Wed Aug  1 08:43:37 2018\0")
+            .refuse()
+            .reply(b"ClamAV 1.0.0/27000/Wed Aug  1 08:43:37 2018\0")
+            .reply(b"/tmp: OK\0");
+        let cclient = ClamClient::new_memory(transport);
+        let options = ScanOptions {
+            all_match: true,
+            ..Default::default()
+        };
+
+        assert!(cclient.scan(ScanTarget::path("/tmp"), options).is_err());
+        assert!(!cclient.ping());
+        // the daemon came back upgraded
+        let results = cclient.scan(ScanTarget::path("/tmp"), options).unwrap();
+        assert_eq!(results, vec![ScanResult::Ok]);
+    }
+
+    #[test]
+    fn test_shutdown_without_reply() {
+        let daemon = MockDaemon::start(b"");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        assert_eq!(cclient.shutdown().unwrap(), ShutdownAck);
+        assert_eq!(daemon.received().command, b"zSHUTDOWN".to_vec());
+    }
+
+    #[test]
+    fn test_client_no_timeout() {
+        let cclient = ClamClient::new("127.0.0.1", 3310).unwrap();
+        let socket_addr =
+            ::std::net::SocketAddr::new(::std::net::IpAddr::from([127, 0, 0, 1]), 3310);
+        assert_eq!(cclient.endpoint(), Endpoint::Tcp(socket_addr));
+        assert_eq!(cclient.timeout, None);
+    }
+
+    #[test]
+    fn test_client_with_timeout() {
+        let cclient = ClamClient::new_with_timeout("127.0.0.1", 3310, 60).unwrap();
+        let socket_addr =
+            ::std::net::SocketAddr::new(::std::net::IpAddr::from([127, 0, 0, 1]), 3310);
+        assert_eq!(cclient.endpoint(), Endpoint::Tcp(socket_addr));
+        assert_eq!(cclient.timeout, Some(::std::time::Duration::from_secs(60)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_client_with_timeout() {
+        let cclient = ClamClient::new_unix_with_timeout("/run/clamd.ctl", 60);
+        assert_eq!(
+            cclient.endpoint(),
+            Endpoint::Unix(PathBuf::from("/run/clamd.ctl"))
+        );
+        assert_eq!(
+            cclient.read_timeout,
+            Some(::std::time::Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn test_dual_stack_race() {
+        // 100::/64 is a discard prefix: depending on the host the attempt
+        // fails at once or never completes, and neither may hold up IPv4
+        let blackhole = "[100::1]:3310".parse().unwrap();
+        let daemon = MockDaemon::start(b"PONG\0");
+        let reachable = ([127, 0, 0, 1], daemon.port).into();
+        let cclient = ClamClient::with_endpoints(
+            vec![Endpoint::Tcp(blackhole), Endpoint::Tcp(reachable)],
+            None,
+        );
+
+        let started = Instant::now();
+        assert!(cclient.ping());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(cclient.endpoint(), Endpoint::Tcp(reachable));
+    }
+
+    #[test]
+    fn test_interleave_address_families() {
+        let endpoints = [
+            "[::1]:1",
+            "[::2]:1",
+            "[::3]:1",
+            "127.0.0.1:1",
+            "127.0.0.2:1",
+        ]
+        .iter()
+        .map(|a| Endpoint::Tcp(a.parse().unwrap()))
+        .collect::<Vec<_>>();
+        assert!(dual_stack(&endpoints));
+        assert!(!dual_stack(&endpoints[..3]));
+
+        let ordered = interleave(endpoints.clone());
+        let expected = [0, 3, 1, 4, 2].map(|i| endpoints[i].clone());
+        assert_eq!(ordered, expected);
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        // accepted by the kernel, never answered
+        let wedged = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = wedged.local_addr().unwrap().port();
+        let cclient = ClamClient::new("127.0.0.1", port)
+            .unwrap()
+            .with_read_timeout(Duration::from_millis(50));
+
+        match cclient.version() {
+            Err(ClamError::Timeout(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match cclient.scan_bytes(b"data") {
+            Err(ClamError::Timeout(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_newline_framing() {
+        let transport = MemoryTransport::new()
+            .reply(b"PONG\n")
+            .reply(b"stream: Eicar FOUND\n")
+            .reply(b"/srv/a: OK\n/srv/b: Eicar FOUND\n")
+            .reply(b"1: PONG\n");
+        let cclient =
+            ClamClient::new_memory(transport.clone()).with_framing(Framing::NewlineTerminated);
+
+        assert!(cclient.ping());
+        match cclient.scan_bytes(b"eicar").unwrap() {
+            ScanResult::Found(_, signature, _) => assert_eq!(signature.raw, "Eicar"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let results = cclient.scan_path("/srv", false).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0], ScanResult::Ok);
+
+        let mut session = cclient.session().unwrap();
+        let ping = session.submit::<String>(&Command::Ping).unwrap();
+        assert_eq!(session.wait(ping).unwrap(), "PONG");
+        session.end().unwrap();
+
+        assert_eq!(
+            transport.received(),
+            [
+                b"nPING\n".to_vec(),
+                b"nINSTREAM\n\0\0\0\x05eicar\0\0\0\0".to_vec(),
+                b"nSCAN /srv\n".to_vec(),
+                b"nIDSESSION\nnPING\nnEND\n".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wait_rejects_foreign_pending() {
+        let daemon = MockDaemon::start_sessions("PONG", 2);
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let mut first = cclient.session().unwrap();
+        let mut second = cclient.session().unwrap();
+        let ping = first.submit::<String>(&Command::Ping).unwrap();
+
+        match second.wait(ping) {
+            Err(ClamError::ForeignPending(1)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let ping = first.submit::<String>(&Command::Ping).unwrap();
+        assert_eq!(first.wait(ping).unwrap(), "PONG");
+        first.end().unwrap();
+        second.end().unwrap();
+        daemon.all_received();
+    }
+
+    #[test]
+    fn test_truncated_reply() {
+        let transport = MemoryTransport::new()
+            .reply(b"stream: Eicar FOU")
+            .reply(b"RELOAD")
+            .reply(b"1: PO");
+        let cclient = ClamClient::new_memory(transport);
+
+        match cclient.scan_bytes(b"eicar") {
+            Err(ClamError::TruncatedResponse(reply)) => assert_eq!(reply, "stream: Eicar FOU"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match cclient.reload() {
+            Err(ClamError::TruncatedResponse(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut session = cclient.session().unwrap();
+        let ping = session.submit::<String>(&Command::Ping).unwrap();
+        match session.wait(ping) {
+            Err(ClamError::TruncatedResponse(reply)) => assert_eq!(reply, "1: PO"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_retries_truncated_reply() {
+        let transport = MemoryTransport::new()
+            .reply(b"1: PO")
+            .reply(b"1: PONG\0")
+            .reply(b"/srv/a: O")
+            .reply(b"/srv/a: O")
+            .reply(b"/srv/a: O")
+            .reply(b"stream: O");
+        let cclient = ClamClient::new_memory(transport.clone()).with_persistent_session();
+
+        // the persistent session is reopened
+        assert!(cclient.ping());
+        match cclient.scan_path("/srv/a", false) {
+            Err(ClamError::TruncatedResponse(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // not idempotent
+        match cclient.scan_bytes(b"data") {
+            Err(ClamError::TruncatedResponse(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(transport.received().len(), 6);
+    }
+
+    #[test]
+    fn test_newline_framing_keepalive() {
+        let transport = MemoryTransport::new()
+            .reply(b"1: PONG\n2: ClamAV 0.103.2/26000/Mon Jan  3 09:00:00 2022\n");
+        let cclient = ClamClient::new_memory(transport)
+            .with_framing(Framing::NewlineTerminated)
+            .with_persistent_session();
+
+        assert!(cclient.ping());
+        assert_eq!(cclient.version().unwrap().version_tag, "ClamAV 0.103.2");
+    }
+
+    #[test]
+    fn test_warm_up_ping_replaces_dead_keepalive() {
+        let transport = MemoryTransport::new()
+            .reply(b"1: PO")
+            .reply(b"1: PONG\x002: ClamAV 0.103.2/26000/Mon Jan  3 09:00:00 2022\0");
+        let cclient = ClamClient::new_memory(transport.clone())
+            .with_persistent_session()
+            .with_warm_up_ping();
+
+        assert_eq!(cclient.version().unwrap().build_number, 26000);
+        assert_eq!(transport.received().len(), 2);
+    }
+
+    #[test]
+    fn test_max_idle_evicts_keepalive() {
+        let transport = MemoryTransport::new()
+            .reply(b"1: PONG\x002: PONG\0")
+            .reply(b"1: PONG\0");
+        let cclient = ClamClient::new_memory(transport.clone())
+            .with_persistent_session()
+            .with_max_idle(Duration::from_millis(20));
+
+        assert!(cclient.ping());
+        thread::sleep(Duration::from_millis(50));
+        assert!(cclient.ping());
+        assert_eq!(transport.received().len(), 2);
+    }
+
+    #[test]
+    fn test_bulk_profile_sends_one_frame() {
+        let transport = MemoryTransport::new().reply(b"stream: OK\0");
+        let cclient = ClamClient::new_memory(transport.clone()).with_bulk_profile();
+
+        assert_eq!(
+            cclient.scan_bytes(vec![7u8; 100_000]).unwrap(),
+            ScanResult::Ok
+        );
+        let received = transport.received();
+        assert_eq!(received[0].len(), b"zINSTREAM\0".len() + 4 + 100_000 + 4);
+    }
+
+    #[test]
+    fn test_connect_falls_back_to_next_address() {
+        let unreachable = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|l| l.local_addr())
+            .unwrap();
+        let daemon = MockDaemon::start_many(b"PONG\0", 2);
+        let reachable = ([127, 0, 0, 1], daemon.port).into();
+        let cclient = ClamClient::with_endpoints(
+            vec![Endpoint::Tcp(unreachable), Endpoint::Tcp(reachable)],
+            None,
+        );
+
+        assert!(cclient.ping());
+        assert_eq!(cclient.endpoint(), Endpoint::Tcp(reachable));
+        assert!(cclient.ping());
+        assert_eq!(daemon.all_received().len(), 2);
+    }
+
+    #[test]
+    fn test_persistent_session() {
+        let daemon = MockDaemon::start_session("PONG");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_persistent_session();
+
+        assert!(cclient.ping());
+        assert!(cclient.ping());
+        drop(cclient);
+
+        let commands = daemon
+            .all_received()
+            .iter()
+            .map(|r| String::from_utf8_lossy(&r.command).into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(commands, vec!["zIDSESSION", "zPING", "zPING", "zEND"]);
+    }
+
+    #[test]
+    fn test_persistent_session_survives_restart() {
+        fn serve(stream: &mut std::net::TcpStream, commands: usize, reply: &[u8]) {
+            let mut received = Vec::new();
+            let mut buf = [0; 64];
+            while received.iter().filter(|&&b| b == 0).count() < commands {
+                let n = stream.read(&mut buf).unwrap();
+                received.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(reply).unwrap();
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let daemon = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            serve(&mut stream, 2, b"1: PONG\0");
+            drop(listener);
+            drop(stream);
+
+            std::thread::sleep(Duration::from_millis(200));
+            let listener = std::net::TcpListener::bind(address).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            serve(&mut stream, 1, b"PONG\0");
+            drop(stream);
+            let (mut stream, _) = listener.accept().unwrap();
+            serve(&mut stream, 2, b"1: PONG\0");
+        });
+
+        let restarts = Arc::new(AtomicU64::new(0));
+        let counted = Arc::clone(&restarts);
+        let cclient = ClamClient::new("127.0.0.1", address.port())
+            .unwrap()
+            .with_persistent_session()
+            .with_reconnect_policy(
+                ReconnectPolicy::new()
+                    .wait_until_ready(Duration::from_secs(5))
+                    .on_restart(move |_, _| {
+                        counted.fetch_add(1, Ordering::Relaxed);
+                    }),
+            );
+
+        assert!(cclient.ping());
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(cclient.ping());
+        assert_eq!(restarts.load(Ordering::Relaxed), 1);
+        daemon.join().unwrap();
+    }
+
+    #[test]
+    fn test_dns_ttl_resolves_again() {
+        let unreachable = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|l| l.local_addr())
+            .unwrap();
+        let daemon = MockDaemon::start(b"PONG\0");
+        // as if the host had moved since the client was created
+        let mut cclient = ClamClient::with_endpoints(vec![Endpoint::Tcp(unreachable)], None)
+            .with_dns_ttl(Duration::ZERO);
+        cclient.address = Some(format!("127.0.0.1:{}", daemon.port));
+
+        assert!(cclient.ping());
+        assert_eq!(
+            cclient.endpoint(),
+            Endpoint::Tcp(([127, 0, 0, 1], daemon.port).into())
+        );
+        daemon.received();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use std::io::BufRead;
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("clamd-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let daemon = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut command = Vec::new();
+            io::BufReader::new(&stream)
+                .read_until(0, &mut command)
+                .unwrap();
+            (&stream).write_all(b"PONG\0").unwrap();
+            command
+        });
+
+        let cclient = ClamClient::new_unix(&path);
+        assert!(cclient.ping());
+        assert_eq!(daemon.join().unwrap(), b"zPING\0");
+        assert_eq!(cclient.endpoint().to_string(), path.display().to_string());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_peer_credentials() {
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("clamd-peer-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        // the socket is ours, and so is the process serving it
+        let owner = std::fs::metadata(&path).unwrap();
+        let accepting = std::thread::spawn(move || {
+            (0..2)
+                .map(|_| listener.accept().unwrap().0)
+                .collect::<Vec<_>>()
+        });
+
+        let trusted = PeerCredentials::new().uid(owner.uid()).gid(owner.gid());
+        let cclient = ClamClient::new_unix(&path).with_peer_credentials(trusted);
+        assert!(cclient.connect().is_ok());
+
+        let other = PeerCredentials::new().uid(owner.uid().wrapping_add(1));
+        let cclient = ClamClient::new_unix(&path).with_peer_credentials(other);
+        match cclient.connect() {
+            Err(ClamError::UntrustedPeer(mismatch)) => {
+                assert!(mismatch.ends_with(&format!("expected uid {}", owner.uid() + 1)))
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+
+        accepting.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_scan_stream_short_reads() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let data = (0..10000).map(|i| i as u8).collect::<Vec<u8>>();
+        let reader = ChunkedReader {
+            data: data.clone(),
+            position: 0,
+            step: 1000,
+            interrupt: false,
+        };
+
+        assert_eq!(cclient.scan_stream(reader).unwrap(), ScanResult::Ok);
+        assert_eq!(daemon.received().payload(), data);
+    }
+
+    #[test]
+    fn test_scan_stream_read_error() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        match cclient.scan_stream(FailingReader) {
+            Err(ClamError::StreamError(e)) => assert_eq!(e.kind(), ErrorKind::BrokenPipe),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_write_frame() {
+        let mut out = Vec::new();
+        write_frame(&mut out, b"payload").unwrap();
+        assert_eq!(out, b"\0\0\0\x07payload".to_vec());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_scan_file_mmap() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("clamav-client-mmap-test-{}", std::process::id()));
+        let data = vec![3u8; FILE_CHUNK_SIZE + 100];
+        std::fs::write(&path, &data).unwrap();
+
+        let result = cclient.scan_file_mmap(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap(), ScanResult::Ok);
+        let received = daemon.received();
+        assert_eq!(received.chunks.len(), 2);
+        assert_eq!(received.payload(), data);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_scan_file_mmap_audited_by_path() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let data_path = std::env::temp_dir().join(format!(
+            "clamav-client-mmap-audit-test-{}",
+            std::process::id()
+        ));
+        let path = data_path.with_extension("jsonl");
+        let _ = std::fs::remove_file(&path);
+        let log = Arc::new(AuditLog::open(&path).unwrap());
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_audit_log(log);
+        std::fs::write(&data_path, b"data").unwrap();
+
+        let result = cclient.scan_file_mmap(&data_path);
+        std::fs::remove_file(&data_path).unwrap();
+        assert_eq!(result.unwrap(), ScanResult::Ok);
+        daemon.received();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let input = crate::json::string(&data_path.to_string_lossy());
+        assert!(written.contains(&format!(r#""input":{},"verdict":"ok""#, input)));
+    }
+
+    #[test]
+    fn test_scan_file() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("clamav-client-file-test-{}", std::process::id()));
+        let data = (0..FILE_CHUNK_SIZE + 100)
+            .map(|i| i as u8)
+            .collect::<Vec<u8>>();
+        std::fs::write(&path, &data).unwrap();
+
+        let result = cclient.scan_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.unwrap(), ScanResult::Ok);
+        let received = daemon.received();
+        assert_eq!(received.chunks.len(), 2);
+        assert_eq!(received.payload(), data);
+    }
+
+    #[test]
+    fn test_retries_while_reloading() {
+        let daemon = MockDaemon::start_replies(&[
+            b"stream: RELOADING\0",
+            b"stream: RELOADING\0",
+            b"stream: Eicar FOUND\0",
+            b"RELOADING\0",
+            b"PONG\0",
+        ]);
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_reload_retry(ReloadRetry::new(2, Duration::from_millis(1)));
+
+        let outcome = cclient.scan_bytes_outcome(b"data").unwrap();
+        assert!(matches!(outcome.result, ScanResult::Found(..)));
+        assert_eq!(outcome.bytes_sent, 4);
+        assert!(cclient.ping());
+
+        let received = daemon.all_received();
+        assert_eq!(received.len(), 5);
+        assert_eq!(received[2].payload(), b"data");
+    }
+
+    #[test]
+    fn test_reload_retries_run_out() {
+        let daemon = MockDaemon::start_many(b"stream: RELOADING\0", 2);
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_reload_retry(ReloadRetry::new(1, Duration::from_millis(1)));
+
+        assert_eq!(
+            cclient.scan_bytes(b"data").unwrap(),
+            ScanResult::Error(String::from("stream: RELOADING"))
+        );
+        assert_eq!(daemon.all_received().len(), 2);
+    }
+
+    #[test]
+    fn test_scan_bytes_batch() {
+        let daemon = MockDaemon::start_session("stream: OK");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let results = cclient
+            .scan_bytes_batch(&[b"one", b"two", b"three"])
+            .unwrap();
+        assert_eq!(results, vec![ScanResult::Ok; 3]);
+        assert!(cclient.scan_bytes_batch(&[]).unwrap().is_empty());
+
+        let received = daemon.all_received();
+        assert_eq!(received.len(), 5);
+        assert_eq!(received[3].payload(), b"three");
+    }
+
+    #[test]
+    fn test_scan_streams() {
+        let daemon = MockDaemon::start_sessions("stream: OK", 2);
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let streams: Vec<Box<dyn Read + Send>> = vec![
+            Box::new(&b"one"[..]),
+            Box::new(FailingReader),
+            Box::new(&b"three"[..]),
+        ];
+        let results = cclient.scan_streams(streams, 1);
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), &ScanResult::Ok);
+        match &results[1] {
+            Err(ClamError::StreamError(e)) => assert_eq!(e.kind(), ErrorKind::BrokenPipe),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(results[2].as_ref().unwrap(), &ScanResult::Ok);
+        assert!(cclient.scan_streams(Vec::<&[u8]>::new(), 4).is_empty());
+    }
+
+    #[test]
+    fn test_scan_stream_capped() {
+        let daemon = MockDaemon::start_many(b"stream: OK\0", 2);
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        assert_eq!(
+            cclient.scan_stream_capped(&b"0123456789"[..], 10).unwrap(),
+            ScanResult::Ok
+        );
+        match cclient.scan_stream_capped(&b"0123456789"[..], 4) {
+            Err(ClamError::SizeCapExceeded(4)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let received = daemon.all_received();
+        assert_eq!(received[0].payload(), b"0123456789");
+        assert_eq!(received[1].payload(), b"0123");
+    }
+
+    #[test]
+    fn test_scan_stream_limited() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let limiter = RateLimiter::new(10_000);
+        let started = Instant::now();
+
+        let data = vec![0u8; 12_000];
+        assert_eq!(
+            cclient
+                .scan_stream_limited(data.as_slice(), &limiter)
+                .unwrap(),
+            ScanResult::Ok
+        );
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(daemon.received().payload(), data);
+    }
+
+    #[test]
+    fn test_stats_snapshot() {
+        let daemon = MockDaemon::start_session("stream: OK");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        cclient.scan_bytes_batch(&[b"one", b"two"]).unwrap();
+        daemon.all_received();
+
+        assert_eq!(
+            cclient.stats_snapshot(),
+            ClientStats {
+                connections_opened: 1,
+                connections_reused: 1,
+                connections_failed: 0,
+                // IDSESSION, two streams of one frame each, END
+                bytes_sent: 11 + 2 * (10 + 7 + 4) + 5,
+                bytes_received: 2 * "1: stream: OK\0".len() as u64,
+                commands: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn test_hooks() {
+        #[derive(Default)]
+        struct Recorder {
+            calls: Mutex<Vec<String>>,
+            refuse: AtomicBool,
+        }
+
+        impl ClientHooks for Recorder {
+            fn on_connect(&self, _: &Endpoint) -> io::Result<()> {
+                self.calls.lock().unwrap().push(String::from("connect"));
+                if self.refuse.load(Ordering::SeqCst) {
+                    return Err(ErrorKind::ConnectionRefused.into());
+                }
+                Ok(())
+            }
+
+            fn on_command_start(&self, command: &Command) {
+                let call = format!("start {}", command.name());
+                self.calls.lock().unwrap().push(call);
+            }
+
+            fn on_command_end(&self, command: &Command, error: Option<&ClamError>) {
+                let call = format!("end {} {}", command.name(), error.is_some());
+                self.calls.lock().unwrap().push(call);
+            }
+        }
+
+        let daemon = MockDaemon::start_replies(&[b"PONG\0", b"stream: OK\0", b"PONG\0"]);
+        let hooks = Arc::new(Recorder::default());
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_hooks(hooks.clone());
+
+        assert!(cclient.ping());
+        assert_eq!(cclient.scan_bytes(b"data").unwrap(), ScanResult::Ok);
+        hooks.refuse.store(true, Ordering::SeqCst);
+        assert!(!cclient.ping());
+
+        assert_eq!(
+            *hooks.calls.lock().unwrap(),
+            [
+                "start PING",
+                "connect",
+                "end PING false",
+                "start INSTREAM",
+                "connect",
+                "end INSTREAM false",
+                "start PING",
+                "connect",
+                "end PING true",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_path_map() {
+        let daemon = MockDaemon::start(b"/srv/a: Eicar FOUND\0/srv/b: Access denied. ERROR\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let options = ScanOptions {
+            continue_on_virus: true,
+            ..Default::default()
+        };
+        let verdicts = cclient.scan_path_map("/srv", options).unwrap();
+
+        assert_eq!(daemon.received().command, b"zCONTSCAN /srv");
+        assert_eq!(
+            verdicts[Path::new("/srv/a")],
+            ScanVerdict::Infected(vec![Signature::from("Eicar")])
+        );
+        assert!(matches!(
+            verdicts[Path::new("/srv/b")],
+            ScanVerdict::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_scan_items() {
+        let daemon = MockDaemon::start(b"/srv/a: OK\0/srv/b: Eicar FOUND\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let items = cclient
+            .scan_items(ScanTarget::path("/srv"), ScanOptions::default())
+            .unwrap();
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].subject, "/srv/a");
+        assert!(items[0].is_clean());
+        assert_eq!(items[1].subject, "/srv/b");
+    }
+
+    #[test]
+    fn test_labeled_scan() {
+        let daemon = MockDaemon::start(b"stream: Eicar FOUND\0");
+        let (events, received) = std::sync::mpsc::channel();
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_observer(Arc::new(events));
+
+        let item = cclient.scan_bytes_labeled(b"data", "upload-42").unwrap();
+
+        assert_eq!(item.subject, "upload-42");
+        assert!(matches!(item.verdict, ScanVerdict::Infected(_)));
+        match received.recv().unwrap() {
+            ScanEvent::ScanStarted { input, .. } => assert_eq!(input, "upload-42"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scan_paths() {
+        let daemon = MockDaemon::start_sessions("/srv: OK", 2);
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("clamav-client-paths-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        std::fs::write(&a, b"a").unwrap();
+        std::fs::write(&b, b"b").unwrap();
+
+        let scan = cclient.scan_paths([&a, &b, &dir], ScanOptions::default());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(scan.results.len(), 3);
+        assert!(scan.report.is_clean());
+        assert_eq!(scan.report.results.len(), 3);
+        assert_eq!(scan.report.timings[2].0, dir);
+
+        let commands = daemon
+            .all_received()
+            .iter()
+            .map(|r| String::from_utf8_lossy(&r.command).into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            vec![
+                String::from("zIDSESSION"),
+                format!("zSCAN {}", a.display()),
+                format!("zSCAN {}", b.display()),
+                String::from("zEND"),
+                format!("zSCAN {}", dir.display()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_glob() {
+        let dir = std::env::temp_dir().join(format!(
+            "clamav-client-scan-glob-test-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("x")).unwrap();
+        std::fs::write(dir.join("a.zip"), b"a").unwrap();
+        std::fs::write(dir.join("x/b.zip"), b"b").unwrap();
+        std::fs::write(dir.join("x/c.txt"), b"c").unwrap();
+        let pattern = format!("{}/**/*.zip", dir.display());
+
+        let daemon = MockDaemon::start_session("stream: OK");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let streamed = cclient.scan_glob(&pattern, ScanOptions::default()).unwrap();
+        let payloads = daemon
+            .all_received()
+            .iter()
+            .map(|r| r.payload())
+            .collect::<Vec<_>>();
+
+        let daemon = MockDaemon::start_session("/srv: OK");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let options = ScanOptions {
+            shared_filesystem: true,
+            ..Default::default()
+        };
+        let shared = cclient.scan_glob(&pattern, options).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let expected = vec![
+            (dir.join("a.zip"), ScanResult::Ok),
+            (dir.join("x/b.zip"), ScanResult::Ok),
+        ];
+        assert_eq!(streamed.results, expected);
+        assert_eq!(streamed.timings.len(), 2);
+        assert_eq!(
+            payloads,
+            [b"".to_vec(), b"a".to_vec(), b"b".to_vec(), b"".to_vec()]
+        );
+        assert_eq!(shared.results, expected);
+        assert_eq!(
+            daemon.all_received()[1].command,
+            format!("zSCAN {}", dir.join("a.zip").display()).into_bytes()
+        );
+    }
+
+    #[test]
+    fn test_scan_rate_limiter() {
+        let daemon = MockDaemon::start_many(b"stream: OK\0", 3);
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_scan_rate_limiter(Arc::new(RateLimiter::with_burst(20, 1)));
+        let started = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(cclient.scan_bytes(b"data").unwrap(), ScanResult::Ok);
+        }
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert_eq!(daemon.all_received().len(), 3);
+    }
+
+    #[test]
+    fn test_circuit_breaker_fails_fast() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let breaker = Arc::new(CircuitBreaker::new(2, Duration::from_secs(60)));
+        let cclient = ClamClient::new("127.0.0.1", port)
+            .unwrap()
+            .with_circuit_breaker(Arc::clone(&breaker));
+
+        for _ in 0..2 {
+            assert!(matches!(
+                cclient.scan_bytes(b"data"),
+                Err(ClamError::ConnectionError(_))
+            ));
+        }
+        assert!(matches!(
+            cclient.scan_bytes(b"data"),
+            Err(ClamError::CircuitOpen(_))
+        ));
+        assert!(!cclient.ping());
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let daemon = MockDaemon::start(b"stream: Eicar FOUND\0");
+        let path = std::env::temp_dir().join(format!(
+            "clamav-client-audit-test-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let log = Arc::new(AuditLog::open(&path).unwrap());
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_audit_log(log);
+
+        assert!(cclient.scan_file("/nonexistent/clamav-client").is_err());
+        cclient.scan_bytes(b"data").unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines = written.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""input":"/nonexistent/clamav-client","verdict":"error""#));
+        assert!(lines[1].contains(r#""input":"stream","verdict":"found","signature":"Eicar""#));
+    }
+
+    #[test]
+    fn test_audit_log_bulk() {
+        let daemon = MockDaemon::start_session("stream: OK");
+        let path = std::env::temp_dir().join(format!(
+            "clamav-client-audit-bulk-test-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let log = Arc::new(AuditLog::open(&path).unwrap());
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_audit_log(log);
+
+        let inputs = vec![b"one".to_vec(), b"two".to_vec()];
+        assert_eq!(cclient.scan_bulk(inputs).count(), 2);
+        drop(cclient);
+        daemon.all_received();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines = written.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines
+            .iter()
+            .all(|line| line.contains(r#""input":"stream","verdict":"ok""#)));
+    }
+
+    #[test]
+    fn test_observer_events() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let (events, received) = std::sync::mpsc::channel();
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_observer(Arc::new(events));
+
+        cclient.scan_bytes(vec![0u8; 5000]).unwrap();
+        drop(cclient);
+
+        let events = received.iter().collect::<Vec<_>>();
+        assert_eq!(
+            events[..3],
+            [
+                ScanEvent::ScanStarted {
+                    scan: 1,
+                    input: String::from("stream"),
+                },
+                ScanEvent::ChunkSent {
+                    scan: 1,
+                    bytes: 4096,
+                    total: 4096,
+                },
+                ScanEvent::ChunkSent {
+                    scan: 1,
+                    bytes: 904,
+                    total: 5000,
+                },
+            ]
+        );
+        assert!(matches!(
+            events[3],
+            ScanEvent::ScanFinished {
+                scan: 1,
+                result: Ok(ScanResult::Ok),
+                ..
+            }
+        ));
+        assert_eq!(events.len(), 4);
+    }
+
+    #[test]
+    fn test_scan_bytes_early_reply() {
+        let daemon = MockDaemon::with_limit(b"INSTREAM size limit exceeded. ERROR\0", 100_000);
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let data = vec![0u8; 16 << 20];
+
+        assert_eq!(
+            cclient.scan_bytes(&data).unwrap(),
+            ScanResult::Error("INSTREAM size limit exceeded. ERROR".to_string())
+        );
+        assert!(daemon.received().payload().len() < data.len());
+    }
+
+    #[test]
+    fn test_scan_bytes_outcome() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let outcome = cclient.scan_bytes_outcome(vec![1u8; 5000]).unwrap();
+        assert_eq!(outcome.result, ScanResult::Ok);
+        assert_eq!(outcome.bytes_sent, 5000);
+        assert_eq!(outcome.endpoint, cclient.endpoint());
+        daemon.received();
+    }
+
+    #[test]
+    fn test_concurrency_limit_fail_fast() {
+        let limiter = Arc::new(ConcurrencyLimiter::fail_fast(1));
+        let cclient = ClamClient::new("127.0.0.1", 3310)
+            .unwrap()
+            .with_concurrency_limiter(Arc::clone(&limiter));
+
+        let _held = ConcurrencyLimiter::acquire(&limiter).unwrap();
+        match cclient.scan_bytes(b"data") {
+            Err(ClamError::ConcurrencyLimitReached(1)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn test_scan_bytes_cached() {
+        let cache = Arc::new(crate::cache::LruScanCache::new(8, Duration::from_secs(60)));
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port)
+            .unwrap()
+            .with_scan_cache(cache.clone());
+
+        assert_eq!(cclient.scan_bytes(b"payload").unwrap(), ScanResult::Ok);
+        daemon.received();
+
+        // the mock daemon is gone, so this can only be answered from cache
+        assert_eq!(cclient.scan_bytes(b"payload").unwrap(), ScanResult::Ok);
+        assert_eq!(cache.len(), 1);
+        let item = cclient
+            .scan_bytes_labeled(b"payload", "upload.bin")
+            .unwrap();
+        assert_eq!(item.subject, "upload.bin");
+    }
+
+    #[test]
+    fn test_scan_stream_cancelled_mid_upload() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let cclient = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let token = CancelToken::new();
+
+        let canceller = {
+            let token = token.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                token.cancel();
+            })
+        };
+
+        // never ends on its own
+        let result = cclient.scan_stream_cancellable(io::repeat(1), &token);
+        canceller.join().unwrap();
+
+        match result {
+            Err(ClamError::Cancelled) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 }
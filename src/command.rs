@@ -0,0 +1,192 @@
+use std::path::{Path, PathBuf};
+
+/// A clamd command. Commands are sent in the `z` form by default: prefixed
+/// with `z` and terminated by a NUL byte, which lets arguments contain
+/// newlines. See `Framing` for the `n` form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Ping,
+    Version,
+    Reload,
+    Shutdown,
+    Stats,
+    Scan(PathBuf),
+    ContScan(PathBuf),
+    MultiScan(PathBuf),
+    AllMatchScan(PathBuf),
+    /// Starts a stream; the payload follows as length-prefixed chunks.
+    Instream,
+    IdSession,
+    End,
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Ping => "PING",
+            Command::Version => "VERSION",
+            Command::Reload => "RELOAD",
+            Command::Shutdown => "SHUTDOWN",
+            Command::Stats => "STATS",
+            Command::Scan(_) => "SCAN",
+            Command::ContScan(_) => "CONTSCAN",
+            Command::MultiScan(_) => "MULTISCAN",
+            Command::AllMatchScan(_) => "ALLMATCHSCAN",
+            Command::Instream => "INSTREAM",
+            Command::IdSession => "IDSESSION",
+            Command::End => "END",
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Command::Scan(path)
+            | Command::ContScan(path)
+            | Command::MultiScan(path)
+            | Command::AllMatchScan(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Whether sending the command again has no further effect, so a failed
+    /// attempt can simply be repeated.
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            Command::Ping
+                | Command::Version
+                | Command::Stats
+                | Command::Scan(_)
+                | Command::ContScan(_)
+                | Command::MultiScan(_)
+                | Command::AllMatchScan(_)
+        )
+    }
+
+    /// The oldest clamd release that understands the command, for commands
+    /// that aren't supported by every daemon still in use.
+    pub fn since(&self) -> Option<(u64, u64, u64)> {
+        match self {
+            Command::AllMatchScan(_) => Some((0, 99, 0)),
+            _ => None,
+        }
+    }
+
+    /// Appends the wire form of the command to `buf`.
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        self.encode_framed_into(Framing::NulTerminated, buf)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        self.encode_framed(Framing::NulTerminated)
+    }
+
+    /// Appends the command to `buf` in the form of `framing`.
+    pub fn encode_framed_into(&self, framing: Framing, buf: &mut Vec<u8>) {
+        buf.push(framing.prefix());
+        buf.extend_from_slice(self.name().as_bytes());
+
+        if let Some(path) = self.path() {
+            buf.push(b' ');
+            buf.extend_from_slice(&path_bytes(path));
+        }
+
+        buf.push(framing.delimiter());
+    }
+
+    pub fn encode_framed(&self, framing: Framing) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_framed_into(framing, &mut buf);
+        buf
+    }
+}
+
+/// How commands and replies are delimited on the wire. clamd answers in the
+/// form it was asked in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Framing {
+    /// `zCOMMAND\0`, with replies terminated by NUL.
+    #[default]
+    NulTerminated,
+    /// `nCOMMAND\n`, with replies terminated by a newline, for proxies and
+    /// daemons that only handle line-based commands. Paths can't contain
+    /// newlines in this form.
+    NewlineTerminated,
+}
+
+impl Framing {
+    pub fn prefix(self) -> u8 {
+        match self {
+            Framing::NulTerminated => b'z',
+            Framing::NewlineTerminated => b'n',
+        }
+    }
+
+    /// The byte terminating each command and each reply.
+    pub fn delimiter(self) -> u8 {
+        match self {
+            Framing::NulTerminated => 0,
+            Framing::NewlineTerminated => b'\n',
+        }
+    }
+
+    /// Rewrites a reply to `command` into the NUL-terminated form the
+    /// parsers expect. STATS is left alone: its lines are newline-separated
+    /// either way and its parser accepts both terminators.
+    pub(crate) fn normalize(self, command: &Command, reply: &mut [u8]) {
+        if self == Framing::NulTerminated || *command == Command::Stats {
+            return;
+        }
+
+        for byte in reply.iter_mut().filter(|byte| **byte == b'\n') {
+            *byte = 0;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().into()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> std::borrow::Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        std::borrow::Cow::Borrowed(s) => s.as_bytes().into(),
+        std::borrow::Cow::Owned(s) => s.into_bytes().into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(Command::Ping.encode(), b"zPING\0");
+        assert_eq!(Command::Instream.encode(), b"zINSTREAM\0");
+        assert_eq!(
+            Command::ContScan(PathBuf::from("/tmp/a b")).encode(),
+            b"zCONTSCAN /tmp/a b\0"
+        );
+
+        let mut buf = Command::Ping.encode();
+        Command::End.encode_into(&mut buf);
+        assert_eq!(buf, b"zPING\0zEND\0");
+    }
+
+    #[test]
+    fn test_encode_newline_terminated() {
+        let framing = Framing::NewlineTerminated;
+        assert_eq!(Command::Ping.encode_framed(framing), b"nPING\n");
+        assert_eq!(
+            Command::Scan(PathBuf::from("/tmp/a")).encode_framed(framing),
+            b"nSCAN /tmp/a\n"
+        );
+
+        let mut reply = b"/tmp/a: OK\n/tmp/b: Eicar FOUND\n".to_vec();
+        framing.normalize(&Command::Scan(PathBuf::from("/tmp")), &mut reply);
+        assert_eq!(reply, b"/tmp/a: OK\0/tmp/b: Eicar FOUND\0");
+    }
+}
@@ -0,0 +1,266 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::client::{ClamClient, Result, DEFAULT_CHUNK_SIZE};
+use crate::error::ClamError;
+use crate::limit::ConcurrencyLimiter;
+use crate::pool::{BufferPool, DEFAULT_BUFFER_CAPACITY};
+use crate::retry::ReloadRetry;
+
+/// Client settings as they'd appear in an application's config file, see
+/// `ClamClient::from_config`. Missing fields take their defaults, so
+///
+/// ```toml
+/// [clamav]
+/// socket = "/run/clamav/clamd.ctl"
+/// ```
+///
+/// is a complete configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct ClientConfig {
+    /// clamd's TCP host, `127.0.0.1` unless `socket` is set.
+    pub host: Option<String>,
+    pub port: u16,
+    /// clamd's Unix socket, or named pipe on Windows, used instead of TCP.
+    pub socket: Option<PathBuf>,
+    /// Connect timeout for TCP, in seconds.
+    pub timeout_secs: Option<u64>,
+    /// How long to wait for a reply, in seconds, see
+    /// `ClamClient::with_read_timeout`.
+    pub read_timeout_secs: Option<u64>,
+    /// INSTREAM frame size in bytes, see `ClamClient::with_chunk_size`.
+    pub chunk_size: usize,
+    /// How often a command answered with `RELOADING` is retried.
+    pub reload_retries: u32,
+    /// The first wait before such a retry, in milliseconds.
+    pub reload_backoff_ms: u64,
+    /// How many idle buffers the client's buffer pool keeps.
+    pub pool_size: usize,
+    /// How many scans may run against the daemon at once, unlimited if
+    /// unset, see `ClamClient::with_concurrency_limiter`.
+    pub max_concurrent_scans: Option<usize>,
+    /// How long a scan waits for one of those slots, in milliseconds,
+    /// before failing with `ConcurrencyLimitReached`. Scans wait as long as
+    /// it takes if unset.
+    pub acquire_timeout_ms: Option<u64>,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        let retry = ReloadRetry::default();
+        Self {
+            host: None,
+            port: 3310,
+            socket: None,
+            timeout_secs: None,
+            read_timeout_secs: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            reload_retries: retry.retries,
+            reload_backoff_ms: retry.backoff.as_millis() as u64,
+            pool_size: 64,
+            max_concurrent_scans: None,
+            acquire_timeout_ms: None,
+        }
+    }
+}
+
+/// Why a `ClientConfig` was rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConfigError {
+    #[error("both a TCP host and a socket are set")]
+    ConflictingEndpoints,
+    #[error("a timeout is zero")]
+    ZeroTimeout,
+    #[error("chunk size is zero")]
+    ZeroChunkSize,
+    #[error("chunk size {0} does not fit an INSTREAM frame length")]
+    ChunkSizeTooLarge(usize),
+    #[error("buffer pool size is zero")]
+    ZeroPoolSize,
+    #[error("concurrency limit is zero")]
+    ZeroConcurrency,
+    #[error("an acquire timeout is set without a concurrency limit")]
+    AcquireTimeoutWithoutLimit,
+}
+
+impl ClientConfig {
+    /// Checks that the settings make sense together, which
+    /// `ClamClient::from_config` does before connecting anywhere.
+    pub fn validate(&self) -> std::result::Result<(), ConfigError> {
+        if self.host.is_some() && self.socket.is_some() {
+            return Err(ConfigError::ConflictingEndpoints);
+        }
+        if self.timeout_secs == Some(0) || self.read_timeout_secs == Some(0) {
+            return Err(ConfigError::ZeroTimeout);
+        }
+        if self.chunk_size == 0 {
+            return Err(ConfigError::ZeroChunkSize);
+        }
+        if self.chunk_size as u64 > u32::MAX as u64 {
+            return Err(ConfigError::ChunkSizeTooLarge(self.chunk_size));
+        }
+        if self.pool_size == 0 {
+            return Err(ConfigError::ZeroPoolSize);
+        }
+        if self.max_concurrent_scans == Some(0) {
+            return Err(ConfigError::ZeroConcurrency);
+        }
+        if self.acquire_timeout_ms.is_some() && self.max_concurrent_scans.is_none() {
+            return Err(ConfigError::AcquireTimeoutWithoutLimit);
+        }
+        Ok(())
+    }
+}
+
+impl ClamClient {
+    /// Builds a client from `config`. Fails with `ClamError::InvalidConfig`
+    /// if it doesn't validate, or if the host doesn't resolve.
+    pub fn from_config(config: &ClientConfig) -> Result<Self> {
+        if let Err(e) = config.validate() {
+            return Err(ClamError::InvalidConfig(e));
+        }
+
+        let host = config.host.as_deref().unwrap_or("127.0.0.1");
+        let client = match &config.socket {
+            #[cfg(unix)]
+            Some(socket) => ClamClient::new_unix(socket),
+            #[cfg(windows)]
+            Some(socket) => ClamClient::new_named_pipe(socket),
+            #[cfg(not(any(unix, windows)))]
+            Some(socket) => {
+                return Err(ClamError::InvalidData(format!(
+                    "sockets are not supported on this platform: {}",
+                    socket.display()
+                )))
+            }
+            None => match config.timeout_secs {
+                Some(timeout) => ClamClient::new_with_timeout(host, config.port, timeout)?,
+                None => ClamClient::new(host, config.port)?,
+            },
+        };
+
+        let pool = BufferPool::new(DEFAULT_BUFFER_CAPACITY, config.pool_size);
+        let client = match config.read_timeout_secs {
+            Some(timeout) => client.with_read_timeout(Duration::from_secs(timeout)),
+            None => client,
+        };
+        let client = match config.max_concurrent_scans {
+            Some(max) => {
+                let limiter = match config.acquire_timeout_ms {
+                    Some(timeout) => ConcurrencyLimiter::new(max)
+                        .with_acquire_timeout(Duration::from_millis(timeout)),
+                    None => ConcurrencyLimiter::new(max),
+                };
+                client.with_concurrency_limiter(Arc::new(limiter))
+            }
+            None => client,
+        };
+
+        Ok(client
+            .with_chunk_size(config.chunk_size)
+            .with_reload_retry(ReloadRetry::new(
+                config.reload_retries,
+                Duration::from_millis(config.reload_backoff_ms),
+            ))
+            .with_buffer_pool(Arc::new(pool)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDaemon;
+    use crate::response::ScanResult;
+    use serde::de::value::{Error, MapDeserializer};
+    use serde::Deserialize;
+
+    #[test]
+    fn test_missing_fields_default() {
+        let fields = vec![("port", 3311u16)];
+        let config =
+            ClientConfig::deserialize(MapDeserializer::<_, Error>::new(fields.into_iter()))
+                .unwrap();
+
+        assert_eq!(
+            config,
+            ClientConfig {
+                port: 3311,
+                ..ClientConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_config() {
+        let invalid = |config: ClientConfig| match ClamClient::from_config(&config) {
+            Err(ClamError::InvalidConfig(e)) => e,
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        };
+
+        let both = ClientConfig {
+            host: Some(String::from("localhost")),
+            socket: Some(PathBuf::from("/run/clamav/clamd.ctl")),
+            ..ClientConfig::default()
+        };
+        assert_eq!(invalid(both), ConfigError::ConflictingEndpoints);
+
+        let zero_chunks = ClientConfig {
+            chunk_size: 0,
+            ..ClientConfig::default()
+        };
+        assert_eq!(invalid(zero_chunks), ConfigError::ZeroChunkSize);
+
+        let no_pool = ClientConfig {
+            pool_size: 0,
+            ..ClientConfig::default()
+        };
+        assert_eq!(invalid(no_pool), ConfigError::ZeroPoolSize);
+
+        let unlimited_timeout = ClientConfig {
+            acquire_timeout_ms: Some(100),
+            ..ClientConfig::default()
+        };
+        assert_eq!(
+            invalid(unlimited_timeout),
+            ConfigError::AcquireTimeoutWithoutLimit
+        );
+    }
+
+    #[test]
+    fn test_from_config() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let config = ClientConfig {
+            port: daemon.port,
+            chunk_size: 3,
+            ..ClientConfig::default()
+        };
+        let client = ClamClient::from_config(&config).unwrap();
+
+        assert_eq!(client.scan_bytes(b"abcdefg").unwrap(), ScanResult::Ok);
+        assert_eq!(daemon.received().chunks, [&b"abc"[..], b"def", b"g"]);
+    }
+
+    #[test]
+    fn test_from_config_concurrency() {
+        let daemon = MockDaemon::start_session("PONG");
+        let config = ClientConfig {
+            port: daemon.port,
+            max_concurrent_scans: Some(1),
+            acquire_timeout_ms: Some(20),
+            ..ClientConfig::default()
+        };
+        let client = ClamClient::from_config(&config).unwrap();
+
+        let session = client.session().unwrap();
+        match client.scan_bytes(b"data") {
+            Err(ClamError::ConcurrencyLimitReached(1)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        session.end().unwrap();
+        daemon.all_received();
+    }
+}
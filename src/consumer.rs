@@ -0,0 +1,238 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::client::{ClamClient, Result};
+use crate::error::ClamError;
+use crate::response::ScanResult;
+use crate::service::ScanJob;
+
+// how often idle workers check for a shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Scans jobs taken from a channel and hands every verdict to a handler,
+/// for services that mostly consist of that loop. Each job comes with a tag
+/// of the caller's choosing, e.g. an upload id, which is passed back with
+/// its verdict.
+///
+/// Jobs failing for reasons that may pass, such as a refused connection, a
+/// timeout or an open circuit breaker, are scanned again after a backoff;
+/// readers can't be sent twice and are never retried.
+///
+/// ```no_run
+/// use std::sync::mpsc;
+/// use clamav::{ClamClient, ScanConsumer, ScanJob};
+///
+/// let (jobs, queue) = mpsc::channel();
+/// let client = ClamClient::new("127.0.0.1", 3310).unwrap();
+/// let consumer = ScanConsumer::new(client)
+///     .workers(4)
+///     .start(queue, |upload: u64, result| println!("{}: {:?}", upload, result));
+///
+/// jobs.send((1, ScanJob::Bytes(b"upload".to_vec()))).unwrap();
+/// drop(jobs);
+/// consumer.wait();
+/// ```
+pub struct ScanConsumer {
+    client: ClamClient,
+    workers: usize,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl ScanConsumer {
+    /// One worker, retrying twice after 1 and 2 seconds.
+    pub fn new(client: ClamClient) -> Self {
+        Self {
+            client,
+            workers: 1,
+            retries: 2,
+            backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Scans up to `workers` jobs at a time.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Retries a failed job up to `retries` times, waiting `backoff` before
+    /// the first retry and twice as long before each further one.
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Starts consuming `jobs` until every sender has hung up or the
+    /// consumer is shut down. `handler` is called on the worker threads.
+    pub fn start<T, H>(self, jobs: Receiver<(T, ScanJob)>, handler: H) -> ConsumerHandle
+    where
+        T: Send + 'static,
+        H: Fn(T, Result<ScanResult>) + Send + Sync + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let shared = Arc::new(Shared {
+            consumer: self,
+            jobs: Mutex::new(jobs),
+            handler,
+            stop: Arc::clone(&stop),
+        });
+
+        let workers = (0..shared.consumer.workers)
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || shared.work())
+            })
+            .collect();
+
+        ConsumerHandle { stop, workers }
+    }
+
+    fn scan(&self, mut job: ScanJob) -> Result<ScanResult> {
+        let mut backoff = self.backoff;
+
+        for _ in 0..self.retries {
+            let retry = match job.try_clone() {
+                Some(retry) => retry,
+                None => break,
+            };
+
+            match job.scan(&self.client) {
+                Err(e) if is_transient(&e) => {
+                    thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                    job = retry;
+                }
+                result => return result,
+            }
+        }
+
+        job.scan(&self.client)
+    }
+}
+
+/// Whether a job that failed with `e` may succeed if scanned again.
+fn is_transient(e: &ClamError) -> bool {
+    matches!(
+        e,
+        ClamError::ConnectionError(_)
+            | ClamError::CommandError(_)
+            | ClamError::Timeout(_)
+            | ClamError::TruncatedResponse(_)
+            | ClamError::CircuitOpen(_)
+            | ClamError::ConcurrencyLimitReached(_)
+    )
+}
+
+struct Shared<T, H> {
+    consumer: ScanConsumer,
+    jobs: Mutex<Receiver<(T, ScanJob)>>,
+    handler: H,
+    stop: Arc<AtomicBool>,
+}
+
+impl<T, H: Fn(T, Result<ScanResult>)> Shared<T, H> {
+    fn work(&self) {
+        while !self.stop.load(Ordering::SeqCst) {
+            let next = match self.jobs.lock() {
+                Ok(jobs) => jobs.recv_timeout(POLL_INTERVAL),
+                Err(_) => return,
+            };
+
+            match next {
+                Ok((tag, job)) => (self.handler)(tag, self.consumer.scan(job)),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+/// A running `ScanConsumer`. Dropping it shuts the consumer down.
+pub struct ConsumerHandle {
+    stop: Arc<AtomicBool>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ConsumerHandle {
+    /// Waits until every sender has hung up and the jobs left in the
+    /// channel have been handled.
+    pub fn wait(mut self) {
+        self.join();
+    }
+
+    /// Stops taking jobs from the channel and waits for the ones being
+    /// scanned to be handled. Jobs still queued are left in the channel.
+    pub fn shutdown(mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.join();
+    }
+
+    fn join(&mut self) {
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for ConsumerHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryTransport;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_consumer_retries_and_handles_jobs() {
+        let transport = MemoryTransport::new()
+            .refuse()
+            .reply(b"stream: OK\0")
+            .reply(b"stream: Eicar-Test FOUND\0")
+            .refuse();
+        let client = ClamClient::new_memory(transport.clone());
+        let (jobs, queue) = mpsc::channel();
+        let (handled, results) = mpsc::channel();
+
+        let consumer = ScanConsumer::new(client).retries(1, Duration::ZERO).start(
+            queue,
+            move |tag, result: Result<ScanResult>| {
+                let _ = handled.send((tag, result.map_err(|e| e.to_string())));
+            },
+        );
+        jobs.send(("one", ScanJob::Bytes(b"one".to_vec()))).unwrap();
+        jobs.send(("two", ScanJob::Bytes(b"two".to_vec()))).unwrap();
+        jobs.send(("three", ScanJob::Reader(Box::new(&b"three"[..]))))
+            .unwrap();
+        drop(jobs);
+        consumer.wait();
+
+        let results = results.iter().collect::<Vec<_>>();
+        assert_eq!(results[0], ("one", Ok(ScanResult::Ok)));
+        assert_eq!(results[1].0, "two");
+        assert!(matches!(results[1].1, Ok(ScanResult::Found(..))));
+        // a reader is only tried once
+        assert_eq!(results[2].0, "three");
+        assert!(results[2].1.is_err());
+        assert_eq!(transport.received().len(), 2);
+    }
+
+    #[test]
+    fn test_consumer_shutdown() {
+        let client = ClamClient::new_memory(MemoryTransport::new());
+        let (_jobs, queue) = mpsc::channel::<((), ScanJob)>();
+        let consumer = ScanConsumer::new(client).workers(2).start(queue, |_, _| {});
+
+        // returns although the sender is still around
+        consumer.shutdown();
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// What a client has done on the wire since it was created, see
+/// `ClamClient::stats_snapshot`. Not to be confused with `Stats`, the
+/// daemon's own STATS reply.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ClientStats {
+    pub connections_opened: u64,
+    /// Commands sent over a session connection that was already open,
+    /// saving a connection each.
+    pub connections_reused: u64,
+    /// Connection attempts that failed, counting every address tried.
+    pub connections_failed: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Every command sent, including the INSTREAM, IDSESSION and END that
+    /// scans and sessions send themselves.
+    pub commands: u64,
+}
+
+/// The live counters behind `ClientStats`, shared with the writers and
+/// sessions of a client.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    connections_opened: AtomicU64,
+    connections_reused: AtomicU64,
+    connections_failed: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    commands: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn connection_opened(&self) {
+        self.connections_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_reused(&self) {
+        self.connections_reused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_failed(&self) {
+        self.connections_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sent(&self, bytes: u64) {
+        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn received(&self, bytes: u64) {
+        self.bytes_received.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn command(&self) {
+        self.commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> ClientStats {
+        ClientStats {
+            connections_opened: self.connections_opened.load(Ordering::Relaxed),
+            connections_reused: self.connections_reused.load(Ordering::Relaxed),
+            connections_failed: self.connections_failed.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            commands: self.commands.load(Ordering::Relaxed),
+        }
+    }
+}
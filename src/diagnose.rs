@@ -0,0 +1,304 @@
+use std::fmt;
+use std::io::ErrorKind;
+
+use crate::client::ClamClient;
+use crate::command::Command;
+use crate::error::ClamError;
+use crate::transport::{Connection, Endpoint};
+
+// daily updates are the norm, so a week without one means they stopped
+#[cfg(feature = "chrono")]
+const STALE_AFTER_DAYS: i64 = 7;
+
+/// How a check of `ClamClient::diagnose` went.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Severity {
+    Pass,
+    /// Works, but needs attention.
+    Warn,
+    Fail,
+}
+
+/// The outcome of one check, with what was found and, for problems, what
+/// usually causes them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    // e.g. `socket`, `connect`, `ping`, `database`
+    pub check: String,
+    pub severity: Severity,
+    pub detail: String,
+}
+
+impl Finding {
+    fn new(check: &str, severity: Severity, detail: String) -> Self {
+        Self {
+            check: check.to_string(),
+            severity,
+            detail,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Pass => "ok",
+            Severity::Warn => "warning",
+            Severity::Fail => "FAILED",
+        };
+        write!(f, "{:<8} {:<8} {}", severity, self.check, self.detail)
+    }
+}
+
+impl ClamClient {
+    /// Checks step by step what it takes to scan with this client, down to
+    /// where it breaks: that the socket file exists and is a socket, that
+    /// each endpoint accepts connections, that the daemon answers PING and
+    /// that it has a signature database loaded, warning if the database is
+    /// more than a week old. Checks that depend on a
+    /// failed one are left out.
+    ///
+    /// ```no_run
+    /// use clamav::ClamClient;
+    ///
+    /// let client = ClamClient::new_unix("/run/clamav/clamd.ctl");
+    /// for finding in client.diagnose() {
+    ///     println!("{}", finding);
+    /// }
+    /// ```
+    pub fn diagnose(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let mut reachable = false;
+
+        for endpoint in self.candidates() {
+            if !check_socket_file(&endpoint, &mut findings) {
+                continue;
+            }
+            reachable |= self.check_connect(&endpoint, &mut findings);
+        }
+
+        if reachable && self.check_ping(&mut findings) {
+            self.check_database(&mut findings);
+        }
+        findings
+    }
+
+    fn check_connect(&self, endpoint: &Endpoint, findings: &mut Vec<Finding>) -> bool {
+        let connected = match Connection::open(endpoint, self.connect_timeout()) {
+            Ok(connection) => self.verify_peer(&connection),
+            Err(e) => Err(ClamError::ConnectionError(e)),
+        };
+
+        let (severity, detail) = match connected {
+            Ok(_) => (Severity::Pass, format!("{} accepts connections", endpoint)),
+            Err(ClamError::ConnectionError(e)) => {
+                let hint = match (e.kind(), endpoint) {
+                    (ErrorKind::ConnectionRefused, Endpoint::Tcp(_)) => {
+                        "; nothing listens there, check that clamd runs and its TCPSocket and TCPAddr"
+                    }
+                    (ErrorKind::ConnectionRefused, _) => {
+                        "; nothing listens on the socket, which may be left over from a clamd that exited"
+                    }
+                    (ErrorKind::PermissionDenied, _) => {
+                        "; this user may not write to the socket, see LocalSocketGroup and LocalSocketMode"
+                    }
+                    (ErrorKind::TimedOut, _) => {
+                        "; packets go unanswered, which usually means a firewall drops them"
+                    }
+                    _ => "",
+                };
+                (
+                    Severity::Fail,
+                    format!("cannot connect to {}: {}{}", endpoint, e, hint),
+                )
+            }
+            Err(e) => (Severity::Fail, format!("{}: {}", endpoint, e)),
+        };
+
+        findings.push(Finding::new("connect", severity, detail));
+        severity == Severity::Pass
+    }
+
+    fn check_ping(&self, findings: &mut Vec<Finding>) -> bool {
+        let reply = self.command_typed::<String>(&Command::Ping);
+        let finding = match reply.as_ref().map(|r| r.trim_end_matches('\0')) {
+            Ok("PONG") => Finding::new("ping", Severity::Pass, String::from("PING answered")),
+            Ok(reply) => Finding::new(
+                "ping",
+                Severity::Fail,
+                format!(
+                    "PING answered with {:?}; is this port served by clamd?",
+                    reply
+                ),
+            ),
+            Err(e) => Finding::new(
+                "ping",
+                Severity::Fail,
+                format!("PING failed: {}; see clamd's log", e),
+            ),
+        };
+
+        findings.push(finding.clone());
+        finding.severity == Severity::Pass
+    }
+
+    fn check_database(&self, findings: &mut Vec<Finding>) {
+        let finding = match self.version() {
+            Ok(version) if version.build_number == 0 => Finding::new(
+                "database",
+                Severity::Fail,
+                String::from("no signature database loaded; run freshclam and RELOAD"),
+            ),
+            #[cfg(feature = "chrono")]
+            Ok(version)
+                if version.release_date.is_some_and(|date| {
+                    chrono::Utc::now() - date > chrono::Duration::days(STALE_AFTER_DAYS)
+                }) =>
+            {
+                Finding::new(
+                    "database",
+                    Severity::Warn,
+                    format!(
+                        "signatures are over {} days old: {}; is freshclam running?",
+                        STALE_AFTER_DAYS, version
+                    ),
+                )
+            }
+            Ok(version) => Finding::new(
+                "database",
+                Severity::Pass,
+                format!("signatures loaded: {}", version),
+            ),
+            Err(e) => Finding::new(
+                "database",
+                Severity::Fail,
+                format!(
+                    "VERSION didn't report a database ({}); run freshclam and RELOAD",
+                    e
+                ),
+            ),
+        };
+
+        findings.push(finding);
+    }
+}
+
+/// Whether there is something to connect to at a Unix socket endpoint;
+/// other endpoints pass without a finding.
+#[cfg(unix)]
+fn check_socket_file(endpoint: &Endpoint, findings: &mut Vec<Finding>) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    let path = match endpoint {
+        Endpoint::Unix(path) => path,
+        _ => return true,
+    };
+    // names in the abstract namespace have no file
+    let name = path.to_string_lossy();
+    if cfg!(target_os = "linux") && (name.starts_with('@') || name.starts_with('\0')) {
+        return true;
+    }
+
+    let (severity, detail) = match std::fs::metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            (Severity::Pass, format!("{} is a socket", path.display()))
+        }
+        Ok(_) => (
+            Severity::Fail,
+            format!("{} exists but is not a socket", path.display()),
+        ),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => (
+            Severity::Fail,
+            format!(
+                "{} does not exist; check that clamd runs and its LocalSocket",
+                path.display()
+            ),
+        ),
+        Err(e) => (
+            Severity::Fail,
+            format!("cannot inspect {}: {}", path.display(), e),
+        ),
+    };
+
+    findings.push(Finding::new("socket", severity, detail));
+    severity == Severity::Pass
+}
+
+#[cfg(not(unix))]
+fn check_socket_file(_: &Endpoint, _: &mut Vec<Finding>) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryTransport;
+    use crate::mock::MockDaemon;
+
+    fn severities(findings: &[Finding]) -> Vec<(&str, Severity)> {
+        findings
+            .iter()
+            .map(|f| (f.check.as_str(), f.severity))
+            .collect()
+    }
+
+    #[test]
+    fn test_diagnose_healthy_daemon() {
+        let transport = MemoryTransport::new()
+            .reply(b"")
+            .reply(b"PONG\0")
+            .reply(b"ClamAV 1.0.0/24802/Mon Jan  1 00:00:00 2024\0");
+        let findings = ClamClient::new_memory(transport).diagnose();
+        // with dates parsed, the 2024 database is found to be stale
+        let database = match cfg!(feature = "chrono") {
+            true => Severity::Warn,
+            false => Severity::Pass,
+        };
+
+        assert_eq!(
+            severities(&findings),
+            vec![
+                ("connect", Severity::Pass),
+                ("ping", Severity::Pass),
+                ("database", database),
+            ]
+        );
+        assert!(findings[2].detail.contains("24802"));
+    }
+
+    #[test]
+    fn test_diagnose_stops_at_failure() {
+        // bound and released, so most likely nothing listens there
+        let port = {
+            let daemon = MockDaemon::start(b"");
+            let port = daemon.port;
+            std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+            daemon.received();
+            port
+        };
+        let findings = ClamClient::new("127.0.0.1", port).unwrap().diagnose();
+        assert_eq!(severities(&findings), vec![("connect", Severity::Fail)]);
+        assert!(findings[0].detail.contains("TCPSocket"));
+
+        let transport = MemoryTransport::new()
+            .reply(b"")
+            .reply(b"PONG\0")
+            .reply(b"ClamAV 1.0.0\0");
+        let findings = ClamClient::new_memory(transport).diagnose();
+        assert_eq!(findings[2].severity, Severity::Fail);
+        assert!(findings[2].detail.contains("freshclam"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_diagnose_missing_socket() {
+        let path = std::env::temp_dir().join(format!(
+            "clamav-client-diagnose-missing-{}.sock",
+            std::process::id()
+        ));
+        let findings = ClamClient::new_unix(&path).diagnose();
+
+        assert_eq!(severities(&findings), vec![("socket", Severity::Fail)]);
+        assert!(findings[0].detail.contains("LocalSocket"));
+    }
+}
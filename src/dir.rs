@@ -0,0 +1,805 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::client::{ClamClient, Result};
+use crate::error::ClamError;
+use crate::filter::FileFilter;
+use crate::notify::{Detection, Webhook};
+use crate::policy::Action;
+use crate::report::ScanReport;
+use crate::response::ScanResult;
+use crate::session::{ClamSession, Pending, ScanInput};
+use crate::skip::{SkipCache, Stamp};
+
+type Progress<'a> = Box<dyn FnMut(&Path, std::result::Result<&ScanResult, &ClamError>) + 'a>;
+
+/// What a directory scan does with symbolic links.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum SymlinkPolicy {
+    /// Leaves links out, listing them in `ScanReport::skipped`.
+    #[default]
+    Skip,
+    /// Scans the files links point to, leaving out links to directories.
+    FollowFiles,
+    /// Follows every link. Each directory is walked once, under the first
+    /// path it is reached by, so links back up the tree can't loop.
+    Follow,
+}
+
+/// Streams every regular file below a directory to the daemon, over one
+/// session per job. Files are visited in sorted path order, which is what
+/// makes a scan resumable: with a checkpoint file configured, the last path
+/// up to which every file has completed is recorded after every file and a
+/// later run skips everything up to it.
+///
+/// Files that can't be read are recorded in the report; daemon or connection
+/// failures abort the run, keeping the checkpoint so it can be resumed.
+pub struct DirScan<'a> {
+    client: &'a ClamClient,
+    root: PathBuf,
+    checkpoint: Option<PathBuf>,
+    jobs: usize,
+    progress: Option<Progress<'a>>,
+    webhook: Option<Webhook>,
+    action: Option<Action>,
+    filter: FileFilter,
+    symlinks: SymlinkPolicy,
+    skip_cache: Option<PathBuf>,
+}
+
+impl<'a> DirScan<'a> {
+    pub fn new<P: AsRef<Path>>(client: &'a ClamClient, root: P) -> Self {
+        Self {
+            client,
+            root: root.as_ref().to_path_buf(),
+            checkpoint: None,
+            jobs: 1,
+            progress: None,
+            webhook: None,
+            action: None,
+            filter: FileFilter::default(),
+            symlinks: SymlinkPolicy::default(),
+            skip_cache: None,
+        }
+    }
+
+    /// Persists progress in `path`, resuming from it if it already exists.
+    /// The file is removed once the scan completes.
+    pub fn checkpoint<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.checkpoint = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Scans `jobs` files at a time, each job over its own connection. The
+    /// report still lists files in path order.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Calls `f` as each file completes, in completion order, with its
+    /// verdict or the error that kept it from being scanned.
+    pub fn on_progress<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&Path, std::result::Result<&ScanResult, &ClamError>) + 'a,
+    {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    /// Posts every detection to `webhook`. Failed notifications don't stop
+    /// the scan; they are listed in the report.
+    pub fn notify(mut self, webhook: Webhook) -> Self {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    /// Applies `action` to every file something is found in, after any
+    /// notification. What was done is listed in the report.
+    pub fn on_found(mut self, action: Action) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    /// Scans only the files `filter` accepts.
+    pub fn filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Handles symbolic links below the root as `policy` says, by default
+    /// leaving them out.
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Remembers in `path` the size and modification time of every file
+    /// found clean, and the build of the signature database that scanned
+    /// it. Later runs against the same build skip files that haven't
+    /// changed since, listing them in `ScanReport::unchanged`; a database
+    /// update invalidates the whole cache.
+    pub fn skip_cache<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.skip_cache = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn run(mut self) -> Result<ScanReport> {
+        let resume_after = match &self.checkpoint {
+            Some(checkpoint) => read_checkpoint(checkpoint)?,
+            None => None,
+        };
+
+        let mut report = ScanReport {
+            resumed_after: resume_after.clone(),
+            ..ScanReport::default()
+        };
+
+        let cache = match &self.skip_cache {
+            Some(path) => Some((SkipCache::open(path)?, self.client.version()?.build_number)),
+            None => None,
+        };
+
+        let mut walk = Walk::new(&self.root, resume_after.map(|p| self.root.join(p)))
+            .with_filter(self.filter.clone())
+            .with_symlinks(self.symlinks);
+        if let Some((cache, build)) = cache {
+            walk = walk.with_cache(cache, build);
+        }
+        let walk = Mutex::new((0, walk));
+        let stop = AtomicBool::new(false);
+        let (done, completed) = mpsc::channel();
+        let mut clean = Vec::new();
+        let client = self.client;
+        let db_version = match self.webhook {
+            Some(_) => client.version().ok().map(|version| version.to_string()),
+            None => None,
+        };
+
+        let failure = thread::scope(|scope| {
+            for _ in 0..self.jobs {
+                let done = done.clone();
+                let files = SharedWalk {
+                    walk: &walk,
+                    stop: &stop,
+                };
+                scope.spawn(move || {
+                    for (file, result) in client.scan_iter(files) {
+                        if done.send((file, result)).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+            drop(done);
+
+            self.collect(completed, &stop, db_version, &mut report, &mut clean)
+        });
+
+        let (_, mut walk) = walk.into_inner().unwrap_or_else(|e| e.into_inner());
+        // files found clean stay cached even if the scan was cut short
+        let saved = match walk.cache.take() {
+            Some((mut cache, build)) => {
+                for (path, stamp) in clean {
+                    cache.record(path, stamp, build);
+                }
+                cache.save(build)
+            }
+            None => Ok(()),
+        };
+        if let Some(e) = failure {
+            return Err(e);
+        }
+        saved?;
+
+        report
+            .errors
+            .extend(walk.errors.into_iter().map(|(p, e)| (p, e.to_string())));
+        report.skipped = walk.skipped;
+        report.unchanged = walk.unchanged;
+
+        if let Some(checkpoint) = &self.checkpoint {
+            remove_checkpoint(checkpoint)?;
+        }
+
+        Ok(report)
+    }
+
+    /// Adds completed files to the report in walk order, advancing the
+    /// checkpoint over the prefix of files that have all completed. Returns
+    /// the error that stopped the scan, if any.
+    fn collect(
+        &mut self,
+        completed: mpsc::Receiver<(WalkedFile, Result<ScanResult>)>,
+        stop: &AtomicBool,
+        db_version: Option<String>,
+        report: &mut ScanReport,
+        clean: &mut Vec<(PathBuf, Stamp)>,
+    ) -> Option<ClamError> {
+        let mut next = 0;
+        let mut out_of_order = BTreeMap::new();
+        let mut failure = None;
+
+        for (file, result) in completed {
+            if let Some(progress) = &mut self.progress {
+                progress(&file.path, result.as_ref());
+            }
+
+            if let (Some(webhook), Ok(ScanResult::Found(_, signature, _))) =
+                (&self.webhook, &result)
+            {
+                let detection = Detection::new(&file.path.to_string_lossy(), signature)
+                    .with_db_version(db_version.clone());
+                if let Err(e) = webhook.notify(&detection) {
+                    report
+                        .notify_errors
+                        .push((file.path.clone(), e.to_string()));
+                }
+            }
+
+            if let (Some(action), Some(handle), Ok(ScanResult::Found(_, signature, _))) =
+                (&self.action, &file.handle, &result)
+            {
+                let taken = action.apply(&file.path, handle, signature);
+                report.actions.push((file.path.clone(), taken));
+            }
+
+            if let (Some(stamp), Ok(ScanResult::Ok)) = (file.stamp, &result) {
+                clean.push((file.path.clone(), stamp));
+            }
+
+            match result {
+                Ok(_) | Err(ClamError::StreamError(_)) if failure.is_none() => {
+                    out_of_order.insert(file.index, (file.path, file.elapsed, result));
+                }
+                Ok(_) | Err(ClamError::StreamError(_)) => {}
+                Err(e) => {
+                    stop.store(true, Ordering::SeqCst);
+                    failure.get_or_insert(e);
+                }
+            }
+
+            let mut last = None;
+            while let Some((path, elapsed, result)) = out_of_order.remove(&next) {
+                report.timings.push((path.clone(), elapsed));
+                match result {
+                    Ok(result) => report.results.push((path.clone(), result)),
+                    Err(e) => report.errors.push((path.clone(), e.to_string())),
+                }
+                last = Some(path);
+                next += 1;
+            }
+
+            if let (Some(checkpoint), Some(path)) = (&self.checkpoint, last) {
+                let relative = path.strip_prefix(&self.root).unwrap_or(&path);
+                if let Err(e) = write_checkpoint(checkpoint, relative) {
+                    stop.store(true, Ordering::SeqCst);
+                    failure.get_or_insert(e);
+                }
+            }
+        }
+
+        failure
+    }
+}
+
+/// A file from the walk, numbered in walk order.
+struct WalkedFile {
+    index: usize,
+    path: PathBuf,
+    // kept open after the scan so actions hit the scanned file
+    handle: Option<File>,
+    // taken from the handle, so a file changed after the scan isn't cached
+    // as clean
+    stamp: Option<Stamp>,
+    elapsed: Duration,
+}
+
+impl ScanInput for WalkedFile {
+    fn scan_in(&mut self, session: &mut ClamSession<'_>) -> Result<ScanResult> {
+        let started = Instant::now();
+        let result = match File::open(&self.path) {
+            Ok(file) => {
+                self.stamp = file.metadata().ok().as_ref().and_then(Stamp::of);
+                session.scan_open_file(self.handle.insert(file), &self.path)
+            }
+            // recorded by the session as any other unreadable file
+            Err(_) => session.scan_file(&self.path),
+        };
+
+        self.elapsed = started.elapsed();
+        result
+    }
+
+    fn submit_in(&mut self, session: &mut ClamSession<'_>) -> Result<Pending<ScanResult>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) => return Err(ClamError::StreamError(e)),
+        };
+        self.stamp = file.metadata().ok().as_ref().and_then(Stamp::of);
+        session.submit_stream(&*self.handle.insert(file))
+    }
+}
+
+/// Hands out the files of one walk to several jobs.
+struct SharedWalk<'w> {
+    walk: &'w Mutex<(usize, Walk)>,
+    stop: &'w AtomicBool,
+}
+
+impl Iterator for SharedWalk<'_> {
+    type Item = WalkedFile;
+
+    fn next(&mut self) -> Option<WalkedFile> {
+        if self.stop.load(Ordering::SeqCst) {
+            return None;
+        }
+
+        let mut walk = match self.walk.lock() {
+            Ok(walk) => walk,
+            Err(_) => return None,
+        };
+        let path = walk.1.next()?;
+        let index = walk.0;
+        walk.0 += 1;
+
+        Some(WalkedFile {
+            index,
+            path,
+            handle: None,
+            stamp: None,
+            elapsed: Duration::ZERO,
+        })
+    }
+}
+
+/// Depth-first walk over regular files, visiting directory entries in name
+/// order so the output is sorted by path. Symlinks are followed as the
+/// policy says, by default not at all.
+pub(crate) struct Walk {
+    root: PathBuf,
+    // entries still to visit, in reverse order
+    pending: Vec<PathBuf>,
+    skip_through: Option<PathBuf>,
+    filter: FileFilter,
+    symlinks: SymlinkPolicy,
+    // canonical paths of the directories walked, when following links
+    visited: HashSet<PathBuf>,
+    pub(crate) errors: Vec<(PathBuf, io::Error)>,
+    // files and hidden directories the filter left out, and links not
+    // followed
+    pub(crate) skipped: Vec<PathBuf>,
+    // with the database build files must have been found clean by
+    cache: Option<(SkipCache, u64)>,
+    pub(crate) unchanged: Vec<PathBuf>,
+}
+
+impl Walk {
+    pub(crate) fn new(root: &Path, skip_through: Option<PathBuf>) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            pending: vec![root.to_path_buf()],
+            skip_through,
+            filter: FileFilter::default(),
+            symlinks: SymlinkPolicy::default(),
+            visited: HashSet::new(),
+            errors: Vec::new(),
+            skipped: Vec::new(),
+            cache: None,
+            unchanged: Vec::new(),
+        }
+    }
+
+    pub(crate) fn with_filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub(crate) fn with_symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    pub(crate) fn with_cache(mut self, cache: SkipCache, build: u64) -> Self {
+        self.cache = Some((cache, build));
+        self
+    }
+
+    /// The metadata of what the link at `path` points to, if the policy
+    /// follows it.
+    fn follow(&mut self, path: &Path) -> Option<Metadata> {
+        if self.symlinks == SymlinkPolicy::Skip {
+            return None;
+        }
+
+        match fs::metadata(path) {
+            Ok(target) if target.is_dir() && self.symlinks == SymlinkPolicy::FollowFiles => None,
+            Ok(target) => Some(target),
+            Err(e) => {
+                self.errors.push((path.to_path_buf(), e));
+                None
+            }
+        }
+    }
+
+    /// Whether the directory at `path` hasn't been walked yet under another
+    /// path. Only links can lead back to a directory, so this is only
+    /// tracked when following them.
+    fn first_visit(&mut self, path: &Path) -> bool {
+        if self.symlinks != SymlinkPolicy::Follow {
+            return true;
+        }
+
+        match fs::canonicalize(path) {
+            Ok(canonical) => self.visited.insert(canonical),
+            Err(_) => true,
+        }
+    }
+
+    /// Whether `path` and everything below it sorts at or before the resume
+    /// point.
+    fn already_done(&self, path: &Path) -> bool {
+        match &self.skip_through {
+            Some(cursor) => path <= cursor.as_path() && !cursor.starts_with(path),
+            None => false,
+        }
+    }
+
+    fn push_children(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.errors.push((dir.to_path_buf(), e));
+                return;
+            }
+        };
+
+        let mut children = Vec::new();
+        for entry in entries {
+            match entry {
+                Ok(entry) => children.push(entry.path()),
+                Err(e) => self.errors.push((dir.to_path_buf(), e)),
+            }
+        }
+
+        children.sort();
+        self.pending.extend(children.into_iter().rev());
+    }
+}
+
+impl Iterator for Walk {
+    type Item = PathBuf;
+
+    fn next(&mut self) -> Option<PathBuf> {
+        while let Some(path) = self.pending.pop() {
+            if self.already_done(&path) {
+                continue;
+            }
+            if path != self.root && self.filter.is_hidden(&path) {
+                self.skipped.push(path);
+                continue;
+            }
+
+            let metadata = match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.file_type().is_symlink() => match self.follow(&path) {
+                    Some(target) => target,
+                    None => {
+                        self.skipped.push(path);
+                        continue;
+                    }
+                },
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    self.errors.push((path, e));
+                    continue;
+                }
+            };
+
+            if metadata.is_dir() {
+                if !self.first_visit(&path) {
+                    self.skipped.push(path);
+                    continue;
+                }
+                self.push_children(&path);
+            } else if metadata.is_file() {
+                if self.skip_through.as_ref() == Some(&path) {
+                    continue;
+                }
+                if !self.filter.accepts(&path, &metadata) {
+                    self.skipped.push(path);
+                    continue;
+                }
+                if let Some((cache, build)) = &self.cache {
+                    if cache.is_unchanged(&path, &metadata, *build) {
+                        self.unchanged.push(path);
+                        continue;
+                    }
+                }
+                return Some(path);
+            }
+        }
+
+        None
+    }
+}
+
+fn read_checkpoint(checkpoint: &Path) -> Result<Option<PathBuf>> {
+    match fs::read_to_string(checkpoint) {
+        Ok(cursor) if cursor.is_empty() => Ok(None),
+        Ok(cursor) => Ok(Some(PathBuf::from(cursor))),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(ClamError::CheckpointError(e)),
+    }
+}
+
+/// Replaces the checkpoint atomically, so an interruption never leaves a
+/// half-written cursor behind.
+fn write_checkpoint(checkpoint: &Path, cursor: &Path) -> Result<()> {
+    let mut partial = checkpoint.as_os_str().to_owned();
+    partial.push(".tmp");
+
+    fs::write(&partial, cursor.to_string_lossy().as_bytes())
+        .and_then(|_| fs::rename(&partial, checkpoint))
+        .map_err(ClamError::CheckpointError)
+}
+
+fn remove_checkpoint(checkpoint: &Path) -> Result<()> {
+    match fs::remove_file(checkpoint) {
+        Ok(_) => Ok(()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(ClamError::CheckpointError(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDaemon;
+    use crate::policy::ActionTaken;
+    use crate::response::ScanResult;
+
+    fn tree(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        fs::write(root.join("a").join("1"), b"one").unwrap();
+        fs::write(root.join("a.txt"), b"two").unwrap();
+        fs::write(root.join("b").join("2"), b"three").unwrap();
+        root
+    }
+
+    #[test]
+    fn test_walk_is_sorted() {
+        let root = tree("clamav-client-walk-test");
+        let paths = Walk::new(&root, None).collect::<Vec<_>>();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            paths,
+            vec![root.join("a/1"), root.join("a.txt"), root.join("b/2")]
+        );
+    }
+
+    #[test]
+    fn test_walk_skips_through_cursor() {
+        let root = tree("clamav-client-resume-walk-test");
+        let paths = Walk::new(&root, Some(root.join("a.txt"))).collect::<Vec<_>>();
+        let missing_cursor = Walk::new(&root, Some(root.join("a/9"))).collect::<Vec<_>>();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(paths, vec![root.join("b/2")]);
+        assert_eq!(missing_cursor, vec![root.join("a.txt"), root.join("b/2")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let root = tree("clamav-client-symlink-walk-test");
+        symlink(&root, root.join("b/loop")).unwrap();
+        symlink(root.join("a.txt"), root.join("c")).unwrap();
+        symlink(root.join("missing"), root.join("d")).unwrap();
+        let walk = |policy| {
+            let mut walk = Walk::new(&root, None).with_symlinks(policy);
+            let paths = (&mut walk).collect::<Vec<_>>();
+            (paths, walk.skipped, walk.errors.len())
+        };
+
+        let skip = walk(SymlinkPolicy::Skip);
+        let files = walk(SymlinkPolicy::FollowFiles);
+        let follow = walk(SymlinkPolicy::Follow);
+        fs::remove_dir_all(&root).unwrap();
+
+        let (a1, a, b2) = (root.join("a/1"), root.join("a.txt"), root.join("b/2"));
+        let (link, c, d) = (root.join("b/loop"), root.join("c"), root.join("d"));
+        assert_eq!(
+            skip,
+            (
+                vec![a1.clone(), a.clone(), b2.clone()],
+                vec![link.clone(), c.clone(), d.clone()],
+                0
+            )
+        );
+        assert_eq!(
+            files,
+            (
+                vec![a1.clone(), a.clone(), b2.clone(), c.clone()],
+                vec![link.clone(), d.clone()],
+                1
+            )
+        );
+        // the link back to the root is walked no further
+        assert_eq!(follow, (vec![a1, a, b2, c], vec![link, d], 1));
+    }
+
+    #[test]
+    fn test_dir_scan_jobs_keep_path_order() {
+        let root = tree("clamav-client-jobs-test");
+        let daemon = MockDaemon::start_sessions("stream: OK", 2);
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let mut progress = Vec::new();
+
+        let report = DirScan::new(&client, &root)
+            .jobs(2)
+            .on_progress(|path, result| progress.push((path.to_path_buf(), result.is_ok())))
+            .run()
+            .unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        // with one session possibly never opened, the daemon isn't joined
+        assert_eq!(
+            report.results,
+            vec![
+                (root.join("a/1"), ScanResult::Ok),
+                (root.join("a.txt"), ScanResult::Ok),
+                (root.join("b/2"), ScanResult::Ok),
+            ]
+        );
+        assert_eq!(
+            report.timings.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+            vec![&root.join("a/1"), &root.join("a.txt"), &root.join("b/2")]
+        );
+        assert_eq!(report.slowest(1).len(), 1);
+        progress.sort();
+        assert_eq!(
+            progress,
+            vec![
+                (root.join("a/1"), true),
+                (root.join("a.txt"), true),
+                (root.join("b/2"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dir_scan_notifies_detections() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let root = tree("clamav-client-notify-test");
+        // one connection asks for the database version, one scans
+        let daemon = MockDaemon::start_sessions("stream: Eicar-Test FOUND", 2);
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        // accepts the first notification and refuses the rest
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 4096]);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n");
+        });
+
+        let report = DirScan::new(&client, &root)
+            .notify(Webhook::new(&url).unwrap())
+            .run()
+            .unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.infected().count(), 3);
+        assert_eq!(report.notify_errors.len(), 2);
+    }
+
+    #[test]
+    fn test_dir_scan_applies_action() {
+        let root = tree("clamav-client-action-test");
+        let daemon = MockDaemon::start_session("stream: Eicar-Test FOUND");
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let report = DirScan::new(&client, &root)
+            .on_found(Action::Delete)
+            .run()
+            .unwrap();
+        let left = Walk::new(&root, None).count();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(left, 0);
+        assert_eq!(
+            report.actions,
+            vec![
+                (root.join("a/1"), ActionTaken::Deleted),
+                (root.join("a.txt"), ActionTaken::Deleted),
+                (root.join("b/2"), ActionTaken::Deleted),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dir_scan_filters_files() {
+        let root = tree("clamav-client-filter-dir-test");
+        let daemon = MockDaemon::start_session("stream: OK");
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let report = DirScan::new(&client, &root)
+            .filter(FileFilter::new().allow_extensions(["txt"]))
+            .run()
+            .unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.results, vec![(root.join("a.txt"), ScanResult::Ok)]);
+        assert_eq!(report.skipped, vec![root.join("a/1"), root.join("b/2")]);
+        assert_eq!(daemon.all_received()[1].payload(), b"two".to_vec());
+    }
+
+    #[test]
+    fn test_dir_scan_skips_unchanged_files() {
+        use crate::memory::MemoryTransport;
+
+        let root = tree("clamav-client-skip-cache-dir-test");
+        let cache = std::env::temp_dir().join(format!(
+            "clamav-client-skip-cache-dir-test-{}.cache",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&cache);
+        let version = b"ClamAV 1.0.0/24802/Mon Jan  1 00:00:00 2024\0";
+        let transport = MemoryTransport::new()
+            .reply(version)
+            .reply(b"1: stream: OK\x002: stream: OK\x003: stream: OK\0")
+            .reply(version)
+            .reply(b"1: stream: OK\0");
+        let client = ClamClient::new_memory(transport.clone());
+        let scan = || DirScan::new(&client, &root).skip_cache(&cache).run();
+
+        assert_eq!(scan().unwrap().results.len(), 3);
+        fs::write(root.join("a.txt"), b"changed").unwrap();
+        let report = scan().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(&cache).unwrap();
+
+        assert_eq!(report.results, vec![(root.join("a.txt"), ScanResult::Ok)]);
+        assert_eq!(report.unchanged, vec![root.join("a/1"), root.join("b/2")]);
+        assert_eq!(transport.received().len(), 4);
+    }
+
+    #[test]
+    fn test_dir_scan_resumes_from_checkpoint() {
+        let root = tree("clamav-client-resume-test");
+        let checkpoint = std::env::temp_dir().join(format!(
+            "clamav-client-resume-test-{}.cursor",
+            std::process::id()
+        ));
+        fs::write(&checkpoint, "a.txt").unwrap();
+
+        let daemon = MockDaemon::start_session("stream: OK");
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let report = DirScan::new(&client, &root)
+            .checkpoint(&checkpoint)
+            .run()
+            .unwrap();
+        let removed = !checkpoint.exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.resumed_after, Some(PathBuf::from("a.txt")));
+        assert_eq!(report.results, vec![(root.join("b/2"), ScanResult::Ok)]);
+        assert!(removed);
+        assert_eq!(daemon.all_received()[1].payload(), b"three".to_vec());
+    }
+}
@@ -0,0 +1,106 @@
+//! Runs clamd in a `clamav/clamav` container for integration tests, using
+//! the docker CLI.
+
+use std::io::{self, ErrorKind};
+use std::process::{Command, Output};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::client::{ClamClient, Result};
+use crate::error::ClamError;
+
+const IMAGE: &str = "clamav/clamav:stable";
+// the image downloads and loads the signature database before listening
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A running clamd container, removed again on drop.
+pub struct ClamavContainer {
+    id: String,
+    host: String,
+    port: u16,
+}
+
+impl ClamavContainer {
+    /// Starts the stable image and waits until clamd answers PING.
+    pub fn start() -> Result<Self> {
+        Self::start_image(IMAGE, STARTUP_TIMEOUT)
+    }
+
+    pub fn start_image(image: &str, timeout: Duration) -> Result<Self> {
+        let output = docker(&["run", "-d", "--rm", "-p", "127.0.0.1::3310", image])?;
+        let id = stdout(output).trim().to_string();
+
+        // from here on, dropping the container removes it
+        let mut container = Self {
+            id,
+            host: String::from("127.0.0.1"),
+            port: 0,
+        };
+
+        let output = docker(&["port", &container.id, "3310/tcp"])?;
+        let mapping = stdout(output);
+        container.port = match mapping
+            .lines()
+            .next()
+            .and_then(|line| line.rsplit(':').next())
+            .and_then(|port| port.trim().parse().ok())
+        {
+            Some(port) => port,
+            None => return Err(ClamError::InvalidData(mapping)),
+        };
+
+        container.wait_ready(timeout)?;
+        Ok(container)
+    }
+
+    /// A client connected to the container's clamd.
+    pub fn client(&self) -> Result<ClamClient> {
+        ClamClient::new(&self.host, self.port)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn wait_ready(&self, timeout: Duration) -> Result<()> {
+        let client = self.client()?;
+        let started = Instant::now();
+
+        while !client.ping() {
+            if started.elapsed() > timeout {
+                return Err(ClamError::ConnectionError(io::Error::new(
+                    ErrorKind::TimedOut,
+                    "clamd in the container did not become ready",
+                )));
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ClamavContainer {
+    fn drop(&mut self) {
+        let _ = docker(&["rm", "-f", &self.id]);
+    }
+}
+
+fn docker(args: &[&str]) -> Result<Output> {
+    let output = match Command::new("docker").args(args).output() {
+        Ok(output) => output,
+        Err(e) => return Err(ClamError::ConnectionError(e)),
+    };
+
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(ClamError::ConnectionError(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )))
+    }
+}
+
+fn stdout(output: Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
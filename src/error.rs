@@ -11,14 +11,62 @@ pub enum ClamError {
     #[error("{0}")]
     CommandError(std::io::Error),
 
+    #[error("{0}")]
+    StreamError(std::io::Error),
+
     #[error("Could not parse: {0}")]
     InvalidData(::std::string::String),
 
+    #[error("Unexpected reply to {0}: {1}")]
+    UnexpectedReply(&'static str, ::std::string::String),
+
+    #[error("Daemon socket is served by an unexpected peer: {0}")]
+    UntrustedPeer(::std::string::String),
+
+    #[error("Reply ended before its terminator: {0}")]
+    TruncatedResponse(::std::string::String),
+
+    #[error("Daemon version {have} is too old, {needs} or newer is required")]
+    UnsupportedByDaemon { needs: String, have: String },
+
     #[error("Invalid data length sent: {0}")]
     InvalidDataLength(usize),
 
-    #[error("{0}")]
-    DateParseError(chrono::format::ParseError),
+    #[error("Invalid client configuration: {0}")]
+    InvalidConfig(crate::config::ConfigError),
+
+    #[error("Stream is larger than the cap of {0} bytes")]
+    SizeCapExceeded(u64),
+
+    #[error("Concurrency limit of {0} scans reached")]
+    ConcurrencyLimitReached(usize),
+
+    #[error("Could not access checkpoint: {0}")]
+    CheckpointError(std::io::Error),
+
+    #[error("Could not access skip cache: {0}")]
+    SkipCacheError(std::io::Error),
+
+    #[error("Could not write audit log: {0}")]
+    AuditError(std::io::Error),
+
+    #[error("Could not send notification: {0}")]
+    NotifyError(std::io::Error),
+
+    #[error("Daemon did not answer in time: {0}")]
+    Timeout(std::io::Error),
+
+    #[error("Daemon is considered down, next attempt in {0:?}")]
+    CircuitOpen(std::time::Duration),
+
+    #[error("Scan was cancelled")]
+    Cancelled,
+
+    #[error("Scan service has stopped")]
+    ServiceStopped,
+
+    #[error("Pending command {0} belongs to another session")]
+    ForeignPending(u64),
 
     #[error("{0}")]
     IntParseError(std::num::ParseIntError),
@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use crate::response::ScanResult;
+use crate::transport::Endpoint;
+
+/// Something that happened while scanning, for progress displays and
+/// dashboards. Events of one scan share its `scan` number, which is unique
+/// per client; errors are carried as their message.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ScanEvent {
+    ScanStarted {
+        scan: u64,
+        // the file path, or `stream`
+        input: String,
+    },
+    ChunkSent {
+        scan: u64,
+        bytes: u64,
+        // payload bytes sent so far, including this chunk
+        total: u64,
+    },
+    /// A file scanned by path, sent right before its `ScanFinished`.
+    FileScanned {
+        scan: u64,
+        path: PathBuf,
+        result: Result<ScanResult, String>,
+    },
+    ScanFinished {
+        scan: u64,
+        input: String,
+        result: Result<ScanResult, String>,
+        duration: Duration,
+    },
+    /// An endpoint refused the connection; others may still be tried.
+    ConnectionError { endpoint: Endpoint, error: String },
+}
+
+/// Receives the events of a client, see `ClamClient::with_observer`. Events
+/// are delivered on the scanning thread, so observers should return quickly.
+pub trait ScanObserver: Send + Sync {
+    fn on_event(&self, event: &ScanEvent);
+}
+
+impl<F: Fn(&ScanEvent) + Send + Sync> ScanObserver for F {
+    fn on_event(&self, event: &ScanEvent) {
+        self(event)
+    }
+}
+
+/// Forwards every event to the channel, ignoring a receiver that hung up.
+impl ScanObserver for Sender<ScanEvent> {
+    fn on_event(&self, event: &ScanEvent) {
+        let _ = self.send(event.clone());
+    }
+}
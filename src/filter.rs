@@ -0,0 +1,157 @@
+use std::fs::Metadata;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Which local files `DirScan` and `ClamClient::scan_glob_filtered` send to
+/// the daemon. Files are checked against their metadata before anything is
+/// read, and those left out are listed in `ScanReport::skipped`. The default
+/// filter lets everything through.
+///
+/// ```
+/// use clamav::FileFilter;
+///
+/// let filter = FileFilter::new()
+///     .max_size(64 << 20)
+///     .deny_extensions(["iso", "vmdk"])
+///     .skip_hidden();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileFilter {
+    max_size: Option<u64>,
+    // lowercase, without the dot
+    allowed_extensions: Vec<String>,
+    denied_extensions: Vec<String>,
+    skip_hidden: bool,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+}
+
+impl FileFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Skips files larger than `bytes`.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Scans only files with one of `extensions`, given without the dot
+    /// and matched regardless of case.
+    pub fn allow_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.allowed_extensions
+            .extend(extensions.into_iter().map(|e| e.as_ref().to_lowercase()));
+        self
+    }
+
+    /// Skips files with one of `extensions`, even if they are also allowed.
+    pub fn deny_extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.denied_extensions
+            .extend(extensions.into_iter().map(|e| e.as_ref().to_lowercase()));
+        self
+    }
+
+    /// Skips files and directories whose name starts with a dot, together
+    /// with everything below them.
+    pub fn skip_hidden(mut self) -> Self {
+        self.skip_hidden = true;
+        self
+    }
+
+    /// Skips files last modified at or before `time`. Files whose
+    /// modification time is unavailable are scanned.
+    pub fn modified_after(mut self, time: SystemTime) -> Self {
+        self.modified_after = Some(time);
+        self
+    }
+
+    /// Skips files last modified at or after `time`.
+    pub fn modified_before(mut self, time: SystemTime) -> Self {
+        self.modified_before = Some(time);
+        self
+    }
+
+    pub(crate) fn is_hidden(&self, path: &Path) -> bool {
+        self.skip_hidden
+            && path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+    }
+
+    /// Whether the regular file at `path` is to be scanned.
+    pub(crate) fn accepts(&self, path: &Path, metadata: &Metadata) -> bool {
+        if self.max_size.is_some_and(|max| metadata.len() > max) {
+            return false;
+        }
+
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        let listed = |list: &[String]| {
+            extension
+                .as_ref()
+                .is_some_and(|e| list.iter().any(|listed| listed == e))
+        };
+        if listed(&self.denied_extensions)
+            || (!self.allowed_extensions.is_empty() && !listed(&self.allowed_extensions))
+        {
+            return false;
+        }
+
+        match metadata.modified() {
+            Ok(modified) => {
+                self.modified_after.is_none_or(|after| modified > after)
+                    && self.modified_before.is_none_or(|before| modified < before)
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_accepts() {
+        let dir =
+            std::env::temp_dir().join(format!("clamav-client-filter-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (zip, iso) = (dir.join("a.ZIP"), dir.join("b.iso"));
+        fs::write(&zip, b"zip").unwrap();
+        fs::write(&iso, vec![0; 100]).unwrap();
+        let accepts =
+            |filter: &FileFilter, path: &Path| filter.accepts(path, &fs::metadata(path).unwrap());
+
+        let everything = FileFilter::new();
+        assert!(accepts(&everything, &zip) && accepts(&everything, &iso));
+
+        let small = FileFilter::new().max_size(10);
+        assert!(accepts(&small, &zip) && !accepts(&small, &iso));
+
+        let zips = FileFilter::new().allow_extensions(["zip"]);
+        assert!(accepts(&zips, &zip) && !accepts(&zips, &iso));
+        let denied = zips.deny_extensions(["Zip"]);
+        assert!(!accepts(&denied, &zip));
+
+        let now = SystemTime::now();
+        let recent = FileFilter::new().modified_after(now - Duration::from_secs(3600));
+        let old = FileFilter::new().modified_before(now - Duration::from_secs(3600));
+        assert!(accepts(&recent, &zip) && !accepts(&old, &zip));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let hidden = FileFilter::new().skip_hidden();
+        assert!(hidden.is_hidden(Path::new("/srv/.git")));
+        assert!(!hidden.is_hidden(Path::new("/srv/a.txt")));
+        assert!(!everything.is_hidden(Path::new("/srv/.git")));
+    }
+}
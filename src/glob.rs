@@ -0,0 +1,259 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::client::Result;
+use crate::dir::Walk;
+use crate::error::ClamError;
+use crate::filter::FileFilter;
+
+/// A shell-style pattern over local paths: `*`, `?` and `[...]` match
+/// within one path component, `**` matches any number of components.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Glob {
+    // the leading components without wildcards, walked from
+    base: PathBuf,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    AnyDepth,
+    Name(Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyString,
+    // the ranges of a `[...]` class, and whether it is negated with `!`
+    Class(Vec<(char, char)>, bool),
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> Result<Self> {
+        let mut base = PathBuf::new();
+        let mut segments = Vec::new();
+
+        for component in Path::new(pattern).components() {
+            let name = match component {
+                Component::Normal(name) => name.to_string_lossy(),
+                other if segments.is_empty() => {
+                    base.push(other);
+                    continue;
+                }
+                _ => {
+                    return Err(ClamError::InvalidData(format!(
+                        "glob pattern {:?} has a wildcard before {:?}",
+                        pattern,
+                        component.as_os_str()
+                    )))
+                }
+            };
+
+            let is_literal = !name.contains(['*', '?', '[']);
+            if is_literal && segments.is_empty() {
+                base.push(&*name);
+            } else if name == "**" {
+                segments.push(Segment::AnyDepth);
+            } else {
+                segments.push(Segment::Name(tokens(&name, pattern)?));
+            }
+        }
+
+        Ok(Self { base, segments })
+    }
+
+    /// Every regular file the pattern matches and `filter` accepts, in path
+    /// order.
+    pub(crate) fn expand(&self, filter: FileFilter) -> Expansion {
+        // relative patterns without a literal prefix start in the current
+        // directory, and yield paths without a leading `./`
+        let here = self.base.as_os_str().is_empty();
+        let root = if here { Path::new(".") } else { &self.base };
+        let mut walk = Walk::new(root, None).with_filter(filter);
+        let relative = |path: PathBuf| match path.strip_prefix(".") {
+            Ok(relative) if here => relative.to_path_buf(),
+            _ => path,
+        };
+
+        let matches = (&mut walk)
+            .map(relative)
+            .filter(|path| self.matches(path))
+            .collect();
+        let skipped = walk
+            .skipped
+            .into_iter()
+            .map(relative)
+            .filter(|path| self.matches(path))
+            .collect();
+
+        Expansion {
+            matches,
+            skipped,
+            errors: walk.errors,
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.base).unwrap_or(path);
+        let names = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>();
+
+        matches_names(&self.segments, &names)
+    }
+}
+
+/// What a pattern expanded to.
+pub(crate) struct Expansion {
+    pub(crate) matches: Vec<PathBuf>,
+    pub(crate) skipped: Vec<PathBuf>,
+    // directories that could not be read
+    pub(crate) errors: Vec<(PathBuf, io::Error)>,
+}
+
+fn tokens(name: &str, pattern: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = name.chars();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' => Token::AnyString,
+            '?' => Token::AnyChar,
+            '[' => {
+                let mut class = chars.clone();
+                let negated = class.as_str().starts_with('!');
+                if negated {
+                    class.next();
+                }
+
+                let mut ranges = Vec::new();
+                let mut closed = false;
+                let mut first = true;
+                while let Some(c) = class.next() {
+                    if c == ']' && !first {
+                        closed = true;
+                        break;
+                    }
+                    first = false;
+
+                    let mut ahead = class.clone();
+                    match (ahead.next(), ahead.next()) {
+                        (Some('-'), Some(end)) if end != ']' => {
+                            ranges.push((c, end));
+                            class = ahead;
+                        }
+                        _ => ranges.push((c, c)),
+                    }
+                }
+
+                if !closed {
+                    return Err(ClamError::InvalidData(format!(
+                        "glob pattern {:?} has an unclosed [",
+                        pattern
+                    )));
+                }
+                chars = class;
+                Token::Class(ranges, negated)
+            }
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn matches_names<S: AsRef<str>>(segments: &[Segment], names: &[S]) -> bool {
+    match (segments.split_first(), names.split_first()) {
+        (None, _) => names.is_empty(),
+        (Some((Segment::AnyDepth, rest)), _) => {
+            matches_names(rest, names)
+                || (!names.is_empty() && matches_names(segments, &names[1..]))
+        }
+        (Some((Segment::Name(tokens), rest)), Some((name, names))) => {
+            let name = name.as_ref().chars().collect::<Vec<_>>();
+            matches_name(tokens, &name) && matches_names(rest, names)
+        }
+        (Some(_), None) => false,
+    }
+}
+
+fn matches_name(tokens: &[Token], name: &[char]) -> bool {
+    match (tokens.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some((Token::AnyString, rest)), _) => {
+            matches_name(rest, name) || (!name.is_empty() && matches_name(tokens, &name[1..]))
+        }
+        (Some(_), None) => false,
+        (Some((token, rest)), Some((c, name))) => {
+            let matched = match token {
+                Token::Char(expected) => expected == c,
+                Token::AnyChar | Token::AnyString => true,
+                Token::Class(ranges, negated) => {
+                    ranges.iter().any(|(from, to)| (from..=to).contains(&c)) != *negated
+                }
+            };
+            matched && matches_name(rest, name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).unwrap().matches(Path::new(path))
+    }
+
+    #[test]
+    fn test_matching() {
+        assert_eq!(
+            Glob::new("artifacts/**/*.zip").unwrap().base,
+            Path::new("artifacts")
+        );
+        assert!(matches("artifacts/**/*.zip", "artifacts/a.zip"));
+        assert!(matches("artifacts/**/*.zip", "artifacts/x/y/a.zip"));
+        assert!(!matches("artifacts/**/*.zip", "artifacts/x/a.zip.txt"));
+        assert!(matches("/srv/*/?.[a-c]", "/srv/x/1.b"));
+        assert!(!matches("/srv/*/?.[a-c]", "/srv/x/1.d"));
+        assert!(matches("/srv/[!x]*", "/srv/abc"));
+        assert!(!matches("/srv/*", "/srv/a/b"));
+        assert!(matches("/srv/a.zip", "/srv/a.zip"));
+
+        assert!(Glob::new("/srv/[a-").is_err());
+        assert!(Glob::new("/srv/*/../a").is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        let root =
+            std::env::temp_dir().join(format!("clamav-client-glob-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/b/1.zip"), b"one").unwrap();
+        fs::write(root.join("a/2.zip"), b"two").unwrap();
+        fs::write(root.join("a/3.txt"), b"three").unwrap();
+        fs::write(root.join("a/4.zip"), vec![0; 100]).unwrap();
+        fs::write(root.join("a/.5.zip"), b"five").unwrap();
+
+        let pattern = format!("{}/**/*.zip", root.display());
+        let filter = FileFilter::new().max_size(10).skip_hidden();
+        let expansion = Glob::new(&pattern).unwrap().expand(filter);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            expansion.matches,
+            vec![root.join("a/2.zip"), root.join("a/b/1.zip")]
+        );
+        assert_eq!(
+            expansion.skipped,
+            vec![root.join("a/.5.zip"), root.join("a/4.zip")]
+        );
+        assert!(expansion.errors.is_empty());
+    }
+}
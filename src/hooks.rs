@@ -0,0 +1,26 @@
+use std::io;
+
+use crate::command::Command;
+use crate::error::ClamError;
+use crate::transport::Endpoint;
+
+/// Callbacks around every connection and command of a client, see
+/// `ClamClient::with_hooks`, e.g. for custom metrics, request IDs or fault
+/// injection. All of them do nothing by default and run on the calling
+/// thread.
+///
+/// A command is one request to the daemon: a command such as PING or SCAN,
+/// or a streamed scan, which is reported as INSTREAM. Commands submitted to
+/// a `ClamSession` by hand are not reported.
+pub trait ClientHooks: Send + Sync {
+    /// Called once a connection to `endpoint` is open. An error closes it and
+    /// counts as the endpoint refusing the connection.
+    fn on_connect(&self, _endpoint: &Endpoint) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_command_start(&self, _command: &Command) {}
+
+    /// Called when `command` is done, with the error it failed with, if any.
+    fn on_command_end(&self, _command: &Command, _error: Option<&ClamError>) {}
+}
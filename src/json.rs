@@ -0,0 +1,33 @@
+//! Just enough JSON output for notifications, audit logs and the bundled
+//! binaries, without pulling in serde_json.
+
+use std::fmt::Write;
+
+/// `s` as a quoted JSON string.
+pub fn string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// `s` as a quoted JSON string, or `null`.
+pub(crate) fn optional(s: Option<&str>) -> String {
+    match s {
+        Some(s) => string(s),
+        None => String::from("null"),
+    }
+}
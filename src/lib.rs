@@ -1,11 +1,93 @@
 #[macro_use]
 extern crate serde;
-#[macro_use]
-extern crate nom;
 
-pub use client::ClamClient;
-pub use response::Signature;
+#[cfg(feature = "tokio")]
+pub use async_writer::{AsyncScanWriter, ScanSink};
+pub use audit::AuditLog;
+pub use breaker::CircuitBreaker;
+pub use cancel::CancelToken;
+pub use client::{ClamClient, PathScan};
+pub use command::{Command, Framing};
+pub use config::ClientConfig;
+pub use consumer::ScanConsumer;
+pub use counters::ClientStats;
+pub use diagnose::Finding;
+pub use dir::{DirScan, SymlinkPolicy};
+pub use event::{ScanEvent, ScanObserver};
+pub use filter::FileFilter;
+pub use hooks::ClientHooks;
+pub use limit::{ConcurrencyLimiter, RateLimiter};
+pub use memory::MemoryTransport;
+pub use monitor::StatsMonitor;
+pub use notify::Webhook;
+#[cfg(feature = "tokio")]
+pub use offload::AsyncClamClient;
+pub use options::{ScanOptions, ScanTarget};
+#[cfg(feature = "rayon")]
+pub use par::ParScan;
+pub use pool::BufferPool;
+pub use reconnect::ReconnectPolicy;
+pub use report::ScanReport;
+pub use response::{ReloadAck, ScanItem, ScanVerdict, ShutdownAck, Signature, StatsDelta, Target};
+pub use retry::ReloadRetry;
+pub use scan::{AsyncClamScan, ClamScan};
+pub use schedule::{Rescan, Schedule};
+pub use service::{ScanJob, ScanService};
+pub use session::{ClamSession, Pending};
+pub use transport::Endpoint;
+#[cfg(unix)]
+pub use transport::PeerCredentials;
+pub use watch::DirWatch;
+pub use writer::ClamScanWriter;
 
+#[cfg(feature = "tokio")]
+pub mod async_writer;
+pub mod audit;
+pub mod breaker;
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod cancel;
 pub mod client;
+pub mod command;
+pub mod config;
+pub mod consumer;
+pub mod counters;
+pub mod diagnose;
+pub mod dir;
+#[cfg(feature = "docker")]
+pub mod docker;
 pub mod error;
+pub mod event;
+pub mod filter;
+mod glob;
+pub mod hooks;
+pub mod json;
+pub mod limit;
+pub mod memory;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(test)]
+mod mock;
+pub mod monitor;
+pub mod notify;
+#[cfg(feature = "tokio")]
+pub mod offload;
+pub mod options;
+#[cfg(feature = "rayon")]
+pub mod par;
+pub mod policy;
+pub mod pool;
+pub mod process;
+pub mod reconnect;
+pub mod report;
 pub mod response;
+pub mod retry;
+pub mod scan;
+pub mod schedule;
+pub mod service;
+pub mod session;
+mod skip;
+pub mod testing;
+pub mod transport;
+pub mod watch;
+pub mod writer;
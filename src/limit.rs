@@ -0,0 +1,264 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Token bucket refilled at a fixed rate per second. Callers take what they
+/// need and sleep off any deficit, so concurrent users of a shared limiter
+/// are served in the order they arrived.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    /// Allows `rate` units per second with a burst of one second's worth.
+    pub fn new(rate: u64) -> Self {
+        Self::with_burst(rate, rate)
+    }
+
+    pub fn with_burst(rate: u64, burst: u64) -> Self {
+        let rate = rate.max(1) as f64;
+        let burst = burst.max(1) as f64;
+
+        Self {
+            rate,
+            burst,
+            state: Mutex::new(Bucket {
+                tokens: burst,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Blocks until `amount` units may be spent.
+    pub fn acquire(&self, amount: u64) {
+        let wait = self.reserve(amount);
+        if wait > Duration::from_secs(0) {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Takes `amount` units, returning how long the caller has to wait before
+    /// using them.
+    fn reserve(&self, amount: u64) -> Duration {
+        let mut bucket = match self.state.lock() {
+            Ok(bucket) => bucket,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.refilled = now;
+        bucket.tokens -= amount as f64;
+
+        if bucket.tokens < 0.0 {
+            Duration::from_secs_f64(-bucket.tokens / self.rate)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+}
+
+/// Caps the number of scans a client (or several clients sharing the
+/// limiter) keeps in flight, so bursts don't exhaust clamd's MaxThreads.
+/// Scans over the limit either wait for a free slot, possibly for a bounded
+/// time, or are rejected.
+#[derive(Debug)]
+pub struct ConcurrencyLimiter {
+    max: usize,
+    fail_fast: bool,
+    // how long a queued scan waits before it is rejected, unbounded if unset
+    acquire_timeout: Option<Duration>,
+    in_flight: Mutex<usize>,
+    released: Condvar,
+}
+
+impl ConcurrencyLimiter {
+    /// Allows `max` concurrent scans, queueing the rest.
+    pub fn new(max: usize) -> Self {
+        Self {
+            max: max.max(1),
+            fail_fast: false,
+            acquire_timeout: None,
+            in_flight: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Allows `max` concurrent scans, rejecting the rest immediately.
+    pub fn fail_fast(max: usize) -> Self {
+        Self {
+            fail_fast: true,
+            ..Self::new(max)
+        }
+    }
+
+    /// Rejects scans that waited `timeout` for a slot without getting one,
+    /// so queueing delays stay bounded.
+    pub fn with_acquire_timeout(mut self, timeout: Duration) -> Self {
+        self.acquire_timeout = Some(timeout);
+        self
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    pub fn acquire_timeout(&self) -> Option<Duration> {
+        self.acquire_timeout
+    }
+
+    /// Takes a slot, waiting for one unless the limiter fails fast, in which
+    /// case `None` is returned when all slots are taken. `None` is also
+    /// returned once the acquire timeout passes.
+    pub fn acquire(limiter: &Arc<Self>) -> Option<ConcurrencyPermit> {
+        let deadline = limiter
+            .acquire_timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut in_flight = match limiter.in_flight.lock() {
+            Ok(n) => n,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        while *in_flight >= limiter.max {
+            if limiter.fail_fast {
+                return None;
+            }
+
+            in_flight = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left == Duration::from_secs(0) {
+                        return None;
+                    }
+                    match limiter.released.wait_timeout(in_flight, left) {
+                        Ok((n, _)) => n,
+                        Err(poisoned) => poisoned.into_inner().0,
+                    }
+                }
+                None => match limiter.released.wait(in_flight) {
+                    Ok(n) => n,
+                    Err(poisoned) => poisoned.into_inner(),
+                },
+            };
+        }
+
+        *in_flight += 1;
+        Some(ConcurrencyPermit {
+            limiter: Arc::clone(limiter),
+        })
+    }
+
+    pub fn in_flight(&self) -> usize {
+        match self.in_flight.lock() {
+            Ok(n) => *n,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+}
+
+/// A slot held for the duration of one scan, released on drop.
+#[derive(Debug)]
+pub struct ConcurrencyPermit {
+    limiter: Arc<ConcurrencyLimiter>,
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        let mut in_flight = match self.limiter.in_flight.lock() {
+            Ok(n) => n,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        *in_flight -= 1;
+        self.limiter.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_is_free() {
+        let limiter = RateLimiter::new(10_000);
+        assert_eq!(limiter.reserve(10_000), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_deficit_is_waited_off() {
+        let limiter = RateLimiter::new(10_000);
+        limiter.reserve(10_000);
+
+        let wait = limiter.reserve(5_000);
+        assert!(wait > Duration::from_millis(400));
+        assert!(wait <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_acquire_sleeps() {
+        let limiter = RateLimiter::new(10_000);
+        let started = Instant::now();
+
+        limiter.acquire(10_000);
+        limiter.acquire(1_000);
+        assert!(started.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn test_fail_fast_rejects_over_limit() {
+        let limiter = Arc::new(ConcurrencyLimiter::fail_fast(1));
+
+        let permit = ConcurrencyLimiter::acquire(&limiter).unwrap();
+        assert!(ConcurrencyLimiter::acquire(&limiter).is_none());
+
+        drop(permit);
+        assert!(ConcurrencyLimiter::acquire(&limiter).is_some());
+    }
+
+    #[test]
+    fn test_queueing_waits_for_release() {
+        let limiter = Arc::new(ConcurrencyLimiter::new(1));
+        let permit = ConcurrencyLimiter::acquire(&limiter).unwrap();
+
+        let waiter = {
+            let limiter = Arc::clone(&limiter);
+            thread::spawn(move || ConcurrencyLimiter::acquire(&limiter).is_some())
+        };
+
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(limiter.in_flight(), 1);
+        drop(permit);
+
+        assert!(waiter.join().unwrap());
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_acquire_timeout_bounds_waiting() {
+        let limiter =
+            Arc::new(ConcurrencyLimiter::new(1).with_acquire_timeout(Duration::from_millis(50)));
+        let permit = ConcurrencyLimiter::acquire(&limiter).unwrap();
+
+        let started = Instant::now();
+        assert!(ConcurrencyLimiter::acquire(&limiter).is_none());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let waiter = {
+            let limiter = Arc::clone(&limiter);
+            thread::spawn(move || ConcurrencyLimiter::acquire(&limiter).is_some())
+        };
+        thread::sleep(Duration::from_millis(10));
+        drop(permit);
+        assert!(waiter.join().unwrap());
+    }
+}
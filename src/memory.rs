@@ -0,0 +1,244 @@
+//! A transport that never leaves the process, for testing protocol handling
+//! without sockets or a daemon. Replies are scripted up front rather than
+//! computed from what the client sends.
+
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A scripted daemon. Every connection a client opens takes the next reply
+/// of the script; when the script runs out, connections are refused. The
+/// reply can be read straight away, whatever the client has sent so far,
+/// like a daemon that answers early. What each connection was sent is kept
+/// for `received`.
+///
+/// ```
+/// use clamav::{ClamClient, MemoryTransport};
+///
+/// let transport = MemoryTransport::new().reply(b"PONG\0");
+/// let client = ClamClient::new_memory(transport.clone());
+///
+/// assert!(client.ping());
+/// assert_eq!(transport.received(), [b"zPING\0".to_vec()]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTransport {
+    script: Arc<Mutex<Script>>,
+}
+
+#[derive(Debug, Default)]
+struct Script {
+    // the reply of each connection still to be opened, `None` refusing it
+    pending: VecDeque<Option<Vec<u8>>>,
+    opened: Vec<MemoryConnection>,
+}
+
+/// One end of an in-memory connection; clones share it, like the handles of
+/// a socket.
+#[derive(Debug, Clone)]
+pub(crate) struct MemoryConnection {
+    pipe: Arc<Mutex<Pipe>>,
+}
+
+#[derive(Debug, Default)]
+struct Pipe {
+    reply: Vec<u8>,
+    position: usize,
+    sent: Vec<u8>,
+    read_closed: bool,
+    write_closed: bool,
+}
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the next connection with `reply`, e.g. `b"stream: OK\0"`, or
+    /// for an IDSESSION connection every reply of the session, each prefixed
+    /// with its request number.
+    pub fn reply(self, reply: &[u8]) -> Self {
+        lock(&self.script).pending.push_back(Some(reply.to_vec()));
+        self
+    }
+
+    /// Refuses the next connection, as a daemon that is down would.
+    pub fn refuse(self) -> Self {
+        lock(&self.script).pending.push_back(None);
+        self
+    }
+
+    /// The bytes sent over each connection so far, in the order they were
+    /// opened.
+    pub fn received(&self) -> Vec<Vec<u8>> {
+        lock(&self.script)
+            .opened
+            .iter()
+            .map(|connection| lock(&connection.pipe).sent.clone())
+            .collect()
+    }
+
+    pub(crate) fn connect(&self) -> io::Result<MemoryConnection> {
+        let mut script = lock(&self.script);
+        let reply = match script.pending.pop_front() {
+            Some(Some(reply)) => reply,
+            _ => return Err(ErrorKind::ConnectionRefused.into()),
+        };
+
+        let connection = MemoryConnection {
+            pipe: Arc::new(Mutex::new(Pipe {
+                reply,
+                ..Pipe::default()
+            })),
+        };
+        script.opened.push(connection.clone());
+        Ok(connection)
+    }
+}
+
+/// Transports are equal if they share a script.
+impl PartialEq for MemoryTransport {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.script, &other.script)
+    }
+}
+
+impl Eq for MemoryTransport {}
+
+impl Hash for MemoryTransport {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.script).hash(state)
+    }
+}
+
+impl MemoryConnection {
+    pub(crate) fn shutdown(&self, how: Shutdown) {
+        let mut pipe = lock(&self.pipe);
+        match how {
+            Shutdown::Read => pipe.read_closed = true,
+            Shutdown::Write => pipe.write_closed = true,
+            Shutdown::Both => {
+                pipe.read_closed = true;
+                pipe.write_closed = true;
+            }
+        }
+    }
+}
+
+impl Read for &MemoryConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = lock(&self.pipe);
+        if pipe.read_closed {
+            return Ok(0);
+        }
+
+        let start = pipe.position;
+        let n = buf.len().min(pipe.reply.len() - start);
+        buf[..n].copy_from_slice(&pipe.reply[start..start + n]);
+        pipe.position += n;
+        Ok(n)
+    }
+}
+
+impl Write for &MemoryConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pipe = lock(&self.pipe);
+        if pipe.write_closed {
+            return Err(ErrorKind::BrokenPipe.into());
+        }
+
+        pipe.sent.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod nonblocking {
+    use std::io;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::*;
+
+    // nothing ever blocks, so every poll is ready
+    impl AsyncRead for MemoryConnection {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let n = (&*self).read(buf.initialize_unfilled());
+            Poll::Ready(n.map(|n| buf.advance(n)))
+        }
+    }
+
+    impl AsyncWrite for MemoryConnection {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready((&*self).write(buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.shutdown(Shutdown::Write);
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ClamClient;
+    use crate::response::ScanResult;
+
+    #[test]
+    fn test_scripted_connections() {
+        let transport = MemoryTransport::new()
+            .reply(b"stream: OK\0")
+            .reply(b"1: PONG\x002: stream: Eicar FOUND\0")
+            .refuse();
+        let client = ClamClient::new_memory(transport.clone());
+
+        assert_eq!(client.scan_bytes(b"data").unwrap(), ScanResult::Ok);
+
+        let mut session = client.session().unwrap();
+        let ping = session.submit::<String>(&crate::Command::Ping).unwrap();
+        assert_eq!(session.wait(ping).unwrap(), "PONG");
+        match session.scan_bytes(b"eicar").unwrap() {
+            ScanResult::Found(_, signature, _) => assert_eq!(signature.raw, "Eicar"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        session.end().unwrap();
+
+        assert!(!client.ping());
+        assert_eq!(
+            transport.received(),
+            [
+                b"zINSTREAM\0\0\0\0\x04data\0\0\0\0".to_vec(),
+                b"zIDSESSION\0zPING\0zINSTREAM\0\0\0\0\x05eicar\0\0\0\0zEND\0".to_vec(),
+            ]
+        );
+    }
+}
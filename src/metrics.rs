@@ -0,0 +1,254 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::error::ClamError;
+use crate::response::ScanResult;
+
+/// Upper bounds of the latency buckets, in milliseconds; slower scans land
+/// in a final unbounded bucket.
+pub const LATENCY_BUCKETS_MS: [u64; 14] = [
+    1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 30_000,
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Verdict {
+    Clean,
+    Found,
+    Error,
+}
+
+impl Verdict {
+    const ALL: [Verdict; 3] = [Verdict::Clean, Verdict::Found, Verdict::Error];
+
+    pub fn of(result: std::result::Result<&ScanResult, &ClamError>) -> Self {
+        match result {
+            Ok(ScanResult::Ok) => Verdict::Clean,
+            Ok(ScanResult::Found(..)) => Verdict::Found,
+            Ok(ScanResult::Failed(..) | ScanResult::Error(_)) | Err(_) => Verdict::Error,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Verdict::Clean => "clean",
+            Verdict::Found => "found",
+            Verdict::Error => "error",
+        }
+    }
+}
+
+/// How much was uploaded for a scan.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SizeClass {
+    /// Under 64 KiB.
+    Small,
+    /// Under 1 MiB.
+    Medium,
+    /// Under 16 MiB.
+    Large,
+    Huge,
+}
+
+impl SizeClass {
+    const ALL: [SizeClass; 4] = [
+        SizeClass::Small,
+        SizeClass::Medium,
+        SizeClass::Large,
+        SizeClass::Huge,
+    ];
+
+    pub fn of(bytes: u64) -> Self {
+        match bytes {
+            b if b < 64 << 10 => SizeClass::Small,
+            b if b < 1 << 20 => SizeClass::Medium,
+            b if b < 16 << 20 => SizeClass::Large,
+            _ => SizeClass::Huge,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SizeClass::Small => "small",
+            SizeClass::Medium => "medium",
+            SizeClass::Large => "large",
+            SizeClass::Huge => "huge",
+        }
+    }
+}
+
+/// Scan latency histograms, one per verdict and size class. Attach to a
+/// client with `ClamClient::with_metrics`; recording is lock-free, so one
+/// instance can be shared by every client of a process.
+#[derive(Debug, Default)]
+pub struct ScanMetrics {
+    histograms: [[Histogram; 4]; 3],
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS_MS.len() + 1],
+    sum_micros: AtomicU64,
+}
+
+/// The state of one histogram at the time of `ScanMetrics::snapshot`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    pub verdict: Verdict,
+    pub size: SizeClass,
+    // scans per bucket of LATENCY_BUCKETS_MS, plus the unbounded one
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum: Duration,
+}
+
+impl HistogramSnapshot {
+    /// The upper bound of the bucket the `q` quantile (0.0 to 1.0) falls in,
+    /// or `None` if it is in the unbounded bucket or nothing was recorded.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((self.count as f64) * q.clamp(0.0, 1.0)).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (count, bound) in self.buckets.iter().zip(LATENCY_BUCKETS_MS.iter()) {
+            seen += count;
+            if seen >= rank {
+                return Some(Duration::from_millis(*bound));
+            }
+        }
+        None
+    }
+}
+
+impl ScanMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, verdict: Verdict, bytes: u64, elapsed: Duration) {
+        let histogram = &self.histograms[verdict as usize][SizeClass::of(bytes) as usize];
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| elapsed <= Duration::from_millis(bound))
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+
+        histogram.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        histogram
+            .sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Every histogram that has recorded at least one scan.
+    pub fn snapshot(&self) -> Vec<HistogramSnapshot> {
+        let mut snapshots = Vec::new();
+
+        for verdict in Verdict::ALL {
+            for size in SizeClass::ALL {
+                let histogram = &self.histograms[verdict as usize][size as usize];
+                let buckets = histogram
+                    .buckets
+                    .iter()
+                    .map(|b| b.load(Ordering::Relaxed))
+                    .collect::<Vec<_>>();
+                let count = buckets.iter().sum();
+                if count == 0 {
+                    continue;
+                }
+
+                snapshots.push(HistogramSnapshot {
+                    verdict,
+                    size,
+                    buckets,
+                    count,
+                    sum: Duration::from_micros(histogram.sum_micros.load(Ordering::Relaxed)),
+                });
+            }
+        }
+
+        snapshots
+    }
+
+    /// The histograms in the Prometheus text format, as
+    /// `clamav_scan_duration_seconds` labelled by `verdict` and `size`.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::from(
+            "# HELP clamav_scan_duration_seconds Time taken by clamd scans.\n\
+             # TYPE clamav_scan_duration_seconds histogram\n",
+        );
+
+        for snapshot in self.snapshot() {
+            let labels = format!(
+                "verdict=\"{}\",size=\"{}\"",
+                snapshot.verdict.label(),
+                snapshot.size.label()
+            );
+
+            let mut cumulative = 0;
+            for (count, bound) in snapshot.buckets.iter().zip(LATENCY_BUCKETS_MS.iter()) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "clamav_scan_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels,
+                    *bound as f64 / 1000.0,
+                    cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "clamav_scan_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, snapshot.count
+            );
+            let _ = writeln!(
+                out,
+                "clamav_scan_duration_seconds_sum{{{}}} {}",
+                labels,
+                snapshot.sum.as_secs_f64()
+            );
+            let _ = writeln!(
+                out,
+                "clamav_scan_duration_seconds_count{{{}}} {}",
+                labels, snapshot.count
+            );
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histograms() {
+        let metrics = ScanMetrics::new();
+        for ms in [1, 3, 3, 40, 60_000] {
+            metrics.record(Verdict::Clean, 100, Duration::from_millis(ms));
+        }
+        metrics.record(Verdict::Found, 2 << 20, Duration::from_millis(7));
+
+        let snapshots = metrics.snapshot();
+        assert_eq!(snapshots.len(), 2);
+
+        let clean = &snapshots[0];
+        assert_eq!(
+            (clean.verdict, clean.size),
+            (Verdict::Clean, SizeClass::Small)
+        );
+        assert_eq!(clean.count, 5);
+        assert_eq!(clean.quantile(0.5), Some(Duration::from_millis(5)));
+        assert_eq!(clean.quantile(0.8), Some(Duration::from_millis(50)));
+        assert_eq!(clean.quantile(0.99), None);
+        assert_eq!(snapshots[1].size, SizeClass::Large);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains(
+            "clamav_scan_duration_seconds_bucket{verdict=\"clean\",size=\"small\",le=\"0.005\"} 3\n"
+        ));
+        assert!(text
+            .contains("clamav_scan_duration_seconds_count{verdict=\"found\",size=\"large\"} 1\n"));
+    }
+}
@@ -0,0 +1,170 @@
+//! Minimal clamd stand-in used by the unit tests.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+pub struct MockDaemon {
+    pub port: u16,
+    handle: JoinHandle<Vec<Received>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Received {
+    pub command: Vec<u8>,
+    pub chunks: Vec<Vec<u8>>,
+}
+
+impl Received {
+    pub fn payload(&self) -> Vec<u8> {
+        self.chunks.concat()
+    }
+}
+
+impl MockDaemon {
+    /// Accepts one connection, records the command (and INSTREAM frames if
+    /// any) and answers with `reply`.
+    pub fn start(reply: &'static [u8]) -> Self {
+        Self::with_limit(reply, usize::MAX)
+    }
+
+    /// Like `start`, but replies as soon as `limit` bytes of INSTREAM payload
+    /// have arrived, the way clamd rejects streams over StreamMaxLength.
+    pub fn with_limit(reply: &'static [u8], limit: usize) -> Self {
+        Self::serve(vec![reply], limit)
+    }
+
+    /// Like `start`, but answers `connections` connections one after another.
+    pub fn start_many(reply: &'static [u8], connections: usize) -> Self {
+        Self::serve(vec![reply; connections], usize::MAX)
+    }
+
+    /// Answers one connection per reply, in order.
+    pub fn start_replies(replies: &[&'static [u8]]) -> Self {
+        Self::serve(replies.to_vec(), usize::MAX)
+    }
+
+    fn serve(replies: Vec<&'static [u8]>, limit: usize) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            replies
+                .into_iter()
+                .map(|reply| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let received = receive(&mut stream, limit);
+                    // the client may already have hung up, e.g. after a local error
+                    let _ = stream.write_all(reply);
+                    let _ = stream.shutdown(Shutdown::Write);
+                    // drain whatever is still in flight so closing doesn't reset
+                    let _ = io::copy(&mut stream, &mut io::sink());
+                    received
+                })
+                .collect()
+        });
+
+        Self { port, handle }
+    }
+
+    /// Accepts one IDSESSION connection and answers every command in it
+    /// with `"<id>: <reply>"` until the client sends END or hangs up.
+    pub fn start_session(reply: &'static str) -> Self {
+        Self::start_sessions(reply, 1)
+    }
+
+    /// Like `start_session`, but serves `connections` sessions at once.
+    /// Commands are recorded session by session; a connection that doesn't
+    /// start with IDSESSION gets `reply` once.
+    pub fn start_sessions(reply: &'static str, connections: usize) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let sessions = (0..connections)
+                .map(|_| {
+                    let (stream, _) = listener.accept().unwrap();
+                    thread::spawn(move || serve_session(stream, reply))
+                })
+                .collect::<Vec<_>>();
+
+            sessions
+                .into_iter()
+                .flat_map(|session| session.join().unwrap())
+                .collect()
+        });
+
+        Self { port, handle }
+    }
+
+    pub fn received(self) -> Received {
+        self.all_received().remove(0)
+    }
+
+    pub fn all_received(self) -> Vec<Received> {
+        self.handle.join().unwrap()
+    }
+}
+
+fn serve_session(mut stream: TcpStream, reply: &str) -> Vec<Received> {
+    let mut commands = vec![receive(&mut stream, usize::MAX)];
+    if !commands[0].command.ends_with(b"IDSESSION") {
+        let _ = stream.write_all(format!("{}\0", reply).as_bytes());
+        return commands;
+    }
+
+    for id in 1.. {
+        let received = receive(&mut stream, usize::MAX);
+        if received.command.is_empty() || received.command.ends_with(b"END") {
+            commands.push(received);
+            break;
+        }
+
+        commands.push(received);
+        let _ = stream.write_all(format!("{}: {}\0", id, reply).as_bytes());
+    }
+
+    commands
+}
+
+fn receive(stream: &mut TcpStream, limit: usize) -> Received {
+    let mut received = Received::default();
+    let mut total = 0;
+    let mut byte = [0; 1];
+
+    loop {
+        if stream.read_exact(&mut byte).is_err() {
+            return received;
+        }
+        if byte[0] == 0 || byte[0] == b'\n' {
+            break;
+        }
+        received.command.push(byte[0]);
+    }
+
+    if received.command.ends_with(b"INSTREAM") {
+        loop {
+            let mut length = [0; 4];
+            if stream.read_exact(&mut length).is_err() {
+                return received;
+            }
+            let length = u32::from_be_bytes(length) as usize;
+            if length == 0 {
+                break;
+            }
+
+            let mut chunk = vec![0; length];
+            if stream.read_exact(&mut chunk).is_err() {
+                return received;
+            }
+            received.chunks.push(chunk);
+
+            total += length;
+            if total >= limit {
+                break;
+            }
+        }
+    }
+
+    received
+}
@@ -0,0 +1,137 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::client::{ClamClient, Result};
+use crate::error::ClamError;
+use crate::response::{Stats, StatsDelta};
+
+// polling slows down to at most this many intervals while STATS keeps failing
+const MAX_BACKOFF: u32 = 16;
+
+/// One STATS poll, with the change since the previous successful poll.
+#[derive(Debug)]
+pub struct StatsSnapshot {
+    pub stats: Stats,
+    pub delta: Option<StatsDelta>,
+    pub taken_at: SystemTime,
+}
+
+/// Polls STATS on a background thread and delivers every snapshot, or the
+/// error of a failed poll, over a channel. Failed polls are retried with
+/// exponential backoff. Dropping the monitor stops the polling thread.
+pub struct StatsMonitor {
+    stop: Option<Sender<()>>,
+    snapshots: Receiver<Result<StatsSnapshot>>,
+    poller: Option<JoinHandle<()>>,
+}
+
+impl StatsMonitor {
+    pub fn start(client: ClamClient, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let (snapshots, receiver) = mpsc::channel();
+        let poller = thread::spawn(move || poll(&client, interval, &stopped, &snapshots));
+
+        Self {
+            stop: Some(stop),
+            snapshots: receiver,
+            poller: Some(poller),
+        }
+    }
+
+    /// Blocks until the next poll completes.
+    pub fn recv(&self) -> Result<StatsSnapshot> {
+        match self.snapshots.recv() {
+            Ok(snapshot) => snapshot,
+            Err(_) => Err(ClamError::ServiceStopped),
+        }
+    }
+
+    /// Returns the next snapshot if one is already waiting.
+    pub fn try_recv(&self) -> Option<Result<StatsSnapshot>> {
+        self.snapshots.try_recv().ok()
+    }
+
+    /// Stops polling and waits for the polling thread to exit.
+    pub fn stop(mut self) {
+        self.halt();
+    }
+
+    fn halt(&mut self) {
+        self.stop = None;
+        if let Some(poller) = self.poller.take() {
+            let _ = poller.join();
+        }
+    }
+}
+
+impl Drop for StatsMonitor {
+    fn drop(&mut self) {
+        self.halt();
+    }
+}
+
+fn poll(
+    client: &ClamClient,
+    interval: Duration,
+    stopped: &Receiver<()>,
+    snapshots: &Sender<Result<StatsSnapshot>>,
+) {
+    let mut previous: Option<Stats> = None;
+    let mut backoff = 1;
+
+    loop {
+        let snapshot = client.stats().map(|stats| StatsSnapshot {
+            delta: previous.as_ref().map(|earlier| stats.delta(earlier)),
+            taken_at: SystemTime::now(),
+            stats,
+        });
+
+        let snapshot = match snapshot {
+            Ok(snapshot) => {
+                backoff = 1;
+                previous = Some(snapshot.stats.clone());
+                Ok(snapshot)
+            }
+            Err(e) => {
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                Err(e)
+            }
+        };
+
+        if snapshots.send(snapshot).is_err() {
+            return;
+        }
+
+        match stopped.recv_timeout(interval * backoff) {
+            Err(RecvTimeoutError::Timeout) => continue,
+            _ => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDaemon;
+
+    static STATS: &[u8] = b"POOLS: 1\n\nSTATE: VALID PRIMARY\nTHREADS: live 1  idle 0 max 12 idle-timeout 30\nQUEUE: 0 items\n\nMEMSTATS: heap 9.082M mmap 0.000M used 6.902M free 2.184M releasable 0.129M pools 1 pools_used 565.979M pools_total 565.999M\nEND\0";
+
+    #[test]
+    fn test_monitor_delivers_snapshots_and_deltas() {
+        let daemon = MockDaemon::start_many(STATS, 2);
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let monitor = StatsMonitor::start(client, Duration::from_millis(10));
+
+        let first = monitor.recv().unwrap();
+        assert_eq!(first.stats.threads_max, 12);
+        assert!(first.delta.is_none());
+
+        let second = monitor.recv().unwrap();
+        assert_eq!(second.delta.unwrap().queue, 0);
+
+        assert!(monitor.recv().is_err());
+        monitor.stop();
+        assert_eq!(daemon.all_received().len(), 2);
+    }
+}
@@ -0,0 +1,247 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client::Result;
+use crate::error::ClamError;
+use crate::json;
+use crate::response::Signature;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Something was found in a scanned input.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Detection {
+    // the file path, or whatever names a stream
+    pub label: String,
+    pub signature: Signature,
+    // the daemon's VERSION reply, if it could be read
+    pub db_version: Option<String>,
+    pub detected_at: SystemTime,
+}
+
+impl Detection {
+    pub fn new(label: &str, signature: &Signature) -> Self {
+        Self {
+            label: label.to_string(),
+            signature: signature.clone(),
+            db_version: None,
+            detected_at: SystemTime::now(),
+        }
+    }
+
+    pub fn with_db_version(mut self, db_version: Option<String>) -> Self {
+        self.db_version = db_version;
+        self
+    }
+
+    /// The webhook payload: `label`, `signature`, `db_version` (or `null`)
+    /// and `timestamp` in seconds since the Unix epoch.
+    pub fn to_json(&self) -> String {
+        let timestamp = self
+            .detected_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        format!(
+            "{{\"label\":{},\"signature\":{},\"db_version\":{},\"timestamp\":{}}}",
+            json::string(&self.label),
+            json::string(&self.signature.raw),
+            json::optional(self.db_version.as_deref()),
+            timestamp
+        )
+    }
+}
+
+/// POSTs every detection as JSON to a URL, for alerting tools that accept
+/// webhooks. Only plain `http://` URLs are supported; put a TLS-terminating
+/// proxy in front of endpoints that need HTTPS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    host: String,
+    port: u16,
+    path: String,
+    timeout: Duration,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Result<Self> {
+        let rest = match url.strip_prefix("http://") {
+            Some(rest) => rest,
+            None => {
+                return Err(ClamError::InvalidData(format!(
+                    "unsupported webhook URL {:?}, expected http://",
+                    url
+                )))
+            }
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => match port.parse() {
+                Ok(port) => (host, port),
+                Err(e) => return Err(ClamError::IntParseError(e)),
+            },
+            _ => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(ClamError::InvalidData(format!("no host in {:?}", url)));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            timeout: TIMEOUT,
+        })
+    }
+
+    /// Limits connecting, sending and waiting for the response, 10 seconds
+    /// by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends `detection`, failing unless the endpoint answers with a 2xx
+    /// status.
+    pub fn notify(&self, detection: &Detection) -> Result<()> {
+        match self.post(&detection.to_json()) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(ClamError::NotifyError(e)),
+        }
+    }
+
+    /// Connects to each address in turn until one accepts.
+    fn connect<I: Iterator<Item = SocketAddr>>(&self, addresses: I) -> io::Result<TcpStream> {
+        let mut last_error = None;
+
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, self.timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| io::Error::new(ErrorKind::NotFound, "host not found")))
+    }
+
+    fn post(&self, body: &str) -> io::Result<()> {
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        let mut stream = self.connect((host, self.port).to_socket_addrs()?)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path,
+            self.host,
+            self.port,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes())?;
+
+        // only the status line matters
+        let mut head = [0; 12];
+        stream.read_exact(&mut head)?;
+        let status = String::from_utf8_lossy(&head);
+        match status.strip_prefix("HTTP/1.") {
+            Some(rest) if rest.get(2..).is_some_and(|code| code.starts_with('2')) => Ok(()),
+            _ => Err(io::Error::other(format!(
+                "webhook answered {:?}",
+                status.trim_end()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_parse_url() {
+        let webhook = Webhook::new("http://soc.example:8080/hooks/clamav").unwrap();
+        assert_eq!(webhook.host, "soc.example");
+        assert_eq!(webhook.port, 8080);
+        assert_eq!(webhook.path, "/hooks/clamav");
+
+        let webhook = Webhook::new("http://[::1]").unwrap();
+        assert_eq!((webhook.host.as_str(), webhook.port), ("[::1]", 80));
+        assert_eq!(webhook.path, "/");
+
+        assert!(Webhook::new("https://soc.example/").is_err());
+        assert!(Webhook::new("http://soc.example:http/").is_err());
+    }
+
+    #[test]
+    fn test_notify() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/alert", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !String::from_utf8_lossy(&request).ends_with('}') {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let signature = Signature::from("Eicar-Test-Signature");
+        let detection = Detection {
+            detected_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            ..Detection::new("/srv/\"a\".com", &signature)
+        };
+        Webhook::new(&url).unwrap().notify(&detection).unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /alert HTTP/1.1\r\n"));
+        assert!(request.ends_with(
+            r#"{"label":"/srv/\"a\".com","signature":"Eicar-Test-Signature","db_version":null,"timestamp":1700000000}"#
+        ));
+    }
+
+    #[test]
+    fn test_connect_tries_every_address() {
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let webhook = Webhook::new(&format!("http://{}/", closed)).unwrap();
+
+        let addresses = vec![closed, listener.local_addr().unwrap()];
+        let stream = webhook.connect(addresses.into_iter()).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+        assert!(webhook.connect(vec![closed].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_notify_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]);
+            let _ = stream.write_all(b"HTTP/1.1 500 Internal Server Error\r\n\r\n");
+        });
+
+        let detection = Detection::new("stream", &Signature::from("Eicar"));
+        assert!(matches!(
+            Webhook::new(&url).unwrap().notify(&detection),
+            Err(ClamError::NotifyError(_))
+        ));
+    }
+}
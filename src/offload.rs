@@ -0,0 +1,485 @@
+use std::future::Future;
+use std::io::{self, ErrorKind, Read};
+use std::panic;
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net;
+use tokio::task::{self, JoinError};
+
+use crate::cancel::CancelToken;
+use crate::client::{check_terminated, read_failed, ClamClient, Result, Tracked};
+use crate::command::Command;
+use crate::error::ClamError;
+use crate::pool::BufferPool;
+use crate::response::{ClamResponse, ReloadAck, ScanResult, Stats, Version};
+use crate::transport::Connection;
+#[cfg(windows)]
+use crate::transport::Endpoint;
+
+/// Runs the blocking client on tokio's blocking thread pool, so scans can be
+/// awaited without stalling the runtime's worker threads. Dropping the
+/// future of a streaming scan cancels the upload instead of letting it run
+/// to completion in the background.
+#[derive(Clone)]
+pub struct AsyncClamClient {
+    client: Arc<ClamClient>,
+}
+
+/// Cancels the token on drop unless disarmed, tying a detached blocking scan
+/// to the lifetime of the future awaiting it.
+struct CancelOnDrop(Option<CancelToken>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+impl AsyncClamClient {
+    pub fn new(client: ClamClient) -> Self {
+        Self {
+            client: Arc::new(client),
+        }
+    }
+
+    pub fn client(&self) -> &ClamClient {
+        &self.client
+    }
+
+    pub async fn ping(&self) -> bool {
+        self.offload(|client| Ok(client.ping()))
+            .await
+            .unwrap_or(false)
+    }
+
+    pub async fn version(&self) -> Result<Version> {
+        self.offload(|client| client.version()).await
+    }
+
+    pub async fn reload(&self) -> Result<ReloadAck> {
+        self.offload(|client| client.reload()).await
+    }
+
+    pub async fn stats(&self) -> Result<Stats> {
+        self.offload(|client| client.stats()).await
+    }
+
+    pub async fn scan_path<P: Into<String>>(
+        &self,
+        path: P,
+        continue_on_virus: bool,
+    ) -> Result<Vec<ScanResult>> {
+        let path = path.into();
+        self.offload(move |client| client.scan_path(&path, continue_on_virus))
+            .await
+    }
+
+    pub async fn scan_file<P>(&self, path: P) -> Result<ScanResult>
+    where
+        P: AsRef<Path> + Send + 'static,
+    {
+        self.offload(move |client| client.scan_file(path)).await
+    }
+
+    pub async fn scan_bytes<B>(&self, b: B) -> Result<ScanResult>
+    where
+        B: AsRef<[u8]> + Send + 'static,
+    {
+        self.offload_cancellable(move |client, token| client.scan_bytes_cancellable(b, token))
+            .await
+    }
+
+    pub async fn scan_stream<T>(&self, s: T) -> Result<ScanResult>
+    where
+        T: Read + Send + 'static,
+    {
+        self.offload_cancellable(move |client, token| client.scan_stream_cancellable(s, token))
+            .await
+    }
+
+    /// Like `ClamClient::scan_bytes_batch`.
+    pub async fn scan_bytes_batch<B>(&self, batch: Vec<B>) -> Result<Vec<ScanResult>>
+    where
+        B: AsRef<[u8]> + Send + 'static,
+    {
+        self.offload(move |client| {
+            let batch = batch.iter().map(B::as_ref).collect::<Vec<_>>();
+            client.scan_bytes_batch(&batch)
+        })
+        .await
+    }
+
+    /// Streams `reader` to clamd on the runtime itself instead of a blocking
+    /// thread, so tokio files, sockets and decoders can be scanned without
+    /// bridging them to `Read`. Only connecting is offloaded, to reuse the
+    /// client's address handling and scan rate limit. The client's byte rate
+    /// limiter is not applied.
+    pub async fn scan_async_read<R: AsyncRead + Unpin>(&self, reader: R) -> Result<ScanResult> {
+        let mut tracked = self.client.track("stream", None);
+        let result = self.stream_async(reader, &mut tracked).await;
+        self.client.finish(tracked, result.as_ref())?;
+        result
+    }
+
+    async fn stream_async<R: AsyncRead + Unpin>(
+        &self,
+        reader: R,
+        tracked: &mut Tracked,
+    ) -> Result<ScanResult> {
+        let _permit = self.offload(|client| client.scan_permit()).await?;
+        let mut buffer = BufferPool::get(self.client.buffers());
+        // room for a frame: the length prefix, then up to a chunk of payload
+        buffer.resize(4 + self.client.chunk_size(), 0);
+
+        // tokio needs a pipe handle opened for overlapped I/O
+        #[cfg(windows)]
+        if let Endpoint::Pipe(path) = self.client.endpoint() {
+            return match net::windows::named_pipe::ClientOptions::new().open(&path) {
+                Ok(pipe) => instream(pipe, reader, &mut buffer, &self.client, tracked).await,
+                Err(e) => Err(ClamError::ConnectionError(e)),
+            };
+        }
+
+        match self.offload(|client| client.connect()).await? {
+            Connection::Tcp(s) => {
+                let connection = match s
+                    .set_nonblocking(true)
+                    .and_then(|_| net::TcpStream::from_std(s))
+                {
+                    Ok(connection) => connection,
+                    Err(e) => return Err(ClamError::ConnectionError(e)),
+                };
+                instream(connection, reader, &mut buffer, &self.client, tracked).await
+            }
+            #[cfg(unix)]
+            Connection::Unix(s) => {
+                let connection = match s
+                    .set_nonblocking(true)
+                    .and_then(|_| net::UnixStream::from_std(s))
+                {
+                    Ok(connection) => connection,
+                    Err(e) => return Err(ClamError::ConnectionError(e)),
+                };
+                instream(connection, reader, &mut buffer, &self.client, tracked).await
+            }
+            #[cfg(windows)]
+            Connection::Pipe(_) => unreachable!("named pipes are opened above"),
+            Connection::Memory(m) => instream(m, reader, &mut buffer, &self.client, tracked).await,
+        }
+    }
+
+    async fn offload<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&ClamClient) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let client = Arc::clone(&self.client);
+        match task::spawn_blocking(move || f(&client)).await {
+            Ok(result) => result,
+            Err(e) => Err(join_error(e)),
+        }
+    }
+
+    async fn offload_cancellable<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&ClamClient, &CancelToken) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let token = CancelToken::new();
+        let mut guard = CancelOnDrop(Some(token.clone()));
+        let result = self.offload(move |client| f(client, &token)).await;
+        guard.0 = None;
+        result
+    }
+}
+
+/// Uploads `reader` and reads the verdict. If the upload breaks off because
+/// clamd has already replied (e.g. the size limit was exceeded), that reply
+/// is returned.
+async fn instream<S, R>(
+    mut connection: S,
+    mut reader: R,
+    buffer: &mut [u8],
+    client: &ClamClient,
+    tracked: &mut Tracked,
+) -> Result<ScanResult>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    let (counters, framing) = (client.counters(), client.framing());
+    let written = upload(&mut connection, &mut reader, buffer, client, tracked).await;
+    // clamd is still waiting for the rest of the stream, or wedged
+    if let Err(e @ ClamError::StreamError(_)) | Err(e @ ClamError::Timeout(_)) = written {
+        return Err(e);
+    }
+
+    let mut reply = Vec::new();
+    let read = timed(client, connection.read_to_end(&mut reply)).await;
+    counters.received(reply.len() as u64);
+    match read {
+        Err(e) if e.kind() == ErrorKind::TimedOut => return Err(ClamError::Timeout(e)),
+        Err(e) if reply.is_empty() => {
+            written?;
+            return Err(ClamError::ConnectionError(e));
+        }
+        Ok(_) if reply.is_empty() => written?,
+        _ => {}
+    }
+
+    check_terminated(&reply, framing)?;
+    framing.normalize(&Command::Instream, &mut reply);
+    <ScanResult as ClamResponse>::parse(&reply)
+}
+
+async fn upload<S, R>(
+    connection: &mut S,
+    reader: &mut R,
+    buffer: &mut [u8],
+    client: &ClamClient,
+    tracked: &mut Tracked,
+) -> Result<()>
+where
+    S: AsyncWrite + Unpin,
+    R: AsyncRead + Unpin,
+{
+    let (counters, framing) = (client.counters(), client.framing());
+    let command = Command::Instream.encode_framed(framing);
+    if let Err(e) = timed(client, connection.write_all(&command)).await {
+        return Err(read_failed(e, ClamError::CommandError));
+    }
+    counters.command();
+    counters.sent(command.len() as u64);
+
+    loop {
+        // read behind the prefix, so the frame goes out in a single write
+        let bytes_read = match reader.read(&mut buffer[4..]).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(ClamError::StreamError(e)),
+        };
+
+        buffer[..4].copy_from_slice(&(bytes_read as u32).to_be_bytes());
+        let frame = &buffer[..4 + bytes_read];
+        if let Err(e) = timed(client, connection.write_all(frame)).await {
+            return Err(read_failed(e, ClamError::CommandError));
+        }
+        counters.sent(frame.len() as u64);
+        client.chunk_sent(tracked, bytes_read as u64);
+    }
+
+    match timed(client, connection.write_all(&[0; 4])).await {
+        Ok(_) => match timed(client, connection.flush()).await {
+            Ok(_) => {
+                counters.sent(4);
+                Ok(())
+            }
+            Err(e) => Err(read_failed(e, ClamError::CommandError)),
+        },
+        Err(e) => Err(read_failed(e, ClamError::CommandError)),
+    }
+}
+
+/// Runs `io` within the client's read timeout, if it has one, so a wedged
+/// daemon can't stall the scan.
+async fn timed<F, T>(client: &ClamClient, io: F) -> io::Result<T>
+where
+    F: Future<Output = io::Result<T>>,
+{
+    match client.read_timeout() {
+        Some(timeout) => match tokio::time::timeout(timeout, io).await {
+            Ok(done) => done,
+            Err(_) => Err(io::Error::new(ErrorKind::TimedOut, "timed out")),
+        },
+        None => io.await,
+    }
+}
+
+/// Re-raises a panic from the blocking task on the awaiting task; a task
+/// dropped by a shutting down runtime counts as cancelled.
+fn join_error(e: JoinError) -> ClamError {
+    if e.is_panic() {
+        panic::resume_unwind(e.into_panic());
+    }
+    ClamError::Cancelled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDaemon;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    fn block_on<F: std::future::Future>(f: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(f)
+    }
+
+    #[test]
+    fn test_scan_bytes_offloaded() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let client = AsyncClamClient::new(ClamClient::new("127.0.0.1", daemon.port).unwrap());
+
+        let result = block_on(client.scan_bytes(b"hello".to_vec())).unwrap();
+
+        assert_eq!(result, ScanResult::Ok);
+        assert_eq!(daemon.received().payload(), b"hello");
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn test_scan_bytes_cached() {
+        let cache = Arc::new(crate::cache::LruScanCache::new(
+            8,
+            std::time::Duration::from_secs(60),
+        ));
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let client = AsyncClamClient::new(
+            ClamClient::new("127.0.0.1", daemon.port)
+                .unwrap()
+                .with_scan_cache(cache.clone()),
+        );
+
+        assert_eq!(
+            block_on(client.scan_bytes(b"payload")).unwrap(),
+            ScanResult::Ok
+        );
+        daemon.received();
+
+        // the mock daemon is gone, so this can only be answered from cache
+        assert_eq!(
+            block_on(client.scan_bytes(b"payload")).unwrap(),
+            ScanResult::Ok
+        );
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_scan_bytes_batch_offloaded() {
+        let daemon = MockDaemon::start_session("stream: OK");
+        let client = AsyncClamClient::new(ClamClient::new("127.0.0.1", daemon.port).unwrap());
+
+        let results = block_on(client.scan_bytes_batch(vec![b"one".to_vec(), b"two".to_vec()]));
+
+        assert_eq!(results.unwrap(), vec![ScanResult::Ok; 2]);
+        assert_eq!(daemon.all_received()[2].payload(), b"two");
+    }
+
+    #[test]
+    fn test_scan_async_read_streams_on_runtime() {
+        let daemon = MockDaemon::start(b"stream: Eicar-Test-Signature FOUND\0");
+        let client = AsyncClamClient::new(ClamClient::new("127.0.0.1", daemon.port).unwrap());
+        let data = vec![7u8; 10_000];
+
+        let result = block_on(client.scan_async_read(data.as_slice())).unwrap();
+
+        assert!(matches!(result, ScanResult::Found(..)));
+        let received = daemon.received();
+        assert_eq!(received.command, b"zINSTREAM");
+        assert_eq!(received.payload(), data);
+    }
+
+    #[test]
+    fn test_upload_writes_whole_frames() {
+        // records every write it is handed
+        #[derive(Default)]
+        struct Writes(Vec<Vec<u8>>);
+
+        impl AsyncWrite for Writes {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                self.0.push(buf.to_vec());
+                Poll::Ready(Ok(buf.len()))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        let client = ClamClient::new("127.0.0.1", 1).unwrap();
+        let mut tracked = client.track("stream", None);
+        let mut writes = Writes::default();
+        let mut buffer = vec![0; 4 + 8];
+
+        block_on(upload(
+            &mut writes,
+            &mut &b"0123456789"[..],
+            &mut buffer,
+            &client,
+            &mut tracked,
+        ))
+        .unwrap();
+
+        let frames = &writes.0[1..];
+        assert_eq!(frames[0], b"\0\0\0\x0801234567");
+        assert_eq!(frames[1], b"\0\0\0\x0289");
+        assert_eq!(frames[2], [0; 4]);
+    }
+
+    #[test]
+    fn test_scan_async_read_timeout() {
+        // accepted by the kernel, never answered
+        let wedged = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = wedged.local_addr().unwrap().port();
+        let client = AsyncClamClient::new(
+            ClamClient::new("127.0.0.1", port)
+                .unwrap()
+                .with_read_timeout(std::time::Duration::from_millis(50)),
+        );
+
+        match block_on(client.scan_async_read(&b"data"[..])) {
+            Err(ClamError::Timeout(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scan_async_read_audited() {
+        let daemon = MockDaemon::start(b"stream: Eicar FOUND\0");
+        let path = std::env::temp_dir().join(format!(
+            "clamav-client-audit-async-test-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let log = Arc::new(crate::AuditLog::open(&path).unwrap());
+        let client = AsyncClamClient::new(
+            ClamClient::new("127.0.0.1", daemon.port)
+                .unwrap()
+                .with_audit_log(log),
+        );
+
+        block_on(client.scan_async_read(&b"data"[..])).unwrap();
+        daemon.received();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(written.contains(r#""input":"stream","verdict":"found","signature":"Eicar""#));
+    }
+
+    #[test]
+    fn test_panics_propagate() {
+        let client = AsyncClamClient::new(ClamClient::new("127.0.0.1", 1).unwrap());
+        let outcome = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            block_on(client.offload(|_| -> Result<()> { panic!("boom") }))
+        }));
+
+        assert!(outcome.is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::client::Result;
+use crate::command::Command;
+use crate::error::ClamError;
+
+/// How `ClamClient::scan` asks the daemon to scan a path. Streamed targets
+/// always go through INSTREAM, which has no variants, so the options only
+/// affect path scans.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Keep scanning the remaining files after a match (CONTSCAN).
+    pub continue_on_virus: bool,
+    /// Let the daemon scan files in parallel (MULTISCAN). Implies
+    /// `continue_on_virus`.
+    pub parallel: bool,
+    /// Report every signature matching a file, not only the first
+    /// (ALLMATCHSCAN). Cannot be combined with `parallel`.
+    pub all_match: bool,
+    /// Have the daemon open the files `ClamClient::scan_glob` matches
+    /// itself instead of streaming them, for daemons that share the
+    /// client's filesystem.
+    pub shared_filesystem: bool,
+}
+
+impl ScanOptions {
+    pub(crate) fn path_command(&self, path: &str) -> Result<Command> {
+        let path = PathBuf::from(path);
+        let command = match (self.parallel, self.all_match, self.continue_on_virus) {
+            (true, true, _) => {
+                return Err(ClamError::InvalidData(String::from(
+                    "parallel and all_match scans cannot be combined",
+                )))
+            }
+            (true, false, _) => Command::MultiScan(path),
+            (false, true, _) => Command::AllMatchScan(path),
+            (false, false, true) => Command::ContScan(path),
+            (false, false, false) => Command::Scan(path),
+        };
+
+        Ok(command)
+    }
+}
+
+/// What `ClamClient::scan` scans.
+pub enum ScanTarget<'a> {
+    /// A path on the daemon's filesystem, scanned in place.
+    Path(&'a str),
+    /// In-memory data, streamed to the daemon.
+    Bytes(&'a [u8]),
+    /// Data read to the end and streamed to the daemon.
+    Reader(Box<dyn Read + 'a>),
+}
+
+impl<'a> ScanTarget<'a> {
+    pub fn path(path: &'a str) -> Self {
+        ScanTarget::Path(path)
+    }
+
+    pub fn reader<R: Read + 'a>(reader: R) -> Self {
+        ScanTarget::Reader(Box::new(reader))
+    }
+}
+
+impl<'a> From<&'a [u8]> for ScanTarget<'a> {
+    fn from(b: &'a [u8]) -> Self {
+        ScanTarget::Bytes(b)
+    }
+}
+
+impl<'a> From<&'a Vec<u8>> for ScanTarget<'a> {
+    fn from(b: &'a Vec<u8>) -> Self {
+        ScanTarget::Bytes(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_commands() {
+        let command = |options: ScanOptions| options.path_command("/tmp").unwrap().encode();
+
+        assert_eq!(command(ScanOptions::default()), b"zSCAN /tmp\0");
+        assert_eq!(
+            command(ScanOptions {
+                continue_on_virus: true,
+                ..Default::default()
+            }),
+            b"zCONTSCAN /tmp\0"
+        );
+        assert_eq!(
+            command(ScanOptions {
+                parallel: true,
+                ..Default::default()
+            }),
+            b"zMULTISCAN /tmp\0"
+        );
+        assert_eq!(
+            command(ScanOptions {
+                all_match: true,
+                continue_on_virus: true,
+                ..Default::default()
+            }),
+            b"zALLMATCHSCAN /tmp\0"
+        );
+        assert!(ScanOptions {
+            parallel: true,
+            all_match: true,
+            ..Default::default()
+        }
+        .path_command("/tmp")
+        .is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use rayon::iter::ParallelIterator;
+
+use crate::client::{ClamClient, Result};
+use crate::response::ScanResult;
+use crate::service::ScanJob;
+
+/// Scans the items of a rayon parallel iterator, such as local files or
+/// buffers, on rayon's thread pool, for batch jobs that are already built on
+/// rayon. Each item is scanned over a connection of its own, with buffers
+/// from the client's `BufferPool`.
+///
+/// ```no_run
+/// use clamav::{ClamClient, ParScan};
+/// use rayon::prelude::*;
+/// use std::path::PathBuf;
+///
+/// let client = ClamClient::new("127.0.0.1", 3310).unwrap();
+/// let files = vec![PathBuf::from("/srv/a"), PathBuf::from("/srv/b")];
+/// let results = files.into_par_iter().par_scan(&client);
+/// ```
+pub trait ParScan: ParallelIterator
+where
+    Self::Item: Into<ScanJob>,
+{
+    /// The verdict of every item, in the order of the iterator.
+    fn par_scan(self, client: &ClamClient) -> Vec<Result<ScanResult>> {
+        self.map(|item| item.into().scan(client)).collect()
+    }
+}
+
+impl<I> ParScan for I
+where
+    I: ParallelIterator,
+    I::Item: Into<ScanJob>,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDaemon;
+    use rayon::iter::IntoParallelIterator;
+
+    #[test]
+    fn test_par_scan() {
+        let daemon = MockDaemon::start_many(b"stream: OK\0", 3);
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let batch = vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()];
+        let results = batch.into_par_iter().par_scan(&client);
+        assert_eq!(results.len(), 3);
+        assert!(results.into_iter().all(|r| r.unwrap() == ScanResult::Ok));
+
+        let mut payloads = daemon
+            .all_received()
+            .iter()
+            .map(|r| r.payload())
+            .collect::<Vec<_>>();
+        payloads.sort();
+        assert_eq!(
+            payloads,
+            vec![b"one".to_vec(), b"three".to_vec(), b"two".to_vec()]
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::response::Signature;
+
+/// Called with the path and the scanned handle of an infected file.
+pub type Callback = Arc<dyn Fn(&Path, &File, &Signature) -> io::Result<()> + Send + Sync>;
+
+/// What to do with a file something was found in.
+///
+/// Actions work on the handle the file was scanned through, so they can't
+/// hit a different file that was put at the same path after the scan:
+/// `Revoke` changes the handle's permissions, and `Delete`/`Quarantine`
+/// first rename the path and then check that the renamed file is the
+/// scanned one, renaming it back if it isn't.
+#[derive(Clone)]
+pub enum Action {
+    Delete,
+    /// Moves the file into the directory, adding a timestamp to its name so
+    /// earlier detections of a file with the same name are kept.
+    Quarantine(PathBuf),
+    /// Removes all permissions (`chmod 000`). Outside Unix the file is only
+    /// made read-only.
+    Revoke,
+    Callback(Callback),
+}
+
+impl fmt::Debug for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Delete => write!(f, "Delete"),
+            Action::Quarantine(dir) => f.debug_tuple("Quarantine").field(dir).finish(),
+            Action::Revoke => write!(f, "Revoke"),
+            Action::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+/// The result of an `Action`, as listed in `ScanReport::actions`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ActionTaken {
+    Deleted,
+    Quarantined(PathBuf),
+    Revoked,
+    CallbackRan,
+    /// The action failed or was refused, e.g. because the path no longer
+    /// refers to the scanned file.
+    Failed(String),
+}
+
+impl fmt::Display for ActionTaken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActionTaken::Deleted => write!(f, "deleted"),
+            ActionTaken::Quarantined(to) => write!(f, "quarantined to {}", to.display()),
+            ActionTaken::Revoked => write!(f, "permissions removed"),
+            ActionTaken::CallbackRan => write!(f, "callback ran"),
+            ActionTaken::Failed(reason) => write!(f, "action failed: {}", reason),
+        }
+    }
+}
+
+impl Action {
+    /// Applies the action to `path`, which was scanned through `file`.
+    pub fn apply(&self, path: &Path, file: &File, signature: &Signature) -> ActionTaken {
+        let taken = match self {
+            Action::Delete => delete(path, file).map(|()| ActionTaken::Deleted),
+            Action::Quarantine(dir) => quarantine(path, file, dir).map(ActionTaken::Quarantined),
+            Action::Revoke => revoke(file).map(|()| ActionTaken::Revoked),
+            Action::Callback(callback) => {
+                callback(path, file, signature).map(|()| ActionTaken::CallbackRan)
+            }
+        };
+
+        match taken {
+            Ok(taken) => taken,
+            Err(e) => ActionTaken::Failed(e.to_string()),
+        }
+    }
+}
+
+fn delete(path: &Path, file: &File) -> io::Result<()> {
+    let staged = staging_path(path);
+    claim(path, &staged, file)?;
+    fs::remove_file(staged)
+}
+
+fn quarantine(path: &Path, file: &File, dir: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let mut to = dir.join(format!("{}.{}", name, stamp));
+    for n in 1.. {
+        if fs::symlink_metadata(&to).is_err() {
+            break;
+        }
+        to = dir.join(format!("{}.{}.{}", name, stamp, n));
+    }
+
+    match claim(path, &to, file) {
+        Ok(()) => Ok(to),
+        // e.g. the quarantine is on another filesystem: claim the file
+        // where it is and copy the scanned content over
+        Err(e) if e.kind() != ErrorKind::InvalidData => {
+            let staged = staging_path(path);
+            claim(path, &staged, file)?;
+
+            let mut file = file;
+            file.seek(SeekFrom::Start(0))?;
+            let copied = File::create(&to).and_then(|mut out| io::copy(&mut file, &mut out));
+            if let Err(e) = copied {
+                let _ = fs::remove_file(&to);
+                let _ = fs::rename(&staged, path);
+                return Err(e);
+            }
+
+            fs::remove_file(staged)?;
+            Ok(to)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn revoke(file: &File) -> io::Result<()> {
+    let mut permissions = file.metadata()?.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(0o000);
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(true);
+
+    file.set_permissions(permissions)
+}
+
+// a hidden name next to `path`, so renaming there stays on one filesystem
+fn staging_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.clamav-{}", name, std::process::id()))
+}
+
+/// Renames `from` to `to` and makes sure what was renamed is `file`,
+/// renaming it back otherwise. Renaming is atomic, so once this succeeds
+/// `to` is the scanned file whatever happens at `from` meanwhile.
+fn claim(from: &Path, to: &Path, file: &File) -> io::Result<()> {
+    fs::rename(from, to)?;
+
+    let same = match (fs::symlink_metadata(to), file.metadata()) {
+        (Ok(renamed), Ok(scanned)) => same_file(&renamed, &scanned),
+        _ => false,
+    };
+    if !same {
+        let _ = fs::rename(to, from);
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "the file was replaced after it was scanned",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+// without inode numbers, a file of the same type, size and modification
+// time is taken to be the same
+#[cfg(not(unix))]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    a.file_type() == b.file_type() && a.len() == b.len() && a.modified().ok() == b.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(name: &str) -> (PathBuf, PathBuf, File) {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("quarantine")).unwrap();
+        let path = dir.join("eicar.com");
+        fs::write(&path, b"data").unwrap();
+        let file = File::open(&path).unwrap();
+        (dir, path, file)
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let (dir, path, file) = setup("clamav-client-quarantine-test");
+        let signature = Signature::from("Eicar");
+
+        let taken = Action::Quarantine(dir.join("quarantine")).apply(&path, &file, &signature);
+        let to = match taken {
+            ActionTaken::Quarantined(to) => to,
+            other => panic!("{:?}", other),
+        };
+
+        assert!(to.starts_with(dir.join("quarantine")));
+        assert_eq!(fs::read(&to).unwrap(), b"data");
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replaced_file_is_left_alone() {
+        let (dir, path, file) = setup("clamav-client-replaced-test");
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, b"innocent").unwrap();
+
+        let taken = Action::Delete.apply(&path, &file, &Signature::from("Eicar"));
+
+        assert!(matches!(taken, ActionTaken::Failed(_)));
+        assert_eq!(fs::read(&path).unwrap(), b"innocent");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_revoke() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (dir, path, file) = setup("clamav-client-revoke-test");
+
+        let taken = Action::Revoke.apply(&path, &file, &Signature::from("Eicar"));
+
+        assert_eq!(taken, ActionTaken::Revoked);
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::{self, IoSlice, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+pub(crate) const DEFAULT_BUFFER_CAPACITY: usize = 8192;
+
+/// Byte buffers shared by the scans of one or more clients. Buffers are handed
+/// out empty and go back to the pool when dropped, so a busy client stops
+/// allocating fresh read, write and reply buffers for every scan.
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    buffer_capacity: usize,
+    max_pooled: usize,
+}
+
+impl BufferPool {
+    /// Creates a pool of buffers pre-sized to `buffer_capacity` bytes, keeping
+    /// at most `max_pooled` idle buffers around.
+    pub fn new(buffer_capacity: usize, max_pooled: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            buffer_capacity,
+            max_pooled,
+        }
+    }
+
+    pub fn get(pool: &Arc<Self>) -> PooledBuffer {
+        let buffer = pool
+            .buffers
+            .lock()
+            .ok()
+            .and_then(|mut buffers| buffers.pop())
+            .unwrap_or_else(|| Vec::with_capacity(pool.buffer_capacity));
+
+        PooledBuffer {
+            buffer,
+            pool: Arc::clone(pool),
+        }
+    }
+
+    /// Number of idle buffers currently held by the pool.
+    pub fn idle(&self) -> usize {
+        self.buffers
+            .lock()
+            .map(|buffers| buffers.len())
+            .unwrap_or(0)
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUFFER_CAPACITY, 64)
+    }
+}
+
+pub struct PooledBuffer {
+    buffer: Vec<u8>,
+    pool: Arc<BufferPool>,
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.clear();
+        // don't let one oversized reply pin its memory in the pool forever
+        buffer.shrink_to(self.pool.buffer_capacity);
+
+        if let Ok(mut buffers) = self.pool.buffers.lock() {
+            if buffers.len() < self.pool.max_pooled {
+                buffers.push(buffer);
+            }
+        }
+    }
+}
+
+/// Write buffering backed by a pooled buffer, used in place of `BufWriter`
+/// for INSTREAM uploads.
+pub(crate) struct PooledWriter<W: Write> {
+    inner: W,
+    buffer: PooledBuffer,
+    capacity: usize,
+}
+
+impl<W: Write> PooledWriter<W> {
+    pub(crate) fn new(inner: W, buffer: PooledBuffer) -> Self {
+        let capacity = buffer.pool.buffer_capacity;
+        Self {
+            inner,
+            buffer,
+            capacity,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    fn flush_buffer(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.inner.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for PooledWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_vectored(&[IoSlice::new(buf)])
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        let total = bufs.iter().map(|b| b.len()).sum::<usize>();
+
+        if self.buffer.len() + total > self.capacity {
+            self.flush_buffer()?;
+        }
+
+        if total >= self.capacity {
+            return self.inner.write_vectored(bufs);
+        }
+
+        for buf in bufs {
+            self.buffer.extend_from_slice(buf);
+        }
+
+        Ok(total)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_buffer()?;
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_reuses_buffers() {
+        let pool = Arc::new(BufferPool::new(16, 4));
+
+        let mut buffer = BufferPool::get(&pool);
+        buffer.extend_from_slice(b"some reply");
+        let ptr = buffer.as_ptr();
+        drop(buffer);
+        assert_eq!(pool.idle(), 1);
+
+        let buffer = BufferPool::get(&pool);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr(), ptr);
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[test]
+    fn test_pool_caps_idle_buffers() {
+        let pool = Arc::new(BufferPool::new(16, 1));
+
+        let first = BufferPool::get(&pool);
+        let second = BufferPool::get(&pool);
+        drop(first);
+        drop(second);
+
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[test]
+    fn test_pooled_writer_batches_small_writes() {
+        let pool = Arc::new(BufferPool::new(16, 1));
+        let mut writer = PooledWriter::new(Vec::new(), BufferPool::get(&pool));
+
+        writer.write_all(b"abc").unwrap();
+        writer.write_all(b"def").unwrap();
+        assert!(writer.inner.is_empty());
+
+        writer.write_all(&[1; 32]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(&writer.inner[..6], b"abcdef");
+        assert_eq!(writer.inner.len(), 38);
+    }
+}
@@ -0,0 +1,216 @@
+//! Runs a locally installed clamd with a throwaway configuration, for
+//! examples and local testing.
+
+use std::fs::{self, File};
+use std::io::{self, ErrorKind};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::client::{ClamClient, Result};
+use crate::error::ClamError;
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+// detects the EICAR test file by its MD5, so the daemon has something to load
+const TEST_DATABASE: &str = "44d88612fea8a8f36de82e1278abb02f:68:Eicar-Test-Signature\n";
+
+static INSTANCES: AtomicUsize = AtomicUsize::new(0);
+
+/// A clamd child process listening on a free local port. Its configuration,
+/// log and (unless another database is given) signature database live in a
+/// temporary directory that is removed, along with the process, on drop.
+pub struct ClamdProcess {
+    child: Child,
+    dir: PathBuf,
+    port: u16,
+}
+
+/// Configures a `ClamdProcess`.
+pub struct ClamdProcessBuilder {
+    clamd: PathBuf,
+    database: Option<PathBuf>,
+    timeout: Duration,
+}
+
+impl ClamdProcess {
+    /// Starts `clamd` from `PATH` with a database that only detects EICAR.
+    pub fn start() -> Result<Self> {
+        Self::builder().start()
+    }
+
+    pub fn builder() -> ClamdProcessBuilder {
+        ClamdProcessBuilder {
+            clamd: PathBuf::from("clamd"),
+            database: None,
+            timeout: STARTUP_TIMEOUT,
+        }
+    }
+
+    pub fn client(&self) -> Result<ClamClient> {
+        ClamClient::new("127.0.0.1", self.port)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// clamd's log and anything it printed to stderr, useful when a test
+    /// fails.
+    pub fn log(&self) -> String {
+        ["clamd.log", "stderr.log"]
+            .iter()
+            .filter_map(|name| fs::read_to_string(self.dir.join(name)).ok())
+            .collect()
+    }
+
+    fn wait_ready(&mut self, timeout: Duration) -> Result<()> {
+        let client = self.client()?;
+        let started = Instant::now();
+
+        while !client.ping() {
+            let exited = match self.child.try_wait() {
+                Ok(status) => status.is_some(),
+                Err(e) => return Err(ClamError::ConnectionError(e)),
+            };
+
+            if exited || started.elapsed() > timeout {
+                let kind = if exited {
+                    ErrorKind::ConnectionRefused
+                } else {
+                    ErrorKind::TimedOut
+                };
+                return Err(ClamError::ConnectionError(io::Error::new(kind, self.log())));
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        Ok(())
+    }
+}
+
+impl ClamdProcessBuilder {
+    /// The clamd binary to run, `clamd` from `PATH` by default.
+    pub fn clamd<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.clamd = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Uses an existing signature database instead of the EICAR-only one.
+    pub fn database<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.database = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn start(self) -> Result<ClamdProcess> {
+        let dir = std::env::temp_dir().join(format!(
+            "clamd-{}-{}",
+            std::process::id(),
+            INSTANCES.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        match self.spawn(&dir) {
+            Ok(process) => Ok(process),
+            Err(e) => {
+                let _ = fs::remove_dir_all(&dir);
+                Err(e)
+            }
+        }
+    }
+
+    fn spawn(&self, dir: &Path) -> Result<ClamdProcess> {
+        let database = match &self.database {
+            Some(database) => database.clone(),
+            None => dir.join("db"),
+        };
+        let port = free_port()?;
+        let config = format!(
+            "Foreground yes\nTCPAddr 127.0.0.1\nTCPSocket {}\nDatabaseDirectory {}\nLogFile {}\n",
+            port,
+            database.display(),
+            dir.join("clamd.log").display()
+        );
+
+        let write = || -> io::Result<File> {
+            fs::create_dir_all(dir.join("db"))?;
+            fs::write(dir.join("db").join("test.hdb"), TEST_DATABASE)?;
+            fs::write(dir.join("clamd.conf"), config)?;
+            File::create(dir.join("stderr.log"))
+        };
+        let stderr = match write() {
+            Ok(stderr) => stderr,
+            Err(e) => return Err(ClamError::ConnectionError(e)),
+        };
+
+        let child = Command::new(&self.clamd)
+            .arg("--config-file")
+            .arg(dir.join("clamd.conf"))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(stderr)
+            .spawn();
+        let child = match child {
+            Ok(child) => child,
+            Err(e) => return Err(ClamError::ConnectionError(e)),
+        };
+
+        let mut process = ClamdProcess {
+            child,
+            dir: dir.to_path_buf(),
+            port,
+        };
+        process.wait_ready(self.timeout)?;
+        Ok(process)
+    }
+}
+
+impl Drop for ClamdProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn free_port() -> Result<u16> {
+    match TcpListener::bind("127.0.0.1:0").and_then(|l| l.local_addr()) {
+        Ok(address) => Ok(address.port()),
+        Err(e) => Err(ClamError::ConnectionError(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_clamd_cleans_up() {
+        let result = ClamdProcess::builder().clamd("/nonexistent/clamd").start();
+
+        match result {
+            Err(ClamError::ConnectionError(e)) => assert_eq!(e.kind(), ErrorKind::NotFound),
+            other => panic!("unexpected result: {:?}", other.map(|p| p.port())),
+        }
+
+        let leftovers = fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(&format!("clamd-{}-", std::process::id()))
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+}
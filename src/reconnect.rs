@@ -0,0 +1,52 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::ClamError;
+use crate::transport::Endpoint;
+
+/// Called with the endpoint and the error of the failed reconnect when the
+/// daemon looks like it restarted.
+pub type RestartCallback = Arc<dyn Fn(&Endpoint, &ClamError) + Send + Sync>;
+
+/// What a client does when a connection it keeps open dies and the daemon
+/// then refuses a new one, which is what a clamd restart looks like: the
+/// persistent session of `with_persistent_session` and the sessions of
+/// `scan_iter` are reopened, after optionally waiting for the daemon to
+/// answer PING again. Without a policy the failure is returned right away.
+#[derive(Clone, Default)]
+pub struct ReconnectPolicy {
+    pub(crate) ready_timeout: Option<Duration>,
+    pub(crate) on_restart: Option<RestartCallback>,
+}
+
+impl fmt::Debug for ReconnectPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectPolicy")
+            .field("ready_timeout", &self.ready_timeout)
+            .field("on_restart", &self.on_restart.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits up to `timeout` for the daemon to come back before reopening,
+    /// see `ClamClient::wait_until_ready`.
+    pub fn wait_until_ready(mut self, timeout: Duration) -> Self {
+        self.ready_timeout = Some(timeout);
+        self
+    }
+
+    /// Calls `f` whenever a restart is detected, e.g. to log it.
+    pub fn on_restart<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Endpoint, &ClamError) + Send + Sync + 'static,
+    {
+        self.on_restart = Some(Arc::new(f));
+        self
+    }
+}
@@ -0,0 +1,61 @@
+use std::cmp::Reverse;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::policy::ActionTaken;
+use crate::response::{ScanResult, Signature};
+
+/// Consolidated outcome of scanning many files.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ScanReport {
+    pub results: Vec<(PathBuf, ScanResult)>,
+    // files that could not be scanned, with the reason
+    pub errors: Vec<(PathBuf, String)>,
+    // how long each scanned file took, including unreadable ones
+    pub timings: Vec<(PathBuf, Duration)>,
+    // what was done to infected files, see DirScan::on_found
+    pub actions: Vec<(PathBuf, ActionTaken)>,
+    // detections whose notification could not be delivered, with the reason
+    pub notify_errors: Vec<(PathBuf, String)>,
+    // files (and hidden directories) left out by a FileFilter, and symlinks
+    // not followed
+    pub skipped: Vec<PathBuf>,
+    // files the skip cache showed unchanged since they were last found
+    // clean, see DirScan::skip_cache
+    pub unchanged: Vec<PathBuf>,
+    // set when the scan continued from a checkpoint
+    pub resumed_after: Option<PathBuf>,
+}
+
+impl ScanReport {
+    pub fn infected(&self) -> impl Iterator<Item = (&PathBuf, &Signature)> {
+        self.results
+            .iter()
+            .filter_map(|(path, result)| match result {
+                ScanResult::Found(_, signature, _) => Some((path, signature)),
+                _ => None,
+            })
+    }
+
+    /// The `n` files that took longest to scan, slowest first; huge
+    /// archives and decompression bombs tend to show up here.
+    pub fn slowest(&self, n: usize) -> Vec<(&PathBuf, Duration)> {
+        let mut timings = self
+            .timings
+            .iter()
+            .map(|(path, elapsed)| (path, *elapsed))
+            .collect::<Vec<_>>();
+        timings.sort_by_key(|&(_, elapsed)| Reverse(elapsed));
+        timings.truncate(n);
+        timings
+    }
+
+    /// True when every file was scanned and none was infected.
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty()
+            && self
+                .results
+                .iter()
+                .all(|(_, result)| *result == ScanResult::Ok)
+    }
+}
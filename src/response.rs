@@ -1,10 +1,40 @@
-use chrono::{DateTime, TimeZone, Utc};
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 use crate::client::Result;
 use crate::error::ClamError;
+use crate::transport::Endpoint;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
+/// A reply that can be parsed from a daemon's raw response, for use with
+/// `ClamClient::command_typed`.
+pub trait ClamResponse: Sized {
+    fn parse(raw: &[u8]) -> Result<Self>;
+}
+
+fn reply_str(raw: &[u8]) -> Result<&str> {
+    match std::str::from_utf8(raw) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(ClamError::InvalidData(
+            String::from_utf8_lossy(raw).into_owned(),
+        )),
+    }
+}
+
+impl ClamResponse for String {
+    fn parse(raw: &[u8]) -> Result<Self> {
+        reply_str(raw).map(str::to_owned)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Signature {
     // Start names with targeted platform or file format
     pub platform: Option<String>,
@@ -57,49 +87,390 @@ impl Signature {
             raw: str.to_string(),
         }
     }
+
+    /// Potentially unwanted applications, e.g. `PUA.Win.Packer.Upx-1`.
+    pub fn is_pua(&self) -> bool {
+        self.platform.as_deref() == Some("PUA")
+    }
+
+    /// Detections from heuristic checks rather than a signature, e.g.
+    /// `Heuristics.Encrypted.PDF`.
+    pub fn is_heuristic(&self) -> bool {
+        self.platform.as_deref() == Some("Heuristics")
+    }
+
+    /// The EICAR test file, in both its old `Eicar-Test-Signature` and its
+    /// current `Win.Test.EICAR_HDB-1` form.
+    pub fn is_test(&self) -> bool {
+        self.category.as_deref() == Some("Test") || self.raw.to_uppercase().starts_with("EICAR")
+    }
+
+    /// The last component of the name, e.g. `Agent` for
+    /// `Win.Trojan.Agent-1234-0` and `Upx` for `PUA.Win.Packer.Upx-1`.
+    pub fn family(&self) -> Option<&str> {
+        self.virus.as_deref().and_then(|v| v.rsplit('.').next())
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
+impl FromStr for Signature {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Infallible> {
+        Ok(Signature::from(s))
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+/// The daemon's verdict on one scanned subject. More variants may be added,
+/// so matches outside this crate need a catch-all arm.
+///
+/// `Found` carries the reply line as a third field since it was added;
+/// matches written for two fields need a `_` for it, e.g.
+/// `Found(path, signature, _)`. Reply lines are left out of comparisons
+/// and hashing, so results equal before keep comparing equal however the
+/// daemon worded its reply.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[non_exhaustive]
 pub enum ScanResult {
     Ok,
-    Found(String, Signature),
+    /// The path (or `stream`), the signature, and the reply line exactly as
+    /// the daemon sent it, for logs that must not depend on how it was
+    /// parsed.
+    Found(String, Signature, String),
+    /// A path (or `stream`) the daemon failed to scan, the message without
+    /// the trailing `ERROR`, and the reply line, e.g. for
+    /// `/srv/a: Can't open file ERROR`.
+    Failed(String, String, String),
+    /// Any other reply line, such as a rejected command.
     Error(String),
 }
 
 impl ScanResult {
+    /// A match, with the reply line clamd would have sent for it.
+    pub fn found<P: Into<String>>(path: P, signature: Signature) -> Self {
+        let path = path.into();
+        let line = format!("{}: {} FOUND", path, signature);
+        ScanResult::Found(path, signature, line)
+    }
+
+    /// What a match was found in; `None` unless this is a match.
+    pub fn target(&self) -> Option<Target> {
+        match self {
+            ScanResult::Found(path, ..) => Some(Target::parse(path)),
+            _ => None,
+        }
+    }
+
     pub fn parse<T: AsRef<str>>(s: T) -> Vec<ScanResult> {
         s.as_ref()
             .split('\0')
             .filter(|s| s != &"")
             .map(|s| {
-                if s.ends_with("OK") {
+                if s == "OK" || s.ends_with(": OK") {
                     return ScanResult::Ok;
                 }
 
-                if s.contains("FOUND") {
-                    let mut split = s.split_whitespace();
-                    let path: String = split.next().unwrap().trim_end_matches(':').to_owned();
-                    let virus = split
-                        .take_while(|s| !s.starts_with("FOUND"))
-                        .collect::<String>();
-
-                    return ScanResult::Found(path, Signature::from(&virus));
+                // signatures never contain ": ", paths may
+                let found = s
+                    .strip_suffix(" FOUND")
+                    .and_then(|found| found.rsplit_once(": "));
+                if let Some((path, virus)) = found {
+                    return ScanResult::Found(
+                        path.to_owned(),
+                        Signature::from(virus),
+                        s.to_owned(),
+                    );
                 }
 
-                ScanResult::Error(s.to_owned())
+                ScanResult::error(s.to_owned())
             })
             .collect::<Vec<ScanResult>>()
     }
+
+    // "<path>: <message> ERROR" lines name what failed
+    fn error(line: String) -> Self {
+        let failed = line
+            .strip_suffix(" ERROR")
+            .and_then(|rest| rest.split_once(": "))
+            .map(|(path, message)| (path.to_owned(), message.to_owned()));
+
+        match failed {
+            Some((path, message)) => ScanResult::Failed(path, message, line),
+            None => ScanResult::Error(line),
+        }
+    }
+
+    // what comparisons look at: everything but the reply line, in variant
+    // order
+    fn key(&self) -> (u8, &str, Option<&Signature>, &str) {
+        match self {
+            ScanResult::Ok => (0, "", None, ""),
+            ScanResult::Found(path, signature, _) => (1, path, Some(signature), ""),
+            ScanResult::Failed(path, message, _) => (2, path, None, message),
+            ScanResult::Error(line) => (3, line, None, ""),
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, PartialOrd)]
+impl PartialEq for ScanResult {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for ScanResult {}
+
+impl PartialOrd for ScanResult {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScanResult {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for ScanResult {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+/// The subject of a reply line: a streamed upload or a file on the
+/// daemon's side.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Target {
+    Stream,
+    Path(PathBuf),
+}
+
+impl Target {
+    /// Recognises the names clamd gives streams: `stream`, and
+    /// `instream(<peer>)` from old daemons.
+    pub fn parse(subject: &str) -> Self {
+        let instream = subject.starts_with("instream(") && subject.ends_with(')');
+        if subject == "stream" || instream {
+            Target::Stream
+        } else {
+            Target::Path(PathBuf::from(subject))
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Target::Stream => None,
+            Target::Path(path) => Some(path),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Stream => f.write_str("stream"),
+            Target::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl ClamResponse for Vec<ScanResult> {
+    fn parse(raw: &[u8]) -> Result<Self> {
+        Ok(ScanResult::parse(String::from_utf8_lossy(raw)))
+    }
+}
+
+/// Formats the result like a line of a clamd scan reply.
+impl fmt::Display for ScanResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScanResult::Ok => f.write_str("OK"),
+            ScanResult::Found(path, signature, _) => write!(f, "{}: {} FOUND", path, signature),
+            ScanResult::Failed(_, _, line) | ScanResult::Error(line) => f.write_str(line),
+        }
+    }
+}
+
+impl ClamResponse for ScanResult {
+    fn parse(raw: &[u8]) -> Result<Self> {
+        let reply = String::from_utf8_lossy(raw);
+        match ScanResult::parse(&reply).into_iter().next() {
+            Some(result) => Ok(result),
+            None => Err(ClamError::InvalidData(reply.into_owned())),
+        }
+    }
+}
+
+/// The outcome for one path of a scan reply, see `ClamClient::scan_path_map`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ScanVerdict {
+    Clean,
+    /// Every signature reported for the file; ALLMATCHSCAN may report
+    /// several.
+    Infected(Vec<Signature>),
+    Error(String),
+}
+
+impl ScanVerdict {
+    /// Keys every line of a path scan reply by the path it names. Lines
+    /// without a path, such as a rejected command, are keyed by `scanned`.
+    pub fn parse_reply<P: AsRef<Path>>(reply: &str, scanned: P) -> BTreeMap<PathBuf, Self> {
+        let mut verdicts = BTreeMap::new();
+
+        for item in ScanItem::parse_reply(reply, scanned.as_ref().to_string_lossy()) {
+            let path = PathBuf::from(item.subject);
+            match (verdicts.get_mut(&path), item.verdict) {
+                (Some(ScanVerdict::Infected(known)), ScanVerdict::Infected(more)) => {
+                    known.extend(more)
+                }
+                (_, verdict) => {
+                    verdicts.insert(path, verdict);
+                }
+            }
+        }
+
+        verdicts
+    }
+}
+
+/// One outcome of a scan together with what was scanned: the file path, or
+/// the label of a stream (`stream` unless given one). Unlike `ScanResult`,
+/// clean files and errors keep their path, so results of a multi-file scan
+/// can be told apart.
+///
+/// Code matching on `ScanResult` can move over one call site at a time:
+/// `ScanResult::from(item)` gives the old form, and
+/// `ScanItem::from_result` turns a `ScanResult` into an item.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScanItem {
+    pub subject: String,
+    pub verdict: ScanVerdict,
+}
+
+impl ScanItem {
+    /// One item per line of a scan reply, in reply order. Lines without a
+    /// subject, such as a rejected command, are attributed to `scanned`.
+    pub fn parse_reply<S: AsRef<str>>(reply: &str, scanned: S) -> Vec<Self> {
+        reply
+            .split('\0')
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (subject, verdict) = if let Some(subject) = line.strip_suffix(": OK") {
+                    (subject, ScanVerdict::Clean)
+                } else if let Some((subject, signature)) = line
+                    .strip_suffix(" FOUND")
+                    .and_then(|found| found.rsplit_once(": "))
+                {
+                    (
+                        subject,
+                        ScanVerdict::Infected(vec![Signature::from(signature)]),
+                    )
+                } else {
+                    match line.split_once(": ") {
+                        Some((subject, message)) => {
+                            (subject, ScanVerdict::Error(message.to_owned()))
+                        }
+                        None => ("", ScanVerdict::Error(line.to_owned())),
+                    }
+                };
+
+                let subject = match subject {
+                    "" => scanned.as_ref(),
+                    subject => subject,
+                };
+                ScanItem {
+                    subject: subject.to_owned(),
+                    verdict,
+                }
+            })
+            .collect()
+    }
+
+    pub fn from_result<S: Into<String>>(subject: S, result: ScanResult) -> Self {
+        let subject = subject.into();
+        let verdict = match result {
+            ScanResult::Ok => ScanVerdict::Clean,
+            ScanResult::Found(_, signature, _) => ScanVerdict::Infected(vec![signature]),
+            // the message is the whole reply line, starting with the subject
+            ScanResult::Failed(_, _, message) | ScanResult::Error(message) => {
+                let prefix = format!("{}: ", subject);
+                match message.strip_prefix(&prefix) {
+                    Some(message) => ScanVerdict::Error(message.to_owned()),
+                    None => ScanVerdict::Error(message),
+                }
+            }
+        };
+
+        ScanItem { subject, verdict }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.verdict == ScanVerdict::Clean
+    }
+
+    pub fn target(&self) -> Target {
+        Target::parse(&self.subject)
+    }
+}
+
+/// The old form of the item, naming only the first signature of an infected
+/// file.
+impl From<ScanItem> for ScanResult {
+    fn from(item: ScanItem) -> Self {
+        match item.verdict {
+            ScanVerdict::Clean => ScanResult::Ok,
+            ScanVerdict::Infected(signatures) => match signatures.into_iter().next() {
+                Some(signature) => ScanResult::found(item.subject, signature),
+                None => ScanResult::Ok,
+            },
+            ScanVerdict::Error(message) => {
+                ScanResult::error(format!("{}: {}", item.subject, message))
+            }
+        }
+    }
+}
+
+/// A streamed scan's verdict together with transfer metadata, for logging
+/// throughput and attributing slow scans to a particular daemon.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScanOutcome {
+    pub result: ScanResult,
+    // payload bytes sent, excluding INSTREAM framing
+    pub bytes_sent: u64,
+    pub duration: Duration,
+    pub endpoint: Endpoint,
+}
+
+/// When the signature database was published: a UTC timestamp with the
+/// `chrono` feature, otherwise the date as clamd formats it.
+#[cfg(feature = "chrono")]
+pub type ReleaseDate = DateTime<Utc>;
+#[cfg(not(feature = "chrono"))]
+pub type ReleaseDate = String;
+
+// tried after collapsing runs of whitespace, so padded and unpadded days match
+#[cfg(feature = "chrono")]
+const RELEASE_DATE_FORMATS: &[&str] = &["%a %b %e %T %Y", "%a %b %d %T %Y", "%b %e %T %Y"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd)]
 pub struct Version {
     pub version_tag: String,
     pub build_number: u64,
-    pub release_date: DateTime<Utc>,
+    // None when the date isn't in a recognized format
+    pub release_date: Option<ReleaseDate>,
+    // the date exactly as clamd reported it
+    pub release_date_raw: String,
 }
 
 impl Version {
+    /// Parses a VERSION reply. An unrecognized release date doesn't fail the
+    /// parse; it is kept in `release_date_raw` only.
     pub fn parse(s: &str) -> Result<Self> {
         let parts = s
             .trim_end_matches('\0')
@@ -116,20 +487,97 @@ impl Version {
             Err(e) => return Err(ClamError::IntParseError(e)),
         };
 
-        let release_date = match Utc.datetime_from_str(&parts[2], "%a %b %e %T %Y") {
-            Ok(v) => v,
-            Err(e) => return Err(ClamError::DateParseError(e)),
-        };
-
         Ok(Version {
             version_tag: parts[0].to_owned(),
             build_number,
-            release_date,
+            release_date: parse_release_date(&parts[2]),
+            release_date_raw: parts[2].to_owned(),
         })
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, PartialOrd)]
+#[cfg(feature = "chrono")]
+fn parse_release_date(s: &str) -> Option<ReleaseDate> {
+    let s = s.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    RELEASE_DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&s, format).ok())
+        .map(|date| date.and_utc())
+}
+
+#[cfg(not(feature = "chrono"))]
+fn parse_release_date(s: &str) -> Option<ReleaseDate> {
+    Some(s.to_owned())
+}
+
+impl Version {
+    /// The release number from the version tag, e.g. `(0, 103, 8)` for
+    /// `ClamAV 0.103.8`. Missing components count as zero.
+    pub fn release(&self) -> Option<(u64, u64, u64)> {
+        let number = self.version_tag.rsplit(' ').next()?;
+        let mut parts = number.split('.').map(|part| {
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            part[..digits].parse::<u64>().ok()
+        });
+
+        let major = parts.next()??;
+        let minor = parts.next().unwrap_or(Some(0))?;
+        let patch = parts.next().unwrap_or(Some(0))?;
+        Some((major, minor, patch))
+    }
+}
+
+impl FromStr for Version {
+    type Err = ClamError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Version::parse(s)
+    }
+}
+
+/// Formats the version the way clamd reports it.
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}",
+            self.version_tag, self.build_number, self.release_date_raw
+        )
+    }
+}
+
+/// Confirms that clamd accepted a RELOAD and is reloading its database.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReloadAck;
+
+impl ClamResponse for ReloadAck {
+    fn parse(raw: &[u8]) -> Result<Self> {
+        let reply = String::from_utf8_lossy(raw);
+        match reply.trim_end_matches(&['\0', '\n'][..]) {
+            "RELOADING" => Ok(ReloadAck),
+            _ => Err(ClamError::UnexpectedReply("RELOAD", reply.into_owned())),
+        }
+    }
+}
+
+/// Confirms that clamd accepted a SHUTDOWN.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShutdownAck;
+
+impl ClamResponse for ShutdownAck {
+    fn parse(raw: &[u8]) -> Result<Self> {
+        let reply = String::from_utf8_lossy(raw);
+        match reply.trim_end_matches(&['\0', '\n'][..]) {
+            "" => Ok(ShutdownAck),
+            _ => Err(ClamError::UnexpectedReply("SHUTDOWN", reply.into_owned())),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
 pub struct Stats {
     pub pools: u64,
     pub state: String,
@@ -138,63 +586,205 @@ pub struct Stats {
     pub threads_max: u64,
     pub threads_idle_timeout_secs: u64,
     pub queue: u64,
-    pub mem_heap: String,
-    pub mem_mmap: String,
-    pub mem_used: String,
-    pub mem_free: String,
-    pub mem_releasable: String,
-    pub pools_used: String,
-    pub pools_total: String,
+    // memory figures are None when the daemon doesn't report them
+    pub mem_heap: Option<String>,
+    pub mem_mmap: Option<String>,
+    pub mem_used: Option<String>,
+    pub mem_free: Option<String>,
+    pub mem_releasable: Option<String>,
+    pub pools_used: Option<String>,
+    pub pools_total: Option<String>,
+    /// The reply as received.
+    pub raw: String,
+    /// Sections and THREADS/MEMSTATS fields this crate doesn't model yet,
+    /// keyed by section name or `SECTION.field`.
+    pub extra: BTreeMap<String, String>,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {} pool(s), threads {} live / {} idle / {} max, {} queued",
+            self.state,
+            self.pools,
+            self.threads_live,
+            self.threads_idle,
+            self.threads_max,
+            self.queue
+        )?;
+
+        if let Some(used) = &self.mem_used {
+            write!(f, ", {} used", used)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Change between two `Stats` snapshots, from the earlier to the later.
+/// Memory changes are in megabytes and None unless both snapshots report
+/// the figure.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatsDelta {
+    pub queue: i64,
+    pub threads_live: i64,
+    pub threads_idle: i64,
+    pub mem_heap: Option<f64>,
+    pub mem_mmap: Option<f64>,
+    pub mem_used: Option<f64>,
+    pub mem_free: Option<f64>,
+    pub mem_releasable: Option<f64>,
+    pub pools_used: Option<f64>,
+    pub pools_total: Option<f64>,
+}
+
+const STATS_SECTIONS: &[&str] = &["POOLS", "STATE", "THREADS", "QUEUE", "MEMSTATS"];
+const THREADS_FIELDS: &[&str] = &["live", "idle", "max", "idle-timeout"];
+const MEMSTATS_FIELDS: &[&str] = &[
+    "heap",
+    "mmap",
+    "used",
+    "free",
+    "releasable",
+    "pools",
+    "pools_used",
+    "pools_total",
+];
+
+impl ClamResponse for Version {
+    fn parse(raw: &[u8]) -> Result<Self> {
+        Version::parse(reply_str(raw)?)
+    }
 }
 
 impl Stats {
+    /// Parses a STATS reply. Sections are looked up by name and fields
+    /// within THREADS and MEMSTATS by key, so reordered or additional
+    /// fields from other daemon versions are tolerated.
     pub fn parse(s: &str) -> Result<Self> {
-        match parse_stats(s) {
-            Ok(x) => Ok(x.1),
-            Err(_) => Err(ClamError::InvalidData(s.to_string())),
+        let sections = s
+            .trim_end_matches('\0')
+            .lines()
+            .filter(|line| !line.starts_with(char::is_whitespace))
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name, value.trim()))
+            .collect::<HashMap<_, _>>();
+        let threads = fields(sections.get("THREADS"));
+        let memstats = fields(sections.get("MEMSTATS"));
+
+        // names the offending field rather than just echoing the reply
+        let invalid = |field: &str| {
+            ClamError::InvalidData(format!(
+                "STATS field {} missing or malformed in {:?}",
+                field, s
+            ))
+        };
+        let number = |field: &str, v: Option<&&str>| -> Result<u64> {
+            v.and_then(|v| v.parse().ok()).ok_or_else(|| invalid(field))
+        };
+        let text = |field: &str, v: Option<&&str>| -> Result<String> {
+            v.map(|v| v.to_string()).ok_or_else(|| invalid(field))
+        };
+        // absent, or reported as N/A by daemons built without allocator stats
+        let memory = |v: Option<&&str>| match v {
+            Some(&"N/A") | None => None,
+            Some(v) => Some(v.to_string()),
+        };
+        let queue = sections
+            .get("QUEUE")
+            .and_then(|q| q.split_whitespace().next());
+
+        let mut extra = sections
+            .iter()
+            .filter(|(name, _)| !STATS_SECTIONS.contains(name))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<BTreeMap<_, _>>();
+        unknown_fields("THREADS", THREADS_FIELDS, &threads, &mut extra);
+        unknown_fields("MEMSTATS", MEMSTATS_FIELDS, &memstats, &mut extra);
+
+        Ok(Stats {
+            pools: number("POOLS", sections.get("POOLS"))?,
+            state: text("STATE", sections.get("STATE"))?,
+            threads_live: number("THREADS live", threads.get("live"))?,
+            threads_idle: number("THREADS idle", threads.get("idle"))?,
+            threads_max: number("THREADS max", threads.get("max"))?,
+            threads_idle_timeout_secs: number("THREADS idle-timeout", threads.get("idle-timeout"))?,
+            queue: number("QUEUE", queue.as_ref())?,
+            mem_heap: memory(memstats.get("heap")),
+            mem_mmap: memory(memstats.get("mmap")),
+            mem_used: memory(memstats.get("used")),
+            mem_free: memory(memstats.get("free")),
+            mem_releasable: memory(memstats.get("releasable")),
+            pools_used: memory(memstats.get("pools_used")),
+            pools_total: memory(memstats.get("pools_total")),
+            raw: s.to_string(),
+            extra,
+        })
+    }
+
+    pub fn delta(&self, earlier: &Stats) -> StatsDelta {
+        let count = |later: u64, earlier: u64| {
+            let signed = |n: u64| n.min(i64::MAX as u64) as i64;
+            signed(later).saturating_sub(signed(earlier))
+        };
+        let memory = |later: &Option<String>, earlier: &Option<String>| {
+            Some(megabytes(later.as_ref()?)? - megabytes(earlier.as_ref()?)?)
+        };
+
+        StatsDelta {
+            queue: count(self.queue, earlier.queue),
+            threads_live: count(self.threads_live, earlier.threads_live),
+            threads_idle: count(self.threads_idle, earlier.threads_idle),
+            mem_heap: memory(&self.mem_heap, &earlier.mem_heap),
+            mem_mmap: memory(&self.mem_mmap, &earlier.mem_mmap),
+            mem_used: memory(&self.mem_used, &earlier.mem_used),
+            mem_free: memory(&self.mem_free, &earlier.mem_free),
+            mem_releasable: memory(&self.mem_releasable, &earlier.mem_releasable),
+            pools_used: memory(&self.pools_used, &earlier.pools_used),
+            pools_total: memory(&self.pools_total, &earlier.pools_total),
         }
     }
 }
 
-named!(parse_stats<&str, Stats>,
-    do_parse!(
-        tag!("POOLS: ") >>
-        pools: map_res!(take_until_and_consume!("\n\nSTATE: "), u64::from_str) >>
-        state: map_res!(take_until_and_consume!("\nTHREADS: live "), FromStr::from_str) >>
-        threads_live: map_res!(take_until_and_consume!("  idle "), u64::from_str) >>
-        threads_idle: map_res!(take_until_and_consume!(" max "), u64::from_str) >>
-        threads_max: map_res!(take_until_and_consume!(" idle-timeout "), u64::from_str) >>
-        threads_idle_timeout_secs: map_res!(take_until_and_consume!("\nQUEUE: "), u64::from_str) >>
-        queue: map_res!(take_until_and_consume!(" items\n"), u64::from_str) >>
-        take_until_and_consume!("heap ") >>
-        mem_heap: map_res!(take_until_and_consume!(" mmap "), FromStr::from_str) >>
-        mem_mmap: map_res!(take_until_and_consume!(" used "), FromStr::from_str) >>
-        mem_used: map_res!(take_until_and_consume!(" free "), FromStr::from_str) >>
-        mem_free: map_res!(take_until_and_consume!(" releasable "), FromStr::from_str) >>
-        mem_releasable: map_res!(take_until_and_consume!(" pools "), FromStr::from_str) >>
-        take_until_and_consume!("pools_used ") >>
-        pools_used: map_res!(take_until_and_consume!(" pools_total "), FromStr::from_str) >>
-        pools_total: map_res!(take_until!("\n"), FromStr::from_str) >>
-        (
-            Stats {
-                pools,
-                state,
-                threads_live,
-                threads_idle,
-                threads_max,
-                threads_idle_timeout_secs,
-                queue,
-                mem_heap,
-                mem_mmap,
-                mem_used,
-                mem_free,
-                mem_releasable,
-                pools_used,
-                pools_total
-            }
-        )
-    )
-);
+/// Reads a MEMSTATS figure such as `9.082M` as megabytes.
+fn megabytes(s: &str) -> Option<f64> {
+    s.trim_end_matches('M').parse().ok()
+}
+
+/// Splits a `key value key value ...` section into its fields.
+fn fields<'a>(section: Option<&&'a str>) -> HashMap<&'a str, &'a str> {
+    let tokens = section
+        .map(|s| s.split_whitespace().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    tokens
+        .chunks(2)
+        .filter_map(|pair| match pair {
+            [key, value] => Some((*key, *value)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn unknown_fields(
+    section: &str,
+    known: &[&str],
+    fields: &HashMap<&str, &str>,
+    extra: &mut BTreeMap<String, String>,
+) {
+    for (key, value) in fields {
+        if !known.contains(key) {
+            extra.insert(format!("{}.{}", section, key), value.to_string());
+        }
+    }
+}
+
+impl ClamResponse for Stats {
+    fn parse(raw: &[u8]) -> Result<Self> {
+        Stats::parse(reply_str(raw)?)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -202,6 +792,10 @@ mod tests {
 
     static VERSION_STRING: &'static str = "ClamAV 0.100.0/24802/Wed Aug  1 08:43:37 2018\0";
     static STATS_STRING: &'static str = "POOLS: 1\n\nSTATE: VALID PRIMARY\nTHREADS: live 1  idle 0 max 12 idle-timeout 30\nQUEUE: 0 items\n\tSTATS 0.000394\n\nMEMSTATS: heap 9.082M mmap 0.000M used 6.902M free 2.184M releasable 0.129M pools 1 pools_used 565.979M pools_total 565.999M\nEND\0";
+    // clamd 0.103 built without mallinfo, with scans queued
+    static STATS_STRING_0_103: &str = "POOLS: 1\n\nSTATE: VALID PRIMARY\nTHREADS: live 3  idle 0 max 10 idle-timeout 30\nQUEUE: 2 items\n\tINSTREAM 0.000291\n\tINSTREAM 0.000104\n\tSTATS 0.000058 \n\nMEMSTATS: heap N/A mmap N/A used N/A free N/A releasable N/A pools 1 pools_used 1279.468M pools_total 1279.497M\nEND\0";
+    // later daemons with several pools and fields in a different order
+    static STATS_STRING_1_X: &str = "STATE: VALID PRIMARY\r\nPOOLS: 2\r\n\r\nTHREADS: max 20 live 1 idle 1 idle-timeout 60 busy 0\r\nQUEUE: 0 items\r\n\tSTATS 0.000031 \r\n\r\nMEMSTATS: heap 12.004M mmap 0.129M used 9.318M free 2.688M releasable 0.096M pools 2 pools_used 1402.113M pools_total 1402.151M\r\nEND\0";
 
     #[test]
     fn test_version_parse_version_tag() {
@@ -217,17 +811,48 @@ mod tests {
         assert_eq!(parsed.build_number, 24802);
     }
 
+    #[cfg(feature = "chrono")]
     #[test]
     fn test_version_parse_publish_dt() {
         let raw = VERSION_STRING.to_owned();
         let parsed = Version::parse(&raw).unwrap();
         assert_eq!(
             parsed.release_date,
-            Utc.datetime_from_str("Wed Aug  1 08:43:37 2018", "%a %b %e %T %Y")
-                .unwrap()
+            NaiveDateTime::parse_from_str("Wed Aug  1 08:43:37 2018", "%a %b %e %T %Y")
+                .ok()
+                .map(|date| date.and_utc())
         );
     }
 
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_version_parse_lenient_date() {
+        let expected = Version::parse(VERSION_STRING).unwrap().release_date;
+        let parsed = Version::parse("ClamAV 0.100.0/24802/Wed Aug 01  08:43:37 2018").unwrap();
+        assert_eq!(parsed.release_date, expected);
+
+        let parsed = Version::parse("ClamAV 0.100.0/24802/2018-08-01").unwrap();
+        assert_eq!(parsed.release_date, None);
+        assert_eq!(parsed.release_date_raw, "2018-08-01");
+        assert_eq!(parsed.build_number, 24802);
+    }
+
+    #[test]
+    fn test_version_release() {
+        let release = |tag: &str| {
+            Version {
+                version_tag: tag.to_string(),
+                ..Version::parse(VERSION_STRING).unwrap()
+            }
+            .release()
+        };
+
+        assert_eq!(release("ClamAV 0.100.0"), Some((0, 100, 0)));
+        assert_eq!(release("ClamAV 1.4.0-rc"), Some((1, 4, 0)));
+        assert_eq!(release("ClamAV 1.0"), Some((1, 0, 0)));
+        assert_eq!(release("ClamAV devel"), None);
+    }
+
     #[test]
     fn test_result_parse_ok() {
         let raw = "/some/file: OK\0";
@@ -245,6 +870,269 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_result_parse_failed() {
+        let raw = "/srv/a: lstat() failed: Permission denied. ERROR\0INSTREAM size limit exceeded. ERROR\0";
+        let parsed = ScanResult::parse(raw);
+        assert_eq!(
+            parsed[0],
+            ScanResult::Failed(
+                "/srv/a".to_string(),
+                "lstat() failed: Permission denied.".to_string(),
+                "/srv/a: lstat() failed: Permission denied. ERROR".to_string()
+            )
+        );
+        assert_eq!(
+            parsed[1],
+            ScanResult::Error("INSTREAM size limit exceeded. ERROR".to_string())
+        );
+
+        let item = ScanItem::from_result("/srv/a", parsed[0].clone());
+        assert_eq!(ScanResult::from(item), parsed[0]);
+    }
+
+    #[test]
+    fn test_result_parse_found_keeps_line() {
+        let raw = "/some/odd file: Win.Test.EICAR_HDB-1 FOUND\0";
+        match &ScanResult::parse(raw)[0] {
+            ScanResult::Found(path, signature, line) => {
+                assert_eq!(path, "/some/odd file");
+                assert_eq!(signature.raw, "Win.Test.EICAR_HDB-1");
+                assert_eq!(line, "/some/odd file: Win.Test.EICAR_HDB-1 FOUND")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_result_comparisons_ignore_line() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |result: &ScanResult| {
+            let mut hasher = DefaultHasher::new();
+            result.hash(&mut hasher);
+            hasher.finish()
+        };
+        let built = ScanResult::found("stream", Signature::from("Eicar"));
+        let session = ScanResult::Found(
+            String::from("stream"),
+            Signature::from("Eicar"),
+            String::from("1: stream: Eicar FOUND"),
+        );
+
+        assert_eq!(session, built);
+        assert_eq!(hash(&session), hash(&built));
+
+        let failed = ScanResult::parse("/srv/a: Can't open file ERROR\0").remove(0);
+        let error = ScanResult::Error(String::from("UNKNOWN COMMAND"));
+        assert!(ScanResult::Ok < built && built < failed && failed < error);
+    }
+
+    #[test]
+    fn test_adversarial_replies() {
+        let replies: &[&[u8]] = &[
+            b"",
+            b"\0\0",
+            b"FOUND\0",
+            b": FOUND\0",
+            b" FOUND\0",
+            b"OK FOUND\0",
+            b": OK\0",
+            b"\xff\xfe: \xff FOUND\0",
+            b"/srv/FOUND/a: Access denied. ERROR\0",
+            b"/srv/a: BOOK\0",
+            b"ClamAV/\0",
+            b"/1/\0",
+            b"ClamAV 1.0/99999999999999999999999/x\0",
+            b"ClamAV \xc3\xa9./1/x\0",
+            b"POOLS: \nTHREADS: live\nQUEUE:\nMEMSTATS: heap\0",
+            b"1: \0",
+        ];
+
+        for raw in replies {
+            let line = String::from_utf8_lossy(raw);
+            let _ = <Vec<ScanResult> as ClamResponse>::parse(raw);
+            let _ = <ScanResult as ClamResponse>::parse(raw);
+            let _ = ScanItem::parse_reply(&line, "/srv");
+            let _ = ScanVerdict::parse_reply(&line, "/srv");
+            let _ = <Stats as ClamResponse>::parse(raw);
+            let _ = <ReloadAck as ClamResponse>::parse(raw);
+            let _ = <ShutdownAck as ClamResponse>::parse(raw);
+            if let Ok(version) = <Version as ClamResponse>::parse(raw) {
+                let _ = version.release();
+            }
+            let _ = Signature::from(&line).family();
+        }
+
+        // only whole suffixes count
+        assert!(matches!(
+            ScanResult::parse("/srv/FOUND/a: Access denied. ERROR\0")[0],
+            ScanResult::Failed(..)
+        ));
+        assert!(matches!(
+            ScanResult::parse("/srv/a: BOOK\0")[0],
+            ScanResult::Error(_)
+        ));
+        assert!(matches!(
+            Version::parse("ClamAV 1.0/99999999999999999999999/x"),
+            Err(ClamError::IntParseError(_))
+        ));
+        match Stats::parse("POOLS: \nTHREADS: live\0") {
+            Err(ClamError::InvalidData(message)) => assert!(message.contains("POOLS")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let mut huge = Stats::parse(STATS_STRING).unwrap();
+        huge.queue = u64::MAX;
+        let quiet = Stats::parse(STATS_STRING).unwrap();
+        assert_eq!(huge.delta(&quiet).queue, i64::MAX);
+        assert_eq!(quiet.delta(&huge).queue, -i64::MAX);
+    }
+
+    #[test]
+    fn test_stream_target() {
+        let raw =
+            "stream: Eicar FOUND\0instream(127.0.0.1@41234): Eicar FOUND\0/srv/a: Eicar FOUND\0";
+        let targets = ScanResult::parse(raw)
+            .iter()
+            .map(|r| r.target().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(targets[0], Target::Stream);
+        assert_eq!(targets[1], Target::Stream);
+        assert_eq!(targets[2].path(), Some(Path::new("/srv/a")));
+        assert_eq!(ScanResult::Ok.target(), None);
+        assert_eq!(
+            ScanItem::from_result("stream", ScanResult::Ok).target(),
+            Target::Stream
+        );
+    }
+
+    #[test]
+    fn test_verdicts_by_path() {
+        let raw = "/srv/a b.exe: Eicar FOUND\0/srv/a b.exe: Win.Test.EICAR_HDB-1 FOUND\0\
+                   /srv/x: y: lstat() failed: No such file or directory. ERROR\0/srv: OK\0";
+        let verdicts = ScanVerdict::parse_reply(raw, "/srv");
+
+        assert_eq!(verdicts.len(), 3);
+        match &verdicts[Path::new("/srv/a b.exe")] {
+            ScanVerdict::Infected(signatures) => assert_eq!(signatures.len(), 2),
+            other => panic!("unexpected verdict: {:?}", other),
+        }
+        assert_eq!(
+            verdicts[Path::new("/srv/x")],
+            ScanVerdict::Error(String::from(
+                "y: lstat() failed: No such file or directory. ERROR"
+            ))
+        );
+        assert_eq!(verdicts[Path::new("/srv")], ScanVerdict::Clean);
+
+        let rejected = ScanVerdict::parse_reply("UNKNOWN COMMAND\0", "/srv");
+        assert!(matches!(rejected[Path::new("/srv")], ScanVerdict::Error(_)));
+    }
+
+    #[test]
+    fn test_scan_items() {
+        let raw = "/srv/a: OK\0/srv/b: Eicar FOUND\0/srv/c: Access denied. ERROR\0";
+        let items = ScanItem::parse_reply(raw, "/srv");
+
+        let subjects = items.iter().map(|i| i.subject.as_str()).collect::<Vec<_>>();
+        assert_eq!(subjects, ["/srv/a", "/srv/b", "/srv/c"]);
+        assert!(items[0].is_clean());
+
+        let legacy = items
+            .iter()
+            .cloned()
+            .map(ScanResult::from)
+            .collect::<Vec<_>>();
+        assert_eq!(legacy, ScanResult::parse(raw));
+        for (item, result) in items.into_iter().zip(legacy) {
+            assert_eq!(ScanItem::from_result(item.subject.clone(), result), item);
+        }
+    }
+
+    #[test]
+    fn test_response_from_raw() {
+        let results = <Vec<ScanResult> as ClamResponse>::parse(b"/tmp/\xff: OK\0").unwrap();
+        assert_eq!(results, vec![ScanResult::Ok]);
+
+        let version = <Version as ClamResponse>::parse(VERSION_STRING.as_bytes()).unwrap();
+        assert_eq!(version.build_number, 24802);
+
+        assert!(<String as ClamResponse>::parse(b"\xff\0").is_err());
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let version = VERSION_STRING.parse::<Version>().unwrap();
+        assert_eq!(format!("{}\0", version), VERSION_STRING);
+
+        let signature = "Win.Test.EICAR_HDB-1".parse::<Signature>().unwrap();
+        let found = ScanResult::found("stream", signature);
+        assert_eq!(found.to_string(), "stream: Win.Test.EICAR_HDB-1 FOUND");
+        assert_eq!(ScanResult::parse(found.to_string()), vec![found]);
+
+        let stats = Stats::parse(STATS_STRING).unwrap();
+        assert_eq!(
+            stats.to_string(),
+            "VALID PRIMARY, 1 pool(s), threads 1 live / 0 idle / 12 max, 0 queued, 6.902M used"
+        );
+    }
+
+    #[test]
+    fn test_results_as_set_keys() {
+        let raw = "/a: Access denied. ERROR\0/b: OK\0/c: Win.Trojan.Agent-1 FOUND\0/d: Eicar-Test-Signature FOUND\0/e: OK\0";
+        let results = ScanResult::parse(raw);
+
+        let unique = results.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(unique.len(), 4);
+
+        let signatures = results
+            .into_iter()
+            .filter_map(|r| match r {
+                ScanResult::Found(_, signature, _) => Some(signature.raw),
+                _ => None,
+            })
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(
+            signatures.into_iter().collect::<Vec<_>>(),
+            vec!["Eicar-Test-Signature", "Win.Trojan.Agent-1"]
+        );
+    }
+
+    #[test]
+    fn test_signature_classification() {
+        let trojan = Signature::from("Win.Trojan.Agent-1234-0");
+        assert!(!trojan.is_pua() && !trojan.is_heuristic() && !trojan.is_test());
+        assert_eq!(trojan.family(), Some("Agent"));
+
+        let pua = Signature::from("PUA.Win.Packer.Upx-1");
+        assert!(pua.is_pua());
+        assert_eq!(pua.family(), Some("Upx"));
+
+        assert!(Signature::from("Heuristics.Encrypted.PDF").is_heuristic());
+        assert!(Signature::from("Win.Test.EICAR_HDB-1").is_test());
+
+        let eicar = Signature::from("Eicar-Test-Signature");
+        assert!(eicar.is_test());
+        assert_eq!(eicar.family(), None);
+    }
+
+    #[test]
+    fn test_reload_ack() {
+        assert_eq!(
+            <ReloadAck as ClamResponse>::parse(b"RELOADING\0").unwrap(),
+            ReloadAck
+        );
+
+        match <ReloadAck as ClamResponse>::parse(b"UNKNOWN COMMAND\0") {
+            Err(ClamError::UnexpectedReply("RELOAD", reply)) => {
+                assert_eq!(reply, "UNKNOWN COMMAND\0")
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
     #[test]
     fn test_stats_parse_pools() {
         let parsed = Stats::parse(STATS_STRING).unwrap();
@@ -290,42 +1178,98 @@ mod tests {
     #[test]
     fn test_stats_parse_mem_heap() {
         let parsed = Stats::parse(STATS_STRING).unwrap();
-        assert_eq!(parsed.mem_heap, "9.082M".to_string());
+        assert_eq!(parsed.mem_heap, Some("9.082M".to_string()));
     }
 
     #[test]
     fn test_stats_parse_mem_mmap() {
         let parsed = Stats::parse(STATS_STRING).unwrap();
-        assert_eq!(parsed.mem_mmap, "0.000M".to_string());
+        assert_eq!(parsed.mem_mmap, Some("0.000M".to_string()));
     }
 
     #[test]
     fn test_stats_parse_mem_used() {
         let parsed = Stats::parse(STATS_STRING).unwrap();
-        assert_eq!(parsed.mem_used, "6.902M".to_string());
+        assert_eq!(parsed.mem_used, Some("6.902M".to_string()));
     }
 
     #[test]
     fn test_stats_parse_mem_free() {
         let parsed = Stats::parse(STATS_STRING).unwrap();
-        assert_eq!(parsed.mem_free, "2.184M".to_string());
+        assert_eq!(parsed.mem_free, Some("2.184M".to_string()));
     }
 
     #[test]
     fn test_stats_parse_mem_releaseable() {
         let parsed = Stats::parse(STATS_STRING).unwrap();
-        assert_eq!(parsed.mem_releasable, "0.129M".to_string());
+        assert_eq!(parsed.mem_releasable, Some("0.129M".to_string()));
     }
 
     #[test]
     fn test_stats_parse_pools_used() {
         let parsed = Stats::parse(STATS_STRING).unwrap();
-        assert_eq!(parsed.pools_used, "565.979M".to_string());
+        assert_eq!(parsed.pools_used, Some("565.979M".to_string()));
     }
 
     #[test]
     fn test_stats_parse_pools_total() {
         let parsed = Stats::parse(STATS_STRING).unwrap();
-        assert_eq!(parsed.pools_total, "565.999M".to_string());
+        assert_eq!(parsed.pools_total, Some("565.999M".to_string()));
+    }
+
+    #[test]
+    fn test_stats_parse_other_versions() {
+        let parsed = Stats::parse(STATS_STRING_0_103).unwrap();
+        assert_eq!(parsed.threads_live, 3);
+        assert_eq!(parsed.queue, 2);
+        assert_eq!(parsed.mem_heap, None);
+        assert_eq!(parsed.pools_total, Some("1279.497M".to_string()));
+
+        let parsed = Stats::parse(STATS_STRING_1_X).unwrap();
+        assert_eq!(parsed.pools, 2);
+        assert_eq!(parsed.state, "VALID PRIMARY".to_string());
+        assert_eq!(parsed.threads_max, 20);
+        assert_eq!(parsed.threads_idle_timeout_secs, 60);
+        assert_eq!(parsed.mem_used, Some("9.318M".to_string()));
+        assert_eq!(parsed.raw, STATS_STRING_1_X);
+        assert_eq!(
+            parsed.extra.into_iter().collect::<Vec<_>>(),
+            vec![("THREADS.busy".to_string(), "0".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_stats_delta() {
+        let earlier = Stats::parse(STATS_STRING).unwrap();
+        let later = Stats::parse(STATS_STRING_0_103).unwrap();
+        let delta = later.delta(&earlier);
+
+        assert_eq!(delta.queue, 2);
+        assert_eq!(delta.threads_live, 2);
+        assert_eq!(delta.threads_idle, 0);
+        assert_eq!(delta.mem_used, None);
+
+        let delta = Stats::parse(STATS_STRING_1_X).unwrap().delta(&earlier);
+        assert!((delta.mem_used.unwrap() - 2.416).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_stats_parse_without_memstats() {
+        let raw = "POOLS: 1\n\nSTATE: VALID PRIMARY\nTHREADS: live 1  idle 0 max 12 idle-timeout 30\nQUEUE: 0 items\nEND\0";
+        let parsed = Stats::parse(raw).unwrap();
+        assert_eq!(parsed.threads_max, 12);
+        assert_eq!(parsed.mem_used, None);
+        assert_eq!(parsed.pools_used, None);
+    }
+
+    #[test]
+    fn test_stats_parse_missing_section() {
+        let raw = "POOLS: 1\n\nSTATE: VALID PRIMARY\nEND\0";
+        match Stats::parse(raw) {
+            Err(ClamError::InvalidData(message)) => {
+                assert!(message.starts_with("STATS field THREADS live missing"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 }
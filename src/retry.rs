@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+use crate::response::ScanResult;
+
+/// How often and how patiently a client retries commands that clamd
+/// answered with `RELOADING` while it was loading new signatures, e.g.
+/// after every freshclam update. The delay doubles after each attempt, up
+/// to `max_backoff`.
+///
+/// Only requests that can be sent again are retried: commands, path scans,
+/// and streamed bytes and files. Scans of arbitrary readers and of session
+/// connections surface the reply as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReloadRetry {
+    pub retries: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ReloadRetry {
+    /// Three retries, waiting 250ms, 500ms and 1s.
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl ReloadRetry {
+    pub fn new(retries: u32, backoff: Duration) -> Self {
+        Self {
+            retries,
+            backoff,
+            ..Self::default()
+        }
+    }
+
+    /// Surfaces reload replies right away.
+    pub fn disabled() -> Self {
+        Self::new(0, Duration::ZERO)
+    }
+
+    /// The waits before each retry.
+    pub(crate) fn delays(&self) -> impl Iterator<Item = Duration> {
+        let (backoff, max_backoff) = (self.backoff, self.max_backoff);
+        (0..self.retries).map(move |n| {
+            backoff
+                .checked_mul(1 << n.min(16))
+                .unwrap_or(max_backoff)
+                .min(max_backoff)
+        })
+    }
+}
+
+/// Whether a raw reply says the daemon is reloading its database. Only whole
+/// reply lines count, so a scanned path that merely contains the word does
+/// not.
+pub(crate) fn reloading_reply(raw: &[u8]) -> bool {
+    String::from_utf8_lossy(raw)
+        .split(['\0', '\n'])
+        .any(reloading_line)
+}
+
+pub(crate) fn reloading_result(result: &ScanResult) -> bool {
+    match result {
+        ScanResult::Error(message) => reloading_line(message),
+        _ => false,
+    }
+}
+
+// "RELOADING", or a scan reply such as "stream: RELOADING"
+fn reloading_line(line: &str) -> bool {
+    let line = line.trim_end();
+    line == "RELOADING" || line.ends_with(": RELOADING")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delays() {
+        let retry = ReloadRetry {
+            retries: 5,
+            backoff: Duration::from_millis(300),
+            max_backoff: Duration::from_secs(1),
+        };
+        let delays = retry.delays().map(|d| d.as_millis()).collect::<Vec<_>>();
+        assert_eq!(delays, [300, 600, 1000, 1000, 1000]);
+        assert_eq!(ReloadRetry::disabled().delays().count(), 0);
+    }
+
+    #[test]
+    fn test_reloading_reply() {
+        assert!(reloading_reply(b"RELOADING\0"));
+        assert!(reloading_reply(b"stream: RELOADING\0"));
+        assert!(!reloading_reply(b"/srv/RELOADING: OK\0"));
+        assert!(!reloading_reply(b"PONG\0"));
+        assert!(reloading_result(&ScanResult::Error(String::from(
+            "stream: RELOADING"
+        ))));
+    }
+}
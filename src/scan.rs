@@ -0,0 +1,89 @@
+use std::future::Future;
+
+use crate::client::{ClamClient, Result};
+#[cfg(feature = "tokio")]
+use crate::offload::AsyncClamClient;
+use crate::options::{ScanOptions, ScanTarget};
+use crate::response::{ScanResult, Stats, Version};
+
+/// The client operations most applications depend on, so code handling
+/// verdicts can be tested against the doubles in `testing` instead of a
+/// running daemon.
+pub trait ClamScan {
+    fn ping(&self) -> bool;
+
+    fn version(&self) -> Result<Version>;
+
+    fn scan(&self, target: ScanTarget<'_>, options: ScanOptions) -> Result<Vec<ScanResult>>;
+
+    fn stats(&self) -> Result<Stats>;
+
+    fn scan_bytes(&self, b: &[u8]) -> Result<ScanResult> {
+        let results = self.scan(ScanTarget::Bytes(b), ScanOptions::default())?;
+        Ok(results.into_iter().next().unwrap_or(ScanResult::Ok))
+    }
+}
+
+/// The async counterpart of `ClamScan`, implemented by `AsyncClamClient` and
+/// the doubles in `testing`. Scans take owned data, as the async client
+/// hands it to a blocking thread.
+pub trait AsyncClamScan {
+    fn ping(&self) -> impl Future<Output = bool> + Send;
+
+    fn version(&self) -> impl Future<Output = Result<Version>> + Send;
+
+    fn scan_path(
+        &self,
+        path: String,
+        continue_on_virus: bool,
+    ) -> impl Future<Output = Result<Vec<ScanResult>>> + Send;
+
+    fn stats(&self) -> impl Future<Output = Result<Stats>> + Send;
+
+    fn scan_bytes(&self, b: Vec<u8>) -> impl Future<Output = Result<ScanResult>> + Send;
+}
+
+impl ClamScan for ClamClient {
+    fn ping(&self) -> bool {
+        ClamClient::ping(self)
+    }
+
+    fn version(&self) -> Result<Version> {
+        ClamClient::version(self)
+    }
+
+    fn scan(&self, target: ScanTarget<'_>, options: ScanOptions) -> Result<Vec<ScanResult>> {
+        ClamClient::scan(self, target, options)
+    }
+
+    fn stats(&self) -> Result<Stats> {
+        ClamClient::stats(self)
+    }
+
+    fn scan_bytes(&self, b: &[u8]) -> Result<ScanResult> {
+        ClamClient::scan_bytes(self, b)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncClamScan for AsyncClamClient {
+    async fn ping(&self) -> bool {
+        AsyncClamClient::ping(self).await
+    }
+
+    async fn version(&self) -> Result<Version> {
+        AsyncClamClient::version(self).await
+    }
+
+    async fn scan_path(&self, path: String, continue_on_virus: bool) -> Result<Vec<ScanResult>> {
+        AsyncClamClient::scan_path(self, path, continue_on_virus).await
+    }
+
+    async fn stats(&self) -> Result<Stats> {
+        AsyncClamClient::stats(self).await
+    }
+
+    async fn scan_bytes(&self, b: Vec<u8>) -> Result<ScanResult> {
+        AsyncClamClient::scan_bytes(self, b).await
+    }
+}
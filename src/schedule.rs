@@ -0,0 +1,459 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client::{ClamClient, Result};
+use crate::dir::{DirScan, SymlinkPolicy};
+use crate::error::ClamError;
+use crate::filter::FileFilter;
+use crate::report::ScanReport;
+
+/// When `Rescan` runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// Right away, then `interval` after each run started; a run that takes
+    /// longer is followed by the next one immediately.
+    Every(Duration),
+    /// At every minute a crontab line would, see `Schedule::cron`.
+    Cron(Cron),
+}
+
+/// The five time fields of a crontab line, as bit sets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // a `*` day field doesn't restrict the other one
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// Parses `minute hour day-of-month month day-of-week`, each field a
+    /// `*`, number or range, optionally with a `/step`, or a comma-separated
+    /// list of those; Sunday is 0 or 7. As in cron, a day matching either
+    /// restricted day field is scheduled. Times are UTC.
+    ///
+    /// ```
+    /// use clamav::schedule::Schedule;
+    ///
+    /// // 02:30 every night, and every six hours on weekends
+    /// assert!(Schedule::cron("30 2 * * *").is_ok());
+    /// assert!(Schedule::cron("0 */6 * * 6,0").is_ok());
+    /// assert!(Schedule::cron("0 24 * * *").is_err());
+    /// ```
+    pub fn cron(expression: &str) -> Result<Self> {
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(ClamError::InvalidData(format!(
+                "cron expression {:?} does not have five fields",
+                expression
+            )));
+        }
+
+        let field = |i: usize, min, max| parse_field(fields[i], min, max, expression);
+        let weekdays = field(4, 0, 7)?;
+        Ok(Schedule::Cron(Cron {
+            minutes: field(0, 0, 59)?,
+            hours: field(1, 0, 23)?,
+            days: field(2, 1, 31)?,
+            months: field(3, 1, 12)?,
+            // 7 is another Sunday
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        }))
+    }
+
+    /// When the first run after `start` is due, if ever.
+    fn first(&self, start: SystemTime) -> Option<SystemTime> {
+        match self {
+            Schedule::Every(_) => Some(start),
+            Schedule::Cron(cron) => cron.next_after(start),
+        }
+    }
+
+    /// When the run after one started at `started` is due, if ever.
+    fn next(&self, started: SystemTime) -> Option<SystemTime> {
+        match self {
+            Schedule::Every(interval) => Some(started + *interval),
+            // runs missed while scanning are skipped
+            Schedule::Cron(cron) => cron.next_after(SystemTime::now().max(started)),
+        }
+    }
+}
+
+impl Cron {
+    /// The first scheduled minute strictly after `time`, looking up to five
+    /// years ahead.
+    fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let minute = time.duration_since(UNIX_EPOCH).ok()?.as_secs() / 60 + 1;
+        let first_day = minute / 1440;
+
+        for day in first_day..first_day + 5 * 366 {
+            if !self.runs_on(day) {
+                continue;
+            }
+
+            let from = if day == first_day { minute % 1440 } else { 0 };
+            if let Some(of_day) = (from..1440).find(|m| self.runs_at(m / 60, m % 60)) {
+                let secs = (day * 1440 + of_day) * 60;
+                return Some(UNIX_EPOCH + Duration::from_secs(secs));
+            }
+        }
+
+        None
+    }
+
+    fn runs_on(&self, days_since_epoch: u64) -> bool {
+        let (_, month, day) = civil_date(days_since_epoch);
+        // the epoch was a Thursday
+        let weekday = (days_since_epoch + 4) % 7;
+        let day_matches = self.days & 1 << day != 0;
+        let weekday_matches = self.weekdays & 1 << weekday != 0;
+
+        self.months & 1 << month != 0
+            && match (self.any_day, self.any_weekday) {
+                (true, true) => true,
+                (true, false) => weekday_matches,
+                (false, true) => day_matches,
+                (false, false) => day_matches || weekday_matches,
+            }
+    }
+
+    fn runs_at(&self, hour: u64, minute: u64) -> bool {
+        self.hours & 1 << hour != 0 && self.minutes & 1 << minute != 0
+    }
+}
+
+fn parse_field(field: &str, min: u64, max: u64, expression: &str) -> Result<u64> {
+    let invalid = || {
+        ClamError::InvalidData(format!(
+            "cron expression {:?} has an invalid field {:?}",
+            expression, field
+        ))
+    };
+    let number = |s: &str| match s.parse::<u64>() {
+        Ok(n) if (min..=max).contains(&n) => Ok(n),
+        _ => Err(invalid()),
+    };
+
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<u64>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(invalid()),
+            },
+            None => (item, 1),
+        };
+
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (number(from)?, number(to)?),
+            // `5/15` runs from 5 to the end of the range
+            None if item.contains('/') => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if from > to {
+            return Err(invalid());
+        }
+
+        for n in (from..=to).step_by(step as usize) {
+            set |= 1 << n;
+        }
+    }
+
+    Ok(set)
+}
+
+/// The year, month and day of a day counted from 1970-01-01, in the
+/// proleptic Gregorian calendar.
+fn civil_date(days_since_epoch: u64) -> (u64, u64, u64) {
+    let days = days_since_epoch + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// One directory scan of a `Rescan` run.
+#[derive(Debug)]
+pub struct ScheduledReport {
+    pub root: PathBuf,
+    pub started_at: SystemTime,
+    pub report: Result<ScanReport>,
+}
+
+/// Rescans a set of directories on a `Schedule`, one after another, on a
+/// background thread; every `ScanReport` is delivered over a channel, and
+/// per-file `ScanEvent`s go to the client's observer as usual. With a skip
+/// cache, only files changed since they were last found clean are sent to
+/// the daemon, which turns nightly full scans into incremental ones.
+///
+/// ```no_run
+/// use clamav::schedule::{Rescan, Schedule};
+/// use clamav::ClamClient;
+///
+/// let client = ClamClient::new("127.0.0.1", 3310).unwrap();
+/// let rescans = Rescan::new(client, Schedule::cron("30 2 * * *").unwrap())
+///     .path("/srv/uploads")
+///     .skip_cache("/var/cache/clamav-client/uploads")
+///     .start();
+///
+/// while let Ok(scheduled) = rescans.recv() {
+///     if let Ok(report) = scheduled.report {
+///         for (path, signature) in report.infected() {
+///             println!("{}: {}", path.display(), signature.raw);
+///         }
+///     }
+/// }
+/// ```
+pub struct Rescan {
+    client: ClamClient,
+    schedule: Schedule,
+    roots: Vec<PathBuf>,
+    skip_cache: Option<PathBuf>,
+    filter: FileFilter,
+    symlinks: SymlinkPolicy,
+    jobs: usize,
+}
+
+impl Rescan {
+    pub fn new(client: ClamClient, schedule: Schedule) -> Self {
+        Self {
+            client,
+            schedule,
+            roots: Vec::new(),
+            skip_cache: None,
+            filter: FileFilter::default(),
+            symlinks: SymlinkPolicy::default(),
+            jobs: 1,
+        }
+    }
+
+    /// Adds a directory to every run.
+    pub fn path<P: AsRef<Path>>(mut self, root: P) -> Self {
+        self.roots.push(root.as_ref().to_path_buf());
+        self
+    }
+
+    /// Shares one skip cache between all directories and runs, see
+    /// `DirScan::skip_cache`.
+    pub fn skip_cache<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.skip_cache = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// See `DirScan::filter`.
+    pub fn filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// See `DirScan::symlinks`.
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// See `DirScan::jobs`.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    pub fn start(self) -> RescanHandle {
+        let (stop, stopped) = mpsc::channel();
+        let (reports, receiver) = mpsc::channel();
+        let runner = thread::spawn(move || self.run(&stopped, &reports));
+
+        RescanHandle {
+            stop: Some(stop),
+            reports: receiver,
+            runner: Some(runner),
+        }
+    }
+
+    fn run(&self, stopped: &Receiver<()>, reports: &Sender<ScheduledReport>) {
+        let mut due = self.schedule.first(SystemTime::now());
+
+        while let Some(at) = due {
+            let wait = at.duration_since(SystemTime::now()).unwrap_or_default();
+            match stopped.recv_timeout(wait) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+
+            let started = SystemTime::now();
+            for root in &self.roots {
+                let report = self.scan(root);
+                let scheduled = ScheduledReport {
+                    root: root.clone(),
+                    started_at: started,
+                    report,
+                };
+                if reports.send(scheduled).is_err() {
+                    return;
+                }
+            }
+
+            due = self.schedule.next(started);
+        }
+    }
+
+    fn scan(&self, root: &Path) -> Result<ScanReport> {
+        let mut scan = DirScan::new(&self.client, root)
+            .filter(self.filter.clone())
+            .symlinks(self.symlinks)
+            .jobs(self.jobs);
+        if let Some(cache) = &self.skip_cache {
+            scan = scan.skip_cache(cache);
+        }
+
+        scan.run()
+    }
+}
+
+/// A running `Rescan`. Dropping it stops the schedule, after the directory
+/// being scanned, if any, is done.
+pub struct RescanHandle {
+    stop: Option<Sender<()>>,
+    reports: Receiver<ScheduledReport>,
+    runner: Option<JoinHandle<()>>,
+}
+
+impl RescanHandle {
+    /// Blocks until the next directory has been scanned.
+    pub fn recv(&self) -> Result<ScheduledReport> {
+        match self.reports.recv() {
+            Ok(report) => Ok(report),
+            Err(_) => Err(ClamError::ServiceStopped),
+        }
+    }
+
+    /// Returns the next report if one is already waiting.
+    pub fn try_recv(&self) -> Option<ScheduledReport> {
+        self.reports.try_recv().ok()
+    }
+
+    /// Stops the schedule and waits for the scanning thread to exit.
+    pub fn stop(mut self) {
+        self.halt();
+    }
+
+    fn halt(&mut self) {
+        self.stop = None;
+        if let Some(runner) = self.runner.take() {
+            let _ = runner.join();
+        }
+    }
+}
+
+impl Drop for RescanHandle {
+    fn drop(&mut self) {
+        self.halt();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryTransport;
+    use crate::response::ScanResult;
+    use std::fs;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn next(expression: &str, after: u64) -> Option<SystemTime> {
+        match Schedule::cron(expression).unwrap() {
+            Schedule::Cron(cron) => cron.next_after(at(after)),
+            Schedule::Every(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_cron() {
+        // 2024-02-28T23:59:30Z, a Wednesday
+        let now = 1_709_164_770;
+        assert_eq!(civil_date(now / 86_400), (2024, 2, 28));
+
+        assert_eq!(next("* * * * *", now), Some(at(1_709_164_800)));
+        assert_eq!(next("30 2 * * *", now), Some(at(1_709_173_800)));
+        // the leap day
+        assert_eq!(next("0 0 29 * *", now), Some(at(1_709_164_800)));
+        assert_eq!(next("0 0 * * 0", now), Some(at(1_709_424_000)));
+        assert_eq!(next("0 0 * * 7", now), Some(at(1_709_424_000)));
+        // either day field matching is enough
+        assert_eq!(next("0 0 1 * 4", now), Some(at(1_709_164_800)));
+        assert_eq!(next("*/20 0 * * *", now + 60), Some(at(1_709_166_000)));
+        assert_eq!(next("0 0 30 2 *", now), None);
+
+        for invalid in [
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "5-1 * * * *",
+            "*/0 * * * *",
+        ] {
+            assert!(Schedule::cron(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_rescan_uses_skip_cache() {
+        let root =
+            std::env::temp_dir().join(format!("clamav-client-rescan-test-{}", std::process::id()));
+        let cache = std::env::temp_dir().join(format!(
+            "clamav-client-rescan-test-{}.cache",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&root);
+        let _ = fs::remove_file(&cache);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a"), b"one").unwrap();
+
+        let version = b"ClamAV 1.0.0/24802/Mon Jan  1 00:00:00 2024\0";
+        let transport = MemoryTransport::new()
+            .reply(version)
+            .reply(b"1: stream: OK\0")
+            .reply(version)
+            .reply(b"");
+        let client = ClamClient::new_memory(transport);
+        let rescans = Rescan::new(client, Schedule::Every(Duration::from_millis(10)))
+            .path(&root)
+            .skip_cache(&cache)
+            .start();
+
+        let first = rescans.recv().unwrap().report.unwrap();
+        let second = rescans.recv().unwrap();
+        // the script has run out
+        assert!(rescans.recv().unwrap().report.is_err());
+        rescans.stop();
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(&cache).unwrap();
+
+        assert_eq!(first.results, vec![(root.join("a"), ScanResult::Ok)]);
+        assert_eq!(second.root, root);
+        let second = second.report.unwrap();
+        assert!(second.results.is_empty());
+        assert_eq!(second.unchanged, vec![root.join("a")]);
+    }
+}
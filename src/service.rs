@@ -0,0 +1,189 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::client::{ClamClient, Result};
+use crate::error::ClamError;
+use crate::response::ScanResult;
+
+/// Work accepted by a `ScanService`.
+pub enum ScanJob {
+    /// A local file, streamed to the daemon.
+    File(PathBuf),
+    Bytes(Vec<u8>),
+    Reader(Box<dyn Read + Send>),
+}
+
+impl ScanJob {
+    pub(crate) fn scan(self, client: &ClamClient) -> Result<ScanResult> {
+        match self {
+            ScanJob::File(path) => client.scan_file(path),
+            ScanJob::Bytes(bytes) => client.scan_bytes(bytes),
+            ScanJob::Reader(reader) => client.scan_stream(reader),
+        }
+    }
+
+    /// A copy to scan again, unless the job is a reader.
+    pub(crate) fn try_clone(&self) -> Option<Self> {
+        match self {
+            ScanJob::File(path) => Some(ScanJob::File(path.clone())),
+            ScanJob::Bytes(bytes) => Some(ScanJob::Bytes(bytes.clone())),
+            ScanJob::Reader(_) => None,
+        }
+    }
+}
+
+impl From<PathBuf> for ScanJob {
+    fn from(path: PathBuf) -> Self {
+        ScanJob::File(path)
+    }
+}
+
+impl From<&Path> for ScanJob {
+    fn from(path: &Path) -> Self {
+        ScanJob::File(path.to_owned())
+    }
+}
+
+impl From<Vec<u8>> for ScanJob {
+    fn from(bytes: Vec<u8>) -> Self {
+        ScanJob::Bytes(bytes)
+    }
+}
+
+struct Task {
+    job: ScanJob,
+    reply: Sender<Result<ScanResult>>,
+}
+
+/// A pool of worker threads scanning submitted jobs with a shared client.
+/// Dropping the service stops accepting work and waits for queued jobs to
+/// finish.
+pub struct ScanService {
+    tasks: Option<Sender<Task>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// Receives the verdict of one submitted job.
+pub struct ScanTicket {
+    reply: Receiver<Result<ScanResult>>,
+}
+
+impl ScanService {
+    pub fn spawn(client: ClamClient, workers: usize) -> Self {
+        let client = Arc::new(client);
+        let (tasks, queue) = mpsc::channel::<Task>();
+        let queue = Arc::new(Mutex::new(queue));
+
+        let workers = (0..workers.max(1))
+            .map(|_| {
+                let client = Arc::clone(&client);
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || work(&client, &queue))
+            })
+            .collect();
+
+        Self {
+            tasks: Some(tasks),
+            workers,
+        }
+    }
+
+    pub fn submit(&self, job: ScanJob) -> Result<ScanTicket> {
+        let (reply, ticket) = mpsc::channel();
+        let task = Task { job, reply };
+
+        match &self.tasks {
+            Some(tasks) if tasks.send(task).is_ok() => Ok(ScanTicket { reply: ticket }),
+            _ => Err(ClamError::ServiceStopped),
+        }
+    }
+
+    /// Stops accepting jobs and waits for the queued ones to complete.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.tasks = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for ScanService {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl ScanTicket {
+    /// Blocks until the job has been scanned.
+    pub fn wait(self) -> Result<ScanResult> {
+        match self.reply.recv() {
+            Ok(result) => result,
+            Err(_) => Err(ClamError::ServiceStopped),
+        }
+    }
+
+    /// Returns the verdict if the job has already been scanned.
+    pub fn try_wait(&self) -> Option<Result<ScanResult>> {
+        self.reply.try_recv().ok()
+    }
+}
+
+fn work(client: &ClamClient, queue: &Mutex<Receiver<Task>>) {
+    loop {
+        let task = match queue.lock() {
+            Ok(queue) => queue.recv(),
+            Err(_) => return,
+        };
+
+        let task = match task {
+            Ok(task) => task,
+            Err(_) => return,
+        };
+
+        let _ = task.reply.send(task.job.scan(client));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDaemon;
+
+    #[test]
+    fn test_service_scans_submitted_jobs() {
+        let daemon = MockDaemon::start_many(b"stream: OK\0", 3);
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let service = ScanService::spawn(client, 2);
+
+        let tickets = vec![
+            service.submit(ScanJob::Bytes(b"one".to_vec())).unwrap(),
+            service
+                .submit(ScanJob::Reader(Box::new(&b"two"[..])))
+                .unwrap(),
+            service.submit(ScanJob::Bytes(b"three".to_vec())).unwrap(),
+        ];
+
+        for ticket in tickets {
+            assert_eq!(ticket.wait().unwrap(), ScanResult::Ok);
+        }
+
+        service.shutdown();
+        let mut payloads = daemon
+            .all_received()
+            .iter()
+            .map(|r| r.payload())
+            .collect::<Vec<_>>();
+        payloads.sort();
+        assert_eq!(
+            payloads,
+            vec![b"one".to_vec(), b"three".to_vec(), b"two".to_vec()]
+        );
+    }
+}
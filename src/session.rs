@@ -0,0 +1,801 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::client::{check_terminated, read_failed, ClamClient, Result, Tracked};
+use crate::command::{Command, Framing};
+use crate::counters::Counters;
+use crate::error::ClamError;
+use crate::limit::ConcurrencyPermit;
+use crate::pool::{BufferPool, PooledWriter};
+use crate::response::{ClamResponse, ScanResult};
+use crate::service::ScanJob;
+use crate::transport::Connection;
+
+static SESSIONS: AtomicU64 = AtomicU64::new(0);
+
+/// One connection kept open with IDSESSION so several commands can be sent
+/// without reconnecting. Replies carry the id of the command they answer,
+/// which lets several commands be submitted before any reply is read; clamd
+/// may answer them in any order.
+pub struct ClamSession<'a> {
+    client: &'a ClamClient,
+    connection: BufReader<Connection>,
+    // tells this session's `Pending`s from those of others
+    session: u64,
+    next_id: u64,
+    // submitted commands whose reply hasn't been read yet
+    outstanding: HashSet<u64>,
+    // replies read while waiting for another command
+    replies: HashMap<u64, String>,
+    // submitted streams, finished once their verdict is waited for
+    tracked: HashMap<u64, Tracked>,
+    // END was sent by `end`, so dropping mustn't send it again
+    ended: bool,
+    // an upload broke off after INSTREAM was sent, so the framing of the
+    // connection can't be trusted
+    broken: bool,
+    _permit: Option<ConcurrencyPermit>,
+}
+
+/// A command submitted to a session whose reply, parsed as `T`, is
+/// collected with `ClamSession::wait`.
+#[derive(Debug)]
+pub struct Pending<T> {
+    session: u64,
+    id: u64,
+    _reply: PhantomData<fn() -> T>,
+}
+
+impl<T> Pending<T> {
+    /// The request number clamd prefixes the reply with.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<'a> ClamSession<'a> {
+    pub(crate) fn start(client: &'a ClamClient) -> Result<Self> {
+        let permit = client.concurrency_permit()?;
+        let connection = client.connect()?;
+        client.command_write(&connection, &Command::IdSession)?;
+
+        Ok(Self {
+            client,
+            connection: BufReader::new(connection),
+            session: SESSIONS.fetch_add(1, Ordering::Relaxed),
+            next_id: 1,
+            outstanding: HashSet::new(),
+            replies: HashMap::new(),
+            tracked: HashMap::new(),
+            ended: false,
+            broken: false,
+            _permit: permit,
+        })
+    }
+
+    pub fn scan_bytes<B: AsRef<[u8]>>(&mut self, b: B) -> Result<ScanResult> {
+        self.scan_stream(b.as_ref())
+    }
+
+    pub fn scan_file<P: AsRef<Path>>(&mut self, path: P) -> Result<ScanResult> {
+        match File::open(&path) {
+            Ok(file) => self.scan_open_file(&file, path),
+            Err(e) => {
+                let path = path.as_ref();
+                let tracked = self.client.track(&path.to_string_lossy(), Some(path));
+                let e = ClamError::StreamError(e);
+                self.client.finish(tracked, Err(&e))?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Like `ClamClient::scan_open_file`, over the session.
+    pub fn scan_open_file<P: AsRef<Path>>(
+        &mut self,
+        mut file: &File,
+        label: P,
+    ) -> Result<ScanResult> {
+        let label = label.as_ref();
+        let tracked = self.client.track(&label.to_string_lossy(), Some(label));
+        if let Err(e) = file.seek(SeekFrom::Start(0)) {
+            let e = ClamError::StreamError(e);
+            self.client.finish(tracked, Err(&e))?;
+            return Err(e);
+        }
+
+        let pending = self.submit_tracked(file, tracked)?;
+        self.wait(pending)
+    }
+
+    pub fn scan_stream<T: Read>(&mut self, s: T) -> Result<ScanResult> {
+        let pending = self.submit_stream(s)?;
+        self.wait(pending)
+    }
+
+    /// Sends a command without waiting for its reply. Streams must be sent
+    /// with `submit_stream`, and the session is opened and ended by the
+    /// session itself.
+    ///
+    /// clamd stops reading while its replies aren't being consumed, so a
+    /// long pipeline should be interleaved with `wait` calls.
+    pub fn submit<T: ClamResponse>(&mut self, command: &Command) -> Result<Pending<T>> {
+        if let Command::Instream | Command::IdSession | Command::End = command {
+            return Err(ClamError::InvalidData(format!(
+                "{} cannot be submitted to a session",
+                command.name()
+            )));
+        }
+
+        self.client.check_supported(command)?;
+        self.client
+            .command_write(self.connection.get_ref(), command)?;
+        Ok(self.pending())
+    }
+
+    pub fn submit_bytes<B: AsRef<[u8]>>(&mut self, b: B) -> Result<Pending<ScanResult>> {
+        self.submit_stream(b.as_ref())
+    }
+
+    pub fn submit_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Pending<ScanResult>> {
+        let path = path.as_ref();
+        let tracked = self.client.track(&path.to_string_lossy(), Some(path));
+        match File::open(path) {
+            Ok(file) => self.submit_tracked(file, tracked),
+            Err(e) => {
+                let e = ClamError::StreamError(e);
+                self.client.finish(tracked, Err(&e))?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Uploads `s` without waiting for the verdict. The scan is audited and
+    /// observed as finished once its verdict is waited for.
+    pub fn submit_stream<T: Read>(&mut self, s: T) -> Result<Pending<ScanResult>> {
+        let tracked = self.client.track("stream", None);
+        self.submit_tracked(s, tracked)
+    }
+
+    fn submit_tracked<T: Read>(
+        &mut self,
+        s: T,
+        mut tracked: Tracked,
+    ) -> Result<Pending<ScanResult>> {
+        match self.upload(s, &mut tracked) {
+            Ok(pending) => {
+                self.tracked.insert(pending.id, tracked);
+                Ok(pending)
+            }
+            Err(e) => {
+                self.broken = true;
+                self.client.finish(tracked, Err(&e))?;
+                Err(e)
+            }
+        }
+    }
+
+    fn upload<T: Read>(&mut self, mut s: T, tracked: &mut Tracked) -> Result<Pending<ScanResult>> {
+        let client = self.client;
+        client.pace_scan();
+        let mut buffer = BufferPool::get(client.buffers());
+        buffer.resize(client.chunk_size(), 0);
+
+        {
+            let connection = self.connection.get_ref();
+            let mut writer = PooledWriter::new(connection, BufferPool::get(client.buffers()));
+            client.command_write(&mut writer, &Command::Instream)?;
+
+            loop {
+                let bytes_read = match s.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(ClamError::StreamError(e)),
+                };
+
+                client.throttle(bytes_read as u64);
+                client.frame_write(&mut writer, &buffer[..bytes_read])?;
+                client.chunk_sent(tracked, bytes_read as u64);
+            }
+
+            client.finish_instream(writer)?;
+        }
+
+        Ok(self.pending())
+    }
+
+    /// Reads replies until the one for `pending` arrives, keeping replies to
+    /// other submitted commands for their own `wait`. Fails with
+    /// `ForeignPending` if `pending` was submitted to another session.
+    pub fn wait<T: ClamResponse>(&mut self, pending: Pending<T>) -> Result<T> {
+        if pending.session != self.session {
+            return Err(ClamError::ForeignPending(pending.id));
+        }
+
+        let reply = self.reply_to(pending.id);
+        if let Some(tracked) = self.tracked.remove(&pending.id) {
+            match &reply {
+                Ok(reply) => {
+                    let verdict = <ScanResult as ClamResponse>::parse(reply.as_bytes());
+                    self.client.finish(tracked, verdict.as_ref())?;
+                }
+                Err(e) => self.client.finish(tracked, Err(e))?,
+            }
+        }
+
+        T::parse(reply?.as_bytes())
+    }
+
+    fn reply_to(&mut self, wanted: u64) -> Result<String> {
+        if let Some(reply) = self.replies.remove(&wanted) {
+            return Ok(reply);
+        }
+
+        loop {
+            let (id, reply) = self.reply()?;
+            if id == wanted {
+                return Ok(reply);
+            }
+            self.replies.insert(id, reply);
+        }
+    }
+
+    /// Fails unless the daemon answers PING over the session.
+    pub(crate) fn ping(&mut self) -> Result<()> {
+        let pending = self.submit::<String>(&Command::Ping)?;
+        match self.wait(pending)?.trim_end_matches('\0') {
+            "PONG" => Ok(()),
+            reply => Err(ClamError::InvalidData(reply.to_string())),
+        }
+    }
+
+    fn pending<T>(&mut self) -> Pending<T> {
+        let id = self.next_id;
+        self.next_id += 1;
+        if id > 1 {
+            self.client.counters().connection_reused();
+        }
+        self.outstanding.insert(id);
+
+        Pending {
+            session: self.session,
+            id,
+            _reply: PhantomData,
+        }
+    }
+
+    /// Ends the session, letting clamd close the connection.
+    pub fn end(mut self) -> Result<()> {
+        self.ended = true;
+        self.send_end()
+    }
+
+    fn send_end(&mut self) -> Result<()> {
+        self.client
+            .command_write(self.connection.get_ref(), &Command::End)
+    }
+
+    /// Reads the next reply and splits off its `<id>: ` prefix, which must
+    /// name an outstanding command.
+    fn reply(&mut self) -> Result<(u64, String)> {
+        let delimiter = self.client.framing().delimiter();
+        let mut raw = Vec::new();
+        match self.connection.read_until(delimiter, &mut raw) {
+            Ok(0) => return Err(ClamError::ConnectionError(ErrorKind::UnexpectedEof.into())),
+            Ok(n) => self.client.counters().received(n as u64),
+            Err(e) => return Err(read_failed(e, ClamError::ConnectionError)),
+        }
+        check_terminated(&raw, self.client.framing())?;
+
+        let raw = String::from_utf8_lossy(&raw);
+        let raw = raw.trim_end_matches(char::from(delimiter));
+
+        if let Some((id, reply)) = raw.split_once(": ") {
+            if let Ok(id) = id.parse() {
+                if self.outstanding.remove(&id) {
+                    return Ok((id, reply.to_owned()));
+                }
+            }
+        }
+
+        Err(ClamError::InvalidData(raw.to_owned()))
+    }
+}
+
+impl Drop for ClamSession<'_> {
+    fn drop(&mut self) {
+        // streams whose verdict was never collected
+        for (_, tracked) in self.tracked.drain() {
+            let _ = self.client.finish(tracked, Err(&ClamError::Cancelled));
+        }
+        if !self.ended {
+            let _ = self.send_end();
+        }
+    }
+}
+
+/// The IDSESSION connection a client keeps open for PING, VERSION and STATS
+/// once `ClamClient::with_persistent_session` is set. Commands are sent one
+/// at a time, so each reply answers the command just sent.
+pub(crate) struct KeepAlive {
+    connection: BufReader<Connection>,
+    next_id: u64,
+    counters: Arc<Counters>,
+    framing: Framing,
+    // when the last command was sent
+    used: Instant,
+}
+
+impl KeepAlive {
+    pub(crate) fn open(client: &ClamClient) -> Result<Self> {
+        let connection = client.connect()?;
+        client.command_write(&connection, &Command::IdSession)?;
+
+        Ok(Self {
+            connection: BufReader::new(connection),
+            next_id: 1,
+            counters: Arc::clone(client.counters()),
+            framing: client.framing(),
+            used: Instant::now(),
+        })
+    }
+
+    /// How long the connection went without a command.
+    pub(crate) fn idle(&self) -> Duration {
+        self.used.elapsed()
+    }
+
+    /// Fails unless the daemon answers PING.
+    pub(crate) fn ping(&mut self, client: &ClamClient) -> Result<()> {
+        let reply = self.command(client, &Command::Ping)?;
+        let reply = String::from_utf8_lossy(&reply);
+        match reply.trim_end_matches(['\0', '\n'].as_ref()) {
+            "PONG" => Ok(()),
+            reply => Err(ClamError::InvalidData(reply.to_string())),
+        }
+    }
+
+    /// Sends `command` and returns its reply without the `<id>: ` prefix.
+    pub(crate) fn command(&mut self, client: &ClamClient, command: &Command) -> Result<Vec<u8>> {
+        client.command_write(self.connection.get_ref(), command)?;
+        self.used = Instant::now();
+        let id = self.next_id;
+        self.next_id += 1;
+        if id > 1 {
+            self.counters.connection_reused();
+        }
+
+        let mut raw = Vec::new();
+        match self
+            .connection
+            .read_until(self.framing.delimiter(), &mut raw)
+        {
+            Ok(0) => return Err(ClamError::ConnectionError(ErrorKind::UnexpectedEof.into())),
+            Ok(n) => self.counters.received(n as u64),
+            Err(e) => return Err(read_failed(e, ClamError::ConnectionError)),
+        }
+        check_terminated(&raw, self.framing)?;
+
+        let prefix = format!("{}: ", id);
+        match raw.strip_prefix(prefix.as_bytes()) {
+            Some(reply) => Ok(reply.to_vec()),
+            None => Err(ClamError::InvalidData(
+                String::from_utf8_lossy(&raw).into_owned(),
+            )),
+        }
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        let end = Command::End.encode_framed(self.framing);
+        if self.connection.get_mut().write_all(&end).is_ok() {
+            self.counters.command();
+            self.counters.sent(end.len() as u64);
+        }
+    }
+}
+
+/// Something `ClamClient::scan_iter` and `ClamClient::scan_bulk` can scan
+/// over a session.
+pub trait ScanInput {
+    fn scan_in(&mut self, session: &mut ClamSession<'_>) -> Result<ScanResult>;
+
+    /// Uploads the input without waiting for its verdict.
+    fn submit_in(&mut self, session: &mut ClamSession<'_>) -> Result<Pending<ScanResult>>;
+}
+
+impl ScanInput for PathBuf {
+    fn scan_in(&mut self, session: &mut ClamSession<'_>) -> Result<ScanResult> {
+        session.scan_file(self)
+    }
+
+    fn submit_in(&mut self, session: &mut ClamSession<'_>) -> Result<Pending<ScanResult>> {
+        session.submit_file(self)
+    }
+}
+
+impl ScanInput for &Path {
+    fn scan_in(&mut self, session: &mut ClamSession<'_>) -> Result<ScanResult> {
+        session.scan_file(self)
+    }
+
+    fn submit_in(&mut self, session: &mut ClamSession<'_>) -> Result<Pending<ScanResult>> {
+        session.submit_file(self)
+    }
+}
+
+impl ScanInput for Vec<u8> {
+    fn scan_in(&mut self, session: &mut ClamSession<'_>) -> Result<ScanResult> {
+        session.scan_bytes(self)
+    }
+
+    fn submit_in(&mut self, session: &mut ClamSession<'_>) -> Result<Pending<ScanResult>> {
+        session.submit_bytes(self)
+    }
+}
+
+impl ScanInput for &[u8] {
+    fn scan_in(&mut self, session: &mut ClamSession<'_>) -> Result<ScanResult> {
+        session.scan_bytes(self)
+    }
+
+    fn submit_in(&mut self, session: &mut ClamSession<'_>) -> Result<Pending<ScanResult>> {
+        session.submit_bytes(self)
+    }
+}
+
+impl ScanInput for ScanJob {
+    fn scan_in(&mut self, session: &mut ClamSession<'_>) -> Result<ScanResult> {
+        match self {
+            ScanJob::File(path) => session.scan_file(path),
+            ScanJob::Bytes(bytes) => session.scan_bytes(bytes),
+            ScanJob::Reader(reader) => session.scan_stream(reader),
+        }
+    }
+
+    fn submit_in(&mut self, session: &mut ClamSession<'_>) -> Result<Pending<ScanResult>> {
+        match self {
+            ScanJob::File(path) => session.submit_file(path),
+            ScanJob::Bytes(bytes) => session.submit_bytes(bytes),
+            ScanJob::Reader(reader) => session.submit_stream(reader),
+        }
+    }
+}
+
+/// Starts the session of `scan_iter` or `scan_bulk`, as a replacement for
+/// one that was dropped after a failure if `lost`.
+fn open_session(client: &ClamClient, lost: bool) -> Result<ClamSession<'_>> {
+    let open = || client.warmed_up(|| ClamSession::start(client), ClamSession::ping);
+    match lost {
+        true => client.reopen(open),
+        false => open(),
+    }
+}
+
+/// Iterator returned by `ClamClient::scan_iter`. Inputs are scanned lazily
+/// over one session, which is re-established after a failed scan, following
+/// the client's reconnect policy if the daemon went away.
+pub struct ScanIter<'a, I> {
+    client: &'a ClamClient,
+    inputs: I,
+    session: Option<ClamSession<'a>>,
+    // a session was dropped because a scan over it failed
+    lost: bool,
+    // when the last scan over the session ended
+    used: Instant,
+}
+
+impl<'a, I> ScanIter<'a, I> {
+    pub(crate) fn new(client: &'a ClamClient, inputs: I) -> Self {
+        Self {
+            client,
+            inputs,
+            session: None,
+            lost: false,
+            used: Instant::now(),
+        }
+    }
+}
+
+impl<'a, I> Iterator for ScanIter<'a, I>
+where
+    I: Iterator,
+    I::Item: ScanInput,
+{
+    type Item = (I::Item, Result<ScanResult>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut input = self.inputs.next()?;
+
+        if let Some(session) = self.session.as_mut() {
+            if !self
+                .client
+                .checkout(session, self.used.elapsed(), ClamSession::ping)
+            {
+                self.session = None;
+            }
+        }
+
+        let session = match &mut self.session {
+            Some(session) => session,
+            None => match open_session(self.client, self.lost) {
+                Ok(session) => {
+                    self.lost = false;
+                    self.session.insert(session)
+                }
+                Err(e) => return Some((input, Err(e))),
+            },
+        };
+
+        let result = input.scan_in(session);
+        self.used = Instant::now();
+
+        if result.is_err() {
+            // whatever went wrong, the session's framing can't be trusted
+            self.session = None;
+            self.lost = true;
+        }
+
+        Some((input, result))
+    }
+}
+
+// uploads kept in flight by `scan_bulk`; clamd stops reading while its
+// replies go unread, so the pipeline has to stay short
+const BULK_DEPTH: usize = 8;
+
+/// Iterator returned by `ClamClient::scan_bulk`. Up to `BULK_DEPTH` inputs
+/// are uploaded over one session before the oldest verdict is read. An input
+/// that can't be opened fails on its own; after any other failure the inputs
+/// already in flight are answered from the session as far as it still can,
+/// then it is re-established like `ScanIter`'s.
+pub struct BulkScan<'a, I: Iterator> {
+    client: &'a ClamClient,
+    inputs: I,
+    session: Option<ClamSession<'a>>,
+    in_flight: VecDeque<(I::Item, Result<Pending<ScanResult>>)>,
+    // something failed, so nothing more is sent over this session
+    broken: bool,
+    lost: bool,
+}
+
+impl<'a, I: Iterator> BulkScan<'a, I> {
+    pub(crate) fn new(client: &'a ClamClient, inputs: I) -> Self {
+        Self {
+            client,
+            inputs,
+            session: None,
+            in_flight: VecDeque::new(),
+            broken: false,
+            lost: false,
+        }
+    }
+}
+
+impl<'a, I> Iterator for BulkScan<'a, I>
+where
+    I: Iterator,
+    I::Item: ScanInput,
+{
+    type Item = (I::Item, Result<ScanResult>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.broken && self.in_flight.len() < BULK_DEPTH {
+            let mut input = match self.inputs.next() {
+                Some(input) => input,
+                None => break,
+            };
+
+            let submitted = match &mut self.session {
+                Some(session) => input.submit_in(session),
+                None => match open_session(self.client, self.lost) {
+                    Ok(session) => {
+                        self.lost = false;
+                        input.submit_in(self.session.insert(session))
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+            self.broken = match (&submitted, &self.session) {
+                (Ok(_), _) => false,
+                // an input that couldn't be opened never reached the session
+                (Err(_), Some(session)) => session.broken,
+                (Err(_), None) => true,
+            };
+            self.in_flight.push_back((input, submitted));
+        }
+
+        let (input, submitted) = self.in_flight.pop_front()?;
+        let result = match (submitted, &mut self.session) {
+            (Ok(pending), Some(session)) => {
+                let result = session.wait(pending);
+                self.broken |= result.is_err();
+                result
+            }
+            (Ok(_), None) => unreachable!("submitted without a session"),
+            // whether it broke the session was settled when it was submitted
+            (Err(e), _) => Err(e),
+        };
+
+        if self.broken && self.in_flight.is_empty() {
+            // whatever went wrong, the session's framing can't be trusted
+            self.session = None;
+            self.broken = false;
+            self.lost = true;
+        }
+
+        Some((input, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::memory::MemoryTransport;
+    use crate::mock::MockDaemon;
+
+    #[test]
+    fn test_scan_iter_reuses_session() {
+        let daemon = MockDaemon::start_session("stream: OK");
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+
+        let results = client
+            .scan_iter(vec![b"one".to_vec(), b"two".to_vec()])
+            .collect::<Vec<_>>();
+
+        assert_eq!(results.len(), 2);
+        for (_, result) in results {
+            assert_eq!(result.unwrap(), ScanResult::Ok);
+        }
+
+        let received = daemon.all_received();
+        let commands = received
+            .iter()
+            .map(|r| String::from_utf8_lossy(&r.command).into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            vec!["zIDSESSION", "zINSTREAM", "zINSTREAM", "zEND"]
+        );
+        assert_eq!(received[2].payload(), b"two".to_vec());
+    }
+
+    #[test]
+    fn test_scan_iter_warms_up_session() {
+        // the first connection hangs up without answering
+        let transport = MemoryTransport::new()
+            .reply(b"")
+            .reply(b"1: PONG\x002: stream: OK\0");
+        let client = ClamClient::new_memory(transport.clone()).with_warm_up_ping();
+
+        let results = client.scan_iter(vec![b"one".to_vec()]).collect::<Vec<_>>();
+        assert_eq!(results[0].1.as_ref().unwrap(), &ScanResult::Ok);
+
+        let received = transport.received();
+        assert_eq!(received.len(), 2);
+        assert!(received[1].starts_with(b"zIDSESSION\0zPING\0zINSTREAM\0"));
+    }
+
+    #[test]
+    fn test_scan_iter_tests_session_on_checkout() {
+        // the session is closed after its first scan
+        let transport = MemoryTransport::new()
+            .reply(b"1: stream: OK\0")
+            .reply(b"1: stream: OK\0");
+        let client = ClamClient::new_memory(transport.clone()).with_test_on_checkout();
+
+        let results = client
+            .scan_iter(vec![b"one".to_vec(), b"two".to_vec()])
+            .collect::<Vec<_>>();
+        for (_, result) in results {
+            assert_eq!(result.unwrap(), ScanResult::Ok);
+        }
+
+        let received = transport.received();
+        assert_eq!(received.len(), 2);
+        assert!(received[0].ends_with(b"zPING\0zEND\0"));
+    }
+
+    #[test]
+    fn test_scan_bulk_keeps_input_order() {
+        let transport = MemoryTransport::new()
+            .reply(b"2: stream: Eicar FOUND\x001: stream: OK\x003: stream: OK\0");
+        let client = ClamClient::new_memory(transport.clone()).with_bulk_profile();
+
+        let results = client
+            .scan_bulk(vec![&b"one"[..], b"eicar", b"three"])
+            .map(|(input, result)| (input, result.unwrap() == ScanResult::Ok))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            vec![(&b"one"[..], true), (b"eicar", false), (b"three", true)]
+        );
+
+        let received = transport.received();
+        assert_eq!(received.len(), 1);
+        assert!(received[0].ends_with(b"\0\0\0\x05three\0\0\0\0zEND\0"));
+    }
+
+    #[test]
+    fn test_scan_bulk_reopens_after_failure() {
+        // the first session answers one scan and hangs up
+        let transport = MemoryTransport::new()
+            .reply(b"1: stream: OK\0")
+            .reply(b"1: stream: OK\0");
+        let client = ClamClient::new_memory(transport.clone());
+
+        let inputs = (0..BULK_DEPTH + 2).map(|i| vec![i as u8]);
+        let results = client
+            .scan_bulk(inputs)
+            .map(|(_, result)| result.is_ok())
+            .collect::<Vec<_>>();
+
+        // the pipeline refilled after the first verdict is lost with the
+        // session
+        let mut expected = vec![true];
+        expected.extend(vec![false; BULK_DEPTH]);
+        expected.push(true);
+        assert_eq!(results, expected);
+        assert_eq!(transport.received().len(), 2);
+    }
+
+    #[test]
+    fn test_scan_bulk_keeps_session_after_open_failure() {
+        let dir = std::env::temp_dir().join(format!(
+            "clamav-client-bulk-open-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a"), b"a").unwrap();
+        fs::write(dir.join("b"), b"b").unwrap();
+        let transport = MemoryTransport::new().reply(b"1: stream: OK\x002: stream: OK\0");
+        let client = ClamClient::new_memory(transport.clone());
+
+        let results = client
+            .scan_bulk(vec![dir.join("a"), dir.join("missing"), dir.join("b")])
+            .map(|(_, result)| result.is_ok())
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(results, vec![true, false, true]);
+        assert_eq!(transport.received().len(), 1);
+    }
+
+    #[test]
+    fn test_session_correlates_pipelined_replies() {
+        let daemon = MockDaemon::start(b"2: stream: Eicar-Test-Signature FOUND\x001: stream: OK\0");
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let mut session = client.session().unwrap();
+
+        let first = session.submit_bytes(b"clean").unwrap();
+        let second = session.submit_bytes(b"eicar").unwrap();
+
+        match session.wait(second).unwrap() {
+            ScanResult::Found(path, ..) => assert_eq!(path, "stream"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(session.wait(first).unwrap(), ScanResult::Ok);
+    }
+
+    #[test]
+    fn test_session_rejects_mismatched_reply_id() {
+        let daemon = MockDaemon::start(b"7: stream: OK\0");
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let mut session = client.session().unwrap();
+
+        match session.scan_bytes(b"data") {
+            Err(ClamError::InvalidData(raw)) => assert_eq!(raw, "7: stream: OK"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
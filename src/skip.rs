@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::client::Result;
+use crate::error::ClamError;
+
+/// The size and modification time of a file, which stand in for its
+/// content: a file whose stamp hasn't changed is taken to be unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Stamp {
+    size: u64,
+    // since the epoch
+    modified: Duration,
+}
+
+impl Stamp {
+    /// `None` where the platform doesn't report modification times.
+    pub(crate) fn of(metadata: &Metadata) -> Option<Self> {
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            size: metadata.len(),
+            modified,
+        })
+    }
+}
+
+/// Files found clean by earlier directory scans, see `DirScan::skip_cache`,
+/// with their stamp and the build of the signature database that scanned
+/// them. Stored as one `<build> <size> <mtime> <path>` line per file.
+#[derive(Debug)]
+pub(crate) struct SkipCache {
+    file: PathBuf,
+    entries: HashMap<PathBuf, (u64, Stamp)>,
+}
+
+impl SkipCache {
+    /// Loads the cache from `file`, starting empty if there is none yet.
+    /// Lines that can't be parsed are dropped.
+    pub(crate) fn open(file: &Path) -> Result<Self> {
+        let contents = match fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(ClamError::SkipCacheError(e)),
+        };
+
+        Ok(Self {
+            file: file.to_path_buf(),
+            entries: contents.lines().filter_map(parse_line).collect(),
+        })
+    }
+
+    /// Whether `path` was found clean by database `build` and hasn't
+    /// changed since.
+    pub(crate) fn is_unchanged(&self, path: &Path, metadata: &Metadata, build: u64) -> bool {
+        match (self.entries.get(path), Stamp::of(metadata)) {
+            (Some(&(scanned_by, stamp)), Some(current)) => scanned_by == build && stamp == current,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn record(&mut self, path: PathBuf, stamp: Stamp, build: u64) {
+        self.entries.insert(path, (build, stamp));
+    }
+
+    /// Writes the entries of database `build` back, replacing the file
+    /// atomically; entries of other builds are of no further use. Paths
+    /// that aren't valid UTF-8 or contain a newline aren't kept.
+    pub(crate) fn save(&self, build: u64) -> Result<()> {
+        let mut contents = String::new();
+        for (path, (scanned_by, stamp)) in &self.entries {
+            match path.to_str() {
+                Some(path) if *scanned_by == build && !path.contains('\n') => {
+                    contents.push_str(&format!(
+                        "{} {} {}.{:09} {}\n",
+                        build,
+                        stamp.size,
+                        stamp.modified.as_secs(),
+                        stamp.modified.subsec_nanos(),
+                        path
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        let mut partial = self.file.as_os_str().to_owned();
+        partial.push(".tmp");
+        fs::write(&partial, contents)
+            .and_then(|_| fs::rename(&partial, &self.file))
+            .map_err(ClamError::SkipCacheError)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(PathBuf, (u64, Stamp))> {
+    let mut fields = line.splitn(4, ' ');
+    let build = fields.next()?.parse().ok()?;
+    let size = fields.next()?.parse().ok()?;
+    let (secs, nanos) = fields.next()?.split_once('.')?;
+    let modified = Duration::new(secs.parse().ok()?, nanos.parse().ok()?);
+    let path = PathBuf::from(fields.next()?);
+
+    Some((path, (build, Stamp { size, modified })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let dir = std::env::temp_dir().join(format!(
+            "clamav-client-skip-cache-test-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let (file, scanned) = (dir.join("cache"), dir.join("a b.txt"));
+        fs::write(&scanned, b"a").unwrap();
+        let metadata = fs::metadata(&scanned).unwrap();
+
+        let mut cache = SkipCache::open(&file).unwrap();
+        assert!(!cache.is_unchanged(&scanned, &metadata, 7));
+        cache.record(scanned.clone(), Stamp::of(&metadata).unwrap(), 7);
+        cache.record(dir.join("old"), Stamp::of(&metadata).unwrap(), 6);
+        cache.save(7).unwrap();
+
+        let cache = SkipCache::open(&file).unwrap();
+        assert!(cache.is_unchanged(&scanned, &metadata, 7));
+        assert!(!cache.is_unchanged(&scanned, &metadata, 8));
+        assert_eq!(cache.entries.len(), 1);
+
+        fs::write(&scanned, b"changed").unwrap();
+        let changed = fs::metadata(&scanned).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!cache.is_unchanged(&scanned, &changed, 7));
+    }
+}
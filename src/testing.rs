@@ -0,0 +1,205 @@
+//! `ClamScan` and `AsyncClamScan` implementations for tests that shouldn't
+//! need a daemon.
+
+use std::collections::BTreeMap;
+
+use crate::client::Result;
+use crate::options::{ScanOptions, ScanTarget};
+use crate::response::{ScanResult, Signature, Stats, Version};
+use crate::scan::{AsyncClamScan, ClamScan};
+
+/// Reports everything as clean.
+#[derive(Debug, Default, Clone)]
+pub struct NoopClient;
+
+/// Reports every scan as infected with `signature`.
+#[derive(Debug, Clone)]
+pub struct AlwaysInfectedClient {
+    pub signature: Signature,
+}
+
+impl Default for AlwaysInfectedClient {
+    fn default() -> Self {
+        Self {
+            signature: Signature::from("Win.Test.EICAR_HDB-1"),
+        }
+    }
+}
+
+impl AlwaysInfectedClient {
+    pub fn new(signature: &str) -> Self {
+        Self {
+            signature: Signature::from(signature),
+        }
+    }
+}
+
+impl ClamScan for NoopClient {
+    fn ping(&self) -> bool {
+        true
+    }
+
+    fn version(&self) -> Result<Version> {
+        Ok(test_version())
+    }
+
+    fn scan(&self, _: ScanTarget<'_>, _: ScanOptions) -> Result<Vec<ScanResult>> {
+        Ok(vec![ScanResult::Ok])
+    }
+
+    fn stats(&self) -> Result<Stats> {
+        Ok(test_stats())
+    }
+}
+
+impl ClamScan for AlwaysInfectedClient {
+    fn ping(&self) -> bool {
+        true
+    }
+
+    fn version(&self) -> Result<Version> {
+        Ok(test_version())
+    }
+
+    fn scan(&self, target: ScanTarget<'_>, _: ScanOptions) -> Result<Vec<ScanResult>> {
+        let path = match target {
+            ScanTarget::Path(path) => path,
+            ScanTarget::Bytes(_) | ScanTarget::Reader(_) => "stream",
+        };
+
+        Ok(vec![ScanResult::found(path, self.signature.clone())])
+    }
+
+    fn stats(&self) -> Result<Stats> {
+        Ok(test_stats())
+    }
+}
+
+impl AsyncClamScan for NoopClient {
+    async fn ping(&self) -> bool {
+        true
+    }
+
+    async fn version(&self) -> Result<Version> {
+        Ok(test_version())
+    }
+
+    async fn scan_path(&self, _: String, _: bool) -> Result<Vec<ScanResult>> {
+        Ok(vec![ScanResult::Ok])
+    }
+
+    async fn stats(&self) -> Result<Stats> {
+        Ok(test_stats())
+    }
+
+    async fn scan_bytes(&self, _: Vec<u8>) -> Result<ScanResult> {
+        Ok(ScanResult::Ok)
+    }
+}
+
+impl AsyncClamScan for AlwaysInfectedClient {
+    async fn ping(&self) -> bool {
+        true
+    }
+
+    async fn version(&self) -> Result<Version> {
+        Ok(test_version())
+    }
+
+    async fn scan_path(&self, path: String, _: bool) -> Result<Vec<ScanResult>> {
+        Ok(vec![ScanResult::found(path, self.signature.clone())])
+    }
+
+    async fn stats(&self) -> Result<Stats> {
+        Ok(test_stats())
+    }
+
+    async fn scan_bytes(&self, _: Vec<u8>) -> Result<ScanResult> {
+        Ok(ScanResult::found("stream", self.signature.clone()))
+    }
+}
+
+fn test_version() -> Version {
+    Version::parse("ClamAV 0.0.0/0/Thu Jan  1 00:00:00 1970").unwrap()
+}
+
+fn test_stats() -> Stats {
+    Stats {
+        pools: 1,
+        state: String::from("VALID PRIMARY"),
+        threads_live: 1,
+        threads_idle: 0,
+        threads_max: 1,
+        threads_idle_timeout_secs: 30,
+        queue: 0,
+        mem_heap: None,
+        mem_mmap: None,
+        mem_used: None,
+        mem_free: None,
+        mem_releasable: None,
+        pools_used: None,
+        pools_total: None,
+        raw: String::new(),
+        extra: BTreeMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    fn verdict(scanner: &dyn ClamScan) -> &'static str {
+        match scanner.scan_bytes(b"upload") {
+            Ok(ScanResult::Ok) => "accept",
+            Ok(ScanResult::Found(_, signature, _)) if signature.is_test() => "quarantine",
+            _ => "reject",
+        }
+    }
+
+    #[test]
+    fn test_doubles() {
+        assert_eq!(verdict(&NoopClient), "accept");
+        assert_eq!(verdict(&AlwaysInfectedClient::default()), "quarantine");
+        assert_eq!(
+            verdict(&AlwaysInfectedClient::new("Win.Trojan.Agent-1")),
+            "reject"
+        );
+    }
+
+    // the doubles never wait, so a single poll completes their futures
+    fn poll_once<F: Future>(f: F) -> F::Output {
+        struct Noop;
+
+        impl Wake for Noop {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let waker = Waker::from(Arc::new(Noop));
+        match pin!(f).poll(&mut Context::from_waker(&waker)) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the double did not complete"),
+        }
+    }
+
+    async fn verdict_async<S: AsyncClamScan>(scanner: &S) -> &'static str {
+        match scanner.scan_bytes(b"upload".to_vec()).await {
+            Ok(ScanResult::Ok) => "accept",
+            Ok(ScanResult::Found(_, signature, _)) if signature.is_test() => "quarantine",
+            _ => "reject",
+        }
+    }
+
+    #[test]
+    fn test_async_doubles() {
+        assert_eq!(poll_once(verdict_async(&NoopClient)), "accept");
+        assert_eq!(
+            poll_once(verdict_async(&AlwaysInfectedClient::default())),
+            "quarantine"
+        );
+        assert!(poll_once(AsyncClamScan::ping(&NoopClient)));
+    }
+}
@@ -0,0 +1,432 @@
+use std::fmt;
+#[cfg(windows)]
+use std::fs::File;
+use std::io::{self, ErrorKind, IoSlice, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(any(unix, windows))]
+use std::path::Path;
+#[cfg(any(unix, windows))]
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::memory::{MemoryConnection, MemoryTransport};
+
+/// Where clamd listens.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Tcp(SocketAddr),
+    /// A Unix domain socket, clamd's `LocalSocket`. On Linux, a path
+    /// starting with `@` or a NUL byte names a socket in the abstract
+    /// namespace.
+    #[cfg(unix)]
+    Unix(PathBuf),
+    /// A named pipe such as `\\.\pipe\clamd`.
+    #[cfg(windows)]
+    Pipe(PathBuf),
+    /// A scripted in-process daemon, for tests. Can't be serialized.
+    #[serde(skip)]
+    Memory(MemoryTransport),
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(address) => write!(f, "{}", address),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => write!(f, "{}", path.display()),
+            #[cfg(windows)]
+            Endpoint::Pipe(path) => write!(f, "{}", path.display()),
+            Endpoint::Memory(_) => f.write_str("memory"),
+        }
+    }
+}
+
+/// Who must be serving a Unix socket, see `ClamClient::with_peer_credentials`.
+/// Unset IDs match anyone.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PeerCredentials {
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+#[cfg(unix)]
+impl PeerCredentials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the daemon to run as user `uid`, e.g. that of `id -u clamav`.
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Requires the daemon to run with primary group `gid`.
+    pub fn gid(mut self, gid: u32) -> Self {
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Why the peer of `stream` doesn't match, if it doesn't.
+    pub(crate) fn mismatch(&self, stream: &UnixStream) -> io::Result<Option<String>> {
+        let (uid, gid) = peer_ids(stream)?;
+        let wrong = |expected: Option<u32>, actual| expected.is_some_and(|id| id != actual);
+
+        if !wrong(self.uid, uid) && !wrong(self.gid, gid) {
+            return Ok(None);
+        }
+
+        let expected = [("uid", self.uid), ("gid", self.gid)]
+            .iter()
+            .filter_map(|(kind, id)| id.map(|id| format!("{} {}", kind, id)))
+            .collect::<Vec<_>>();
+        Ok(Some(format!(
+            "uid {} gid {}, expected {}",
+            uid,
+            gid,
+            expected.join(" ")
+        )))
+    }
+}
+
+/// The user and group of the process at the other end of `stream`, as the
+/// kernel recorded them when it connected.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_ids(stream: &UnixStream) -> io::Result<(u32, u32)> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: the pointers are to a ucred and its size, which is what
+    // SO_PEERCRED fills in
+    let status = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+
+    match status {
+        0 => Ok((credentials.uid, credentials.gid)),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+fn peer_ids(stream: &UnixStream) -> io::Result<(u32, u32)> {
+    use std::os::unix::io::AsRawFd;
+
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: both pointers are to live IDs for getpeereid to fill in
+    match unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } {
+        0 => Ok((uid, gid)),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))
+))]
+fn peer_ids(_: &UnixStream) -> io::Result<(u32, u32)> {
+    Err(io::Error::new(
+        ErrorKind::Unsupported,
+        "peer credentials are not available on this platform",
+    ))
+}
+
+/// An open connection to clamd over any of the supported transports.
+#[derive(Debug)]
+pub(crate) enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    #[cfg(windows)]
+    Pipe(File),
+    Memory(MemoryConnection),
+}
+
+impl Connection {
+    /// Connects to `endpoint`. The timeout applies to TCP and to waiting for
+    /// a free pipe instance; Unix sockets connect immediately or fail.
+    pub(crate) fn open(endpoint: &Endpoint, timeout: Option<Duration>) -> io::Result<Self> {
+        match endpoint {
+            Endpoint::Tcp(address) => match timeout {
+                Some(t) => TcpStream::connect_timeout(address, t).map(Connection::Tcp),
+                None => TcpStream::connect(address).map(Connection::Tcp),
+            },
+            #[cfg(unix)]
+            Endpoint::Unix(path) => connect_unix(path).map(Connection::Unix),
+            #[cfg(windows)]
+            Endpoint::Pipe(path) => open_pipe(path, timeout).map(Connection::Pipe),
+            Endpoint::Memory(transport) => transport.connect().map(Connection::Memory),
+        }
+    }
+
+    pub(crate) fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Connection::Tcp(s) => s.try_clone().map(Connection::Tcp),
+            #[cfg(unix)]
+            Connection::Unix(s) => s.try_clone().map(Connection::Unix),
+            #[cfg(windows)]
+            Connection::Pipe(f) => f.try_clone().map(Connection::Pipe),
+            Connection::Memory(m) => Ok(Connection::Memory(m.clone())),
+        }
+    }
+
+    /// Bounds how long a read waits. Pipes and in-memory connections ignore
+    /// it.
+    pub(crate) fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Connection::Tcp(s) => s.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Connection::Unix(s) => s.set_read_timeout(timeout),
+            #[cfg(windows)]
+            Connection::Pipe(_) => Ok(()),
+            Connection::Memory(_) => Ok(()),
+        }
+    }
+
+    /// Shuts the connection down. Pipes can't be shut down while another
+    /// handle to them is in use, so this is a no-op for them.
+    pub(crate) fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Connection::Tcp(s) => s.shutdown(how),
+            #[cfg(unix)]
+            Connection::Unix(s) => s.shutdown(how),
+            #[cfg(windows)]
+            Connection::Pipe(_) => Ok(()),
+            Connection::Memory(m) => {
+                m.shutdown(how);
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks without blocking whether the peer has sent anything (or hung
+    /// up). Always false for pipes, which std can't peek, and in-memory
+    /// connections, whose reply is only read after the request.
+    pub(crate) fn has_data(&self) -> bool {
+        match self {
+            Connection::Tcp(s) => peek_ready(s),
+            #[cfg(unix)]
+            Connection::Unix(s) => peek_ready(s),
+            #[cfg(windows)]
+            Connection::Pipe(_) => false,
+            Connection::Memory(_) => false,
+        }
+    }
+
+    /// Copies `reader` into the connection. The concrete stream types are
+    /// passed to `io::copy` so it can use `sendfile`/`splice` on Linux.
+    pub(crate) fn copy_from<R: Read>(&self, reader: &mut R) -> io::Result<u64> {
+        match self {
+            Connection::Tcp(s) => io::copy(reader, &mut &*s),
+            #[cfg(unix)]
+            Connection::Unix(s) => io::copy(reader, &mut &*s),
+            #[cfg(windows)]
+            Connection::Pipe(f) => io::copy(reader, &mut &*f),
+            Connection::Memory(m) => io::copy(reader, &mut &*m),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &Path) -> io::Result<UnixStream> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::net::SocketAddr;
+
+        let bytes = path.as_os_str().as_bytes();
+        if let Some(name) = bytes
+            .strip_prefix(b"@")
+            .or_else(|| bytes.strip_prefix(b"\0"))
+        {
+            return UnixStream::connect_addr(&SocketAddr::from_abstract_name(name)?);
+        }
+    }
+
+    UnixStream::connect(path)
+}
+
+/// Opens the pipe at `path`, retrying while every instance of it is busy
+/// until `timeout` has passed.
+#[cfg(windows)]
+fn open_pipe(path: &Path, timeout: Option<Duration>) -> io::Result<File> {
+    // ERROR_PIPE_BUSY
+    const PIPE_BUSY: i32 = 231;
+    let started = std::time::Instant::now();
+
+    loop {
+        let opened = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path);
+        match opened {
+            Err(ref e)
+                if e.raw_os_error() == Some(PIPE_BUSY)
+                    && timeout.is_some_and(|t| started.elapsed() < t) =>
+            {
+                std::thread::sleep(Duration::from_millis(10))
+            }
+            opened => return opened,
+        }
+    }
+}
+
+/// Peeks with MSG_DONTWAIT rather than switching the socket to
+/// non-blocking mode, which would affect every handle sharing it.
+#[cfg(unix)]
+fn peek_ready<S: std::os::unix::io::AsRawFd>(stream: &S) -> bool {
+    let mut byte = 0u8;
+    // SAFETY: the buffer is one live byte, the length recv is given
+    let received = unsafe {
+        libc::recv(
+            stream.as_raw_fd(),
+            &mut byte as *mut u8 as *mut libc::c_void,
+            1,
+            libc::MSG_PEEK | libc::MSG_DONTWAIT,
+        )
+    };
+
+    if received >= 0 {
+        return true;
+    }
+    !matches!(
+        io::Error::last_os_error().kind(),
+        ErrorKind::WouldBlock | ErrorKind::Interrupted
+    )
+}
+
+#[cfg(not(unix))]
+fn peek_ready(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    let mut byte = [0; 1];
+    let ready = match stream.peek(&mut byte) {
+        Ok(_) => true,
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => false,
+        Err(_) => true,
+    };
+
+    let _ = stream.set_nonblocking(false);
+    ready
+}
+
+impl Read for &Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(s) => (&*s).read(buf),
+            #[cfg(unix)]
+            Connection::Unix(s) => (&*s).read(buf),
+            #[cfg(windows)]
+            Connection::Pipe(f) => (&*f).read(buf),
+            Connection::Memory(m) => (&*m).read(buf),
+        }
+    }
+}
+
+impl Write for &Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(s) => (&*s).write(buf),
+            #[cfg(unix)]
+            Connection::Unix(s) => (&*s).write(buf),
+            #[cfg(windows)]
+            Connection::Pipe(f) => (&*f).write(buf),
+            Connection::Memory(m) => (&*m).write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(s) => (&*s).write_vectored(bufs),
+            #[cfg(unix)]
+            Connection::Unix(s) => (&*s).write_vectored(bufs),
+            #[cfg(windows)]
+            Connection::Pipe(f) => (&*f).write_vectored(bufs),
+            Connection::Memory(m) => (&*m).write_vectored(bufs),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_abstract_unix_socket() {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::{SocketAddr, UnixListener};
+
+        let name = format!("clamav-test-{}", std::process::id());
+        let address = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+        let listener = UnixListener::bind_addr(&address).unwrap();
+
+        for path in [format!("@{}", name), format!("\0{}", name)] {
+            let endpoint = Endpoint::Unix(PathBuf::from(path));
+            let mut connection = Connection::open(&endpoint, None).unwrap();
+            let (mut accepted, _) = listener.accept().unwrap();
+
+            connection.write_all(b"zPING\0").unwrap();
+            let mut command = [0; 6];
+            accepted.read_exact(&mut command).unwrap();
+            assert_eq!(&command, b"zPING\0");
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::cancel::CancelToken;
+use crate::client::{ClamClient, Result};
+use crate::error::ClamError;
+use crate::notify::{Detection, Webhook};
+use crate::policy::{Action, ActionTaken};
+use crate::response::ScanResult;
+
+// size and modification time, to notice files that are still being written
+type Stamp = (u64, Option<SystemTime>);
+
+/// A file scanned by `DirWatch`.
+#[derive(Debug)]
+pub struct Watched {
+    pub path: PathBuf,
+    pub result: Result<ScanResult>,
+    /// What the `on_found` action did, if something was found.
+    pub action: Option<ActionTaken>,
+    /// Why the detection couldn't be posted to the webhook.
+    pub notify_error: Option<ClamError>,
+}
+
+/// Polls a directory and scans files once they stop changing. Files already
+/// present when the watch starts are not scanned; new or modified files are
+/// scanned once they have stayed the same for one polling interval.
+///
+/// Files are scanned through an open handle, so an `on_found` action hits
+/// the file that was scanned. Detections are posted to the webhook before
+/// the action is applied.
+pub struct DirWatch<'a> {
+    client: &'a ClamClient,
+    root: PathBuf,
+    interval: Duration,
+    webhook: Option<Webhook>,
+    action: Option<Action>,
+    cancel: Option<CancelToken>,
+}
+
+impl<'a> DirWatch<'a> {
+    pub fn new<P: AsRef<Path>>(client: &'a ClamClient, root: P) -> Self {
+        Self {
+            client,
+            root: root.as_ref().to_path_buf(),
+            interval: Duration::from_secs(2),
+            webhook: None,
+            action: None,
+            cancel: None,
+        }
+    }
+
+    /// Lists the directory every `interval`, by default every two seconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Posts every detection to `webhook`. Failed notifications don't stop
+    /// the watch; they are passed on with the file.
+    pub fn notify(mut self, webhook: Webhook) -> Self {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    /// Applies `action` to every file something is found in. A quarantine
+    /// directory inside the watched one is not watched.
+    pub fn on_found(mut self, action: Action) -> Self {
+        self.action = Some(action);
+        self
+    }
+
+    /// Stops the watch, within one polling interval, once `cancel` is
+    /// cancelled.
+    pub fn cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Watches until cancelled, calling `f` with every file scanned. Fails
+    /// once the directory can no longer be read.
+    pub fn run<F: FnMut(Watched)>(self, mut f: F) -> Result<()> {
+        // so quarantined files aren't picked up again
+        let quarantine = match &self.action {
+            Some(Action::Quarantine(dir)) => fs::canonicalize(dir).ok(),
+            _ => None,
+        };
+        let db_version = match self.webhook {
+            Some(_) => self
+                .client
+                .version()
+                .ok()
+                .map(|version| version.to_string()),
+            None => None,
+        };
+        let list = || {
+            let mut files = HashMap::new();
+            collect(&self.root, quarantine.as_deref(), &mut files)
+                .map(|()| files)
+                .map_err(ClamError::StreamError)
+        };
+
+        let mut handled = list()?;
+        let mut changed: HashMap<PathBuf, Stamp> = HashMap::new();
+
+        loop {
+            thread::sleep(self.interval);
+            if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                return Ok(());
+            }
+            let current = list()?;
+
+            for (path, stamp) in &current {
+                if handled.get(path) == Some(stamp) {
+                    continue;
+                }
+                if changed.get(path) != Some(stamp) {
+                    changed.insert(path.clone(), *stamp);
+                    continue;
+                }
+
+                changed.remove(path);
+                handled.insert(path.clone(), *stamp);
+                f(self.scan(path, &db_version));
+            }
+
+            handled.retain(|path, _| current.contains_key(path));
+            changed.retain(|path, _| current.contains_key(path));
+        }
+    }
+
+    fn scan(&self, path: &Path, db_version: &Option<String>) -> Watched {
+        let (file, result) = match File::open(path) {
+            Ok(file) => {
+                let result = self.client.scan_open_file(&file, path);
+                (Some(file), result)
+            }
+            Err(_) => (None, self.client.scan_file(path)),
+        };
+
+        let mut watched = Watched {
+            path: path.to_path_buf(),
+            result,
+            action: None,
+            notify_error: None,
+        };
+        if let Ok(ScanResult::Found(_, signature, _)) = &watched.result {
+            if let Some(webhook) = &self.webhook {
+                let detection = Detection::new(&path.to_string_lossy(), signature)
+                    .with_db_version(db_version.clone());
+                watched.notify_error = webhook.notify(&detection).err();
+            }
+            if let (Some(action), Some(file)) = (&self.action, &file) {
+                watched.action = Some(action.apply(path, file, signature));
+            }
+        }
+
+        watched
+    }
+}
+
+/// Records every regular file below `dir`, not following symlinks and
+/// skipping the quarantine directory.
+fn collect(
+    dir: &Path,
+    quarantine: Option<&Path>,
+    files: &mut HashMap<PathBuf, Stamp>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            // removed since it was listed
+            Err(_) => continue,
+        };
+
+        if metadata.is_dir() {
+            if quarantine.is_some() && fs::canonicalize(&path).ok().as_deref() == quarantine {
+                continue;
+            }
+            collect(&path, quarantine, files)?;
+        } else if metadata.is_file() {
+            files.insert(path, (metadata.len(), metadata.modified().ok()));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDaemon;
+
+    #[test]
+    fn test_watch_quarantines_new_files() {
+        let root =
+            std::env::temp_dir().join(format!("clamav-client-watch-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let quarantine = root.join("quarantine");
+        fs::create_dir_all(&quarantine).unwrap();
+        fs::write(root.join("old"), b"old").unwrap();
+        let daemon = MockDaemon::start(b"stream: Eicar-Test FOUND\0");
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let cancel = CancelToken::new();
+
+        let mut seen = Vec::new();
+        thread::scope(|scope| {
+            let watch = DirWatch::new(&client, &root)
+                .interval(Duration::from_millis(50))
+                .on_found(Action::Quarantine(quarantine.clone()))
+                .cancel(cancel.clone());
+            let watching = scope.spawn(|| {
+                watch.run(|watched| {
+                    seen.push(watched);
+                    cancel.cancel();
+                })
+            });
+            thread::sleep(Duration::from_millis(100));
+            fs::write(root.join("new"), b"new").unwrap();
+            watching.join().unwrap().unwrap();
+        });
+        let quarantined = fs::read_dir(&quarantine).unwrap().count();
+        let old_kept = root.join("old").exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].path, root.join("new"));
+        assert!(matches!(seen[0].action, Some(ActionTaken::Quarantined(_))));
+        assert_eq!(quarantined, 1);
+        assert!(old_kept);
+    }
+}
@@ -0,0 +1,163 @@
+use std::io::{self, ErrorKind, Write};
+use std::sync::Arc;
+
+use crate::client::{self, Result};
+use crate::command::Framing;
+use crate::counters::Counters;
+use crate::error::ClamError;
+use crate::limit::{ConcurrencyPermit, RateLimiter};
+use crate::pool::{BufferPool, PooledWriter};
+use crate::response::ScanResult;
+use crate::transport::Connection;
+
+/// Streams everything written to it into an INSTREAM scan. Obtained through
+/// `ClamClient::scan_writer`; call `finish` once all data has been written to
+/// send the terminator and collect the verdict.
+///
+/// If clamd answers before the stream is complete (e.g. the size limit was
+/// exceeded) further writes fail with `ConnectionAborted`; `finish` then
+/// returns the daemon's reply.
+pub struct ClamScanWriter {
+    connection: PooledWriter<Connection>,
+    buffers: Arc<BufferPool>,
+    counters: Arc<Counters>,
+    chunk_size: usize,
+    framing: Framing,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    unpolled: usize,
+    replied: bool,
+    _permit: Option<ConcurrencyPermit>,
+}
+
+impl ClamScanWriter {
+    pub(crate) fn new(
+        connection: Connection,
+        buffers: &Arc<BufferPool>,
+        counters: &Arc<Counters>,
+        chunk_size: usize,
+        framing: Framing,
+        rate_limiter: Option<Arc<RateLimiter>>,
+        permit: Option<ConcurrencyPermit>,
+    ) -> Self {
+        Self {
+            connection: PooledWriter::new(connection, BufferPool::get(buffers)),
+            buffers: Arc::clone(buffers),
+            counters: Arc::clone(counters),
+            chunk_size,
+            framing,
+            rate_limiter,
+            unpolled: 0,
+            replied: false,
+            _permit: permit,
+        }
+    }
+
+    pub fn finish(mut self) -> Result<ScanResult> {
+        if !self.replied {
+            let sent = self
+                .connection
+                .write_all(&[0; 4])
+                .and_then(|_| self.connection.flush());
+
+            match sent {
+                Ok(_) => self.counters.sent(4),
+                Err(e) => {
+                    if !client::daemon_replied(self.connection.get_ref()) {
+                        return Err(ClamError::CommandError(e));
+                    }
+                }
+            }
+        }
+
+        client::read_scan_result(
+            self.connection.get_ref(),
+            &mut BufferPool::get(&self.buffers),
+            &self.counters,
+            self.framing,
+        )
+    }
+}
+
+impl Write for ClamScanWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.replied {
+            return Err(io::Error::new(
+                ErrorKind::ConnectionAborted,
+                "clamd replied before the end of the stream",
+            ));
+        }
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // a zero length frame would terminate the stream, so empty writes
+        // are skipped above and large ones are split into several frames
+        let chunk = &buf[..buf.len().min(self.chunk_size)];
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.acquire(chunk.len() as u64);
+        }
+
+        if let Err(e) = client::write_frame(&mut self.connection, chunk) {
+            if client::daemon_replied(self.connection.get_ref()) {
+                self.replied = true;
+                return self.write(buf);
+            }
+            return Err(e);
+        }
+
+        self.counters.sent(4 + chunk.len() as u64);
+        self.unpolled += chunk.len();
+        if self.unpolled >= client::REPLY_POLL_INTERVAL {
+            self.unpolled = 0;
+            self.replied = client::daemon_replied(self.connection.get_ref());
+        }
+
+        Ok(chunk.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.connection.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDaemon;
+    use crate::ClamClient;
+
+    #[test]
+    fn test_writer_frames_copied_data() {
+        let daemon = MockDaemon::start(b"stream: OK\0");
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let data = vec![7u8; 10000];
+
+        let mut writer = client.scan_writer().unwrap();
+        io::copy(&mut data.as_slice(), &mut writer).unwrap();
+        assert_eq!(writer.finish().unwrap(), ScanResult::Ok);
+
+        let received = daemon.received();
+        assert_eq!(received.command, b"zINSTREAM".to_vec());
+        assert!(received
+            .chunks
+            .iter()
+            .all(|c| c.len() <= client::DEFAULT_CHUNK_SIZE));
+        assert_eq!(received.payload(), data);
+    }
+
+    #[test]
+    fn test_writer_early_reply() {
+        let daemon = MockDaemon::with_limit(b"INSTREAM size limit exceeded. ERROR\0", 100_000);
+        let client = ClamClient::new("127.0.0.1", daemon.port).unwrap();
+        let data = vec![0u8; 16 << 20];
+
+        let mut writer = client.scan_writer().unwrap();
+        let copied = io::copy(&mut data.as_slice(), &mut writer);
+        assert_eq!(copied.unwrap_err().kind(), ErrorKind::ConnectionAborted);
+        assert_eq!(
+            writer.finish().unwrap(),
+            ScanResult::Error("INSTREAM size limit exceeded. ERROR".to_string())
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! Runs against a real clamd in a container: `cargo test --features docker`.
+#![cfg(feature = "docker")]
+
+use clamav::docker::ClamavContainer;
+use clamav::response::ScanResult;
+
+// the standard anti-virus test file
+const EICAR: &[u8] = br"X5O!P%@AP[4\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+#[test]
+fn test_scans_against_container() {
+    let container = ClamavContainer::start().unwrap();
+    let client = container.client().unwrap();
+
+    assert!(client.ping());
+    assert_eq!(client.scan_bytes(b"clean").unwrap(), ScanResult::Ok);
+
+    match client.scan_bytes(EICAR).unwrap() {
+        ScanResult::Found(_, signature, _) => assert!(signature.is_test()),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}